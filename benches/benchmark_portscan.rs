@@ -54,33 +54,28 @@ fn criterion_benchmark(c: &mut Criterion) {
     let strategy_tcp = PortStrategy::pick(&Some(range.clone()), None, ScanOrder::Serial);
     let strategy_udp = PortStrategy::pick(&Some(range.clone()), None, ScanOrder::Serial);
 
-    let scanner_tcp = Scanner::new(
-        &addrs,
-        10,
-        Duration::from_millis(10),
-        1,
-        false,
-        strategy_tcp,
-        true,
-        vec![],
-        false,
-    );
+    let scanner_tcp = Scanner::builder()
+        .ips(addrs.clone())
+        .batch_size(10)
+        .timeout(Duration::from_millis(10))
+        .port_strategy(strategy_tcp)
+        .accessible(true)
+        .build()
+        .unwrap();
 
     c.bench_function("portscan tcp", |b| {
         b.iter(|| portscan_tcp(black_box(&scanner_tcp)))
     });
 
-    let scanner_udp = Scanner::new(
-        &addrs,
-        10,
-        Duration::from_millis(10),
-        1,
-        false,
-        strategy_udp,
-        true,
-        vec![],
-        true,
-    );
+    let scanner_udp = Scanner::builder()
+        .ips(addrs.clone())
+        .batch_size(10)
+        .timeout(Duration::from_millis(10))
+        .port_strategy(strategy_udp)
+        .accessible(true)
+        .udp(true)
+        .build()
+        .unwrap();
 
     let mut udp_group = c.benchmark_group("portscan udp");
     udp_group.measurement_time(Duration::from_secs(20));
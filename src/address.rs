@@ -1,5 +1,6 @@
 //! 提供解析输入 IP 地址、CIDR 或文件的功能。
-use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{prelude::*, BufReader};
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
@@ -9,12 +10,56 @@ use std::str::FromStr;
 use cidr_utils::cidr::{IpCidr, IpInet};
 use hickory_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    system_conf::read_system_conf,
     Resolver,
 };
 use log::debug;
 
+use crate::event::{CliPrinter, Event, EventSink};
 use crate::input::Opts;
-use crate::warning;
+use crate::{Error, Result};
+
+/// 一个要扫描的 IP 以及它来自哪些主机名。
+///
+/// ```rust
+/// # use rustscan::address::Target;
+/// let mut target = Target::new("93.184.216.34".parse().unwrap(), "example.com");
+/// assert_eq!(target.to_string(), "93.184.216.34");
+/// target.hostnames.push("example.com".to_owned());
+/// assert_eq!(target.to_string(), "example.com (93.184.216.34)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub ip: IpAddr,
+    /// 解析出这个 IP 的主机名，直接给出 IP 或 CIDR 时为空
+    pub hostnames: Vec<String>,
+    /// 第一次得到这个 IP 的原始输入，例如 `example.com` 或 `10.0.0.0/24`
+    pub source: String,
+}
+
+impl Target {
+    pub fn new(ip: IpAddr, source: &str) -> Self {
+        Self {
+            ip,
+            hostnames: vec![],
+            source: source.to_owned(),
+        }
+    }
+
+    /// 第一个主机名，供需要虚拟主机名的脚本使用。
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostnames.first().map(String::as_str)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hostname() {
+            Some(hostname) => write!(f, "{hostname} ({})", self.ip),
+            None => write!(f, "{}", self.ip),
+        }
+    }
+}
 
 /// 将字符串解析为 IP 地址。
 ///
@@ -26,19 +71,50 @@ use crate::warning;
 /// let mut opts = Opts::default();
 /// opts.addresses = vec!["192.168.0.0/30".to_owned()];
 ///
-/// let ips = parse_addresses(&opts);
+/// let ips = parse_addresses(&opts).unwrap();
 /// ```
 ///
 /// 最后，删除任何重复项以避免过度扫描。
-pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
-    let mut ips: Vec<IpAddr> = Vec::new();
+///
+/// 无法解析的地址按照 `greppable` 和 `accessible` 打印警告，
+/// 不希望打印时使用 [`parse_addresses_with_events`]。
+pub fn parse_addresses(input: &Opts) -> Result<Vec<IpAddr>> {
+    parse_addresses_with_events(input, &CliPrinter::new(input.greppable, input.accessible))
+}
+
+/// 和 [`parse_addresses`] 一样，但把无法解析的地址作为 [`Event::UnresolvedHost`] 交给 `events`。
+pub fn parse_addresses_with_events(input: &Opts, events: &dyn EventSink) -> Result<Vec<IpAddr>> {
+    let targets = parse_targets_with_events(input, events)?;
+    Ok(targets.into_iter().map(|target| target.ip).collect())
+}
+
+/// 和 [`parse_addresses`] 一样，但保留每个 IP 来自的主机名和原始输入。
+///
+/// ```rust
+/// # use rustscan::input::Opts;
+/// # use rustscan::address::parse_targets;
+/// let mut opts = Opts::default();
+/// opts.addresses = vec!["localhost".to_owned(), "127.0.0.1".to_owned()];
+///
+/// let targets = parse_targets(&opts).unwrap();
+/// assert_eq!(targets.len(), 1);
+/// assert_eq!(targets[0].to_string(), "localhost (127.0.0.1)");
+/// ```
+pub fn parse_targets(input: &Opts) -> Result<Vec<Target>> {
+    parse_targets_with_events(input, &CliPrinter::new(input.greppable, input.accessible))
+}
+
+/// 和 [`parse_targets`] 一样，但把无法解析的地址作为 [`Event::UnresolvedHost`] 交给 `events`。
+pub fn parse_targets_with_events(input: &Opts, events: &dyn EventSink) -> Result<Vec<Target>> {
+    let mut targets: Vec<Target> = Vec::new();
     let mut unresolved_addresses: Vec<&str> = Vec::new();
-    let backup_resolver = get_resolver(&input.resolver);
+    let backup_resolver = get_resolver(&input.resolver)?;
+    let family = input.address_family();
 
     for address in &input.addresses {
-        let parsed_ips = parse_address(address, &backup_resolver);
-        if !parsed_ips.is_empty() {
-            ips.extend(parsed_ips);
+        let parsed = parse_target(address, &backup_resolver, family);
+        if !parsed.is_empty() {
+            targets.extend(parsed);
         } else {
             unresolved_addresses.push(address);
         }
@@ -49,33 +125,80 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
         let file_path = Path::new(file_path);
 
         if !file_path.is_file() {
-            warning!(
-                format!("Host {file_path:?} could not be resolved."),
-                input.greppable,
-                input.accessible
-            );
+            events.event(Event::UnresolvedHost(file_path.display().to_string()));
 
             continue;
         }
 
-        if let Ok(x) = read_ips_from_file(file_path, &backup_resolver) {
-            ips.extend(x);
+        if let Ok(x) = read_ips_from_file(file_path, &backup_resolver, family) {
+            targets.extend(x);
         } else {
-            warning!(
-                format!("Host {file_path:?} could not be resolved."),
-                input.greppable,
-                input.accessible
-            );
+            events.event(Event::UnresolvedHost(file_path.display().to_string()));
         }
     }
 
     let excluded_cidrs = parse_excluded_networks(&input.exclude_addresses, &backup_resolver);
 
-    // 移除重复/排除的 IP。
-    let mut seen = BTreeSet::new();
-    ips.retain(|ip| seen.insert(*ip) && !excluded_cidrs.iter().any(|cidr| cidr.contains(ip)));
+    // 移除重复/排除的 IP，重复的 IP 合并它们的主机名。
+    let mut unique: Vec<Target> = Vec::new();
+    let mut index_of: HashMap<IpAddr, usize> = HashMap::new();
+    for target in targets {
+        if excluded_cidrs.iter().any(|cidr| cidr.contains(&target.ip)) {
+            continue;
+        }
+        match index_of.get(&target.ip) {
+            Some(&index) => {
+                let existing = &mut unique[index];
+                for hostname in target.hostnames {
+                    if !existing.hostnames.contains(&hostname) {
+                        existing.hostnames.push(hostname);
+                    }
+                }
+            }
+            None => {
+                index_of.insert(target.ip, unique.len());
+                unique.push(target);
+            }
+        }
+    }
 
-    ips
+    Ok(unique)
+}
+
+/// 主机名解析出多个地址时扫描哪些地址。IP 和 CIDR 不受影响。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// 只使用系统解析器返回的第一个地址，它已经按照系统的地址选择策略排好了序
+    #[default]
+    First,
+    /// 只使用 IPv4 地址
+    V4,
+    /// 只使用 IPv6 地址
+    V6,
+    /// 使用所有地址
+    All,
+}
+
+impl AddressFamily {
+    /// 从解析出的地址中选出要扫描的地址。
+    ///
+    /// ```rust
+    /// # use rustscan::address::AddressFamily;
+    /// # use std::net::IpAddr;
+    /// let resolved: Vec<IpAddr> = vec!["2001:db8::1".parse().unwrap(), "192.0.2.1".parse().unwrap()];
+    /// assert_eq!(AddressFamily::First.select(resolved.clone()), vec![resolved[0]]);
+    /// assert_eq!(AddressFamily::V4.select(resolved.clone()), vec![resolved[1]]);
+    /// assert_eq!(AddressFamily::All.select(resolved.clone()), resolved);
+    /// ```
+    pub fn select(self, resolved: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+        let resolved = resolved.into_iter();
+        match self {
+            AddressFamily::First => resolved.take(1).collect(),
+            AddressFamily::V4 => resolved.filter(IpAddr::is_ipv4).collect(),
+            AddressFamily::V6 => resolved.filter(IpAddr::is_ipv6).collect(),
+            AddressFamily::All => resolved.collect(),
+        }
+    }
 }
 
 /// 给定一个字符串，将其解析为主机、IP 地址或 CIDR。
@@ -92,6 +215,15 @@ pub fn parse_addresses(input: &Opts) -> Vec<IpAddr> {
 /// let ips = parse_address("127.0.0.1", &Resolver::default().unwrap());
 /// ```
 pub fn parse_address(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
+    parse_address_with_family(address, resolver, AddressFamily::First)
+}
+
+/// 和 [`parse_address`] 一样，主机名按 `family` 选择地址。
+pub fn parse_address_with_family(
+    address: &str,
+    resolver: &Resolver,
+    family: AddressFamily,
+) -> Vec<IpAddr> {
     if let Ok(addr) = IpAddr::from_str(address) {
         // `address` 是一个 IP 字符串
         vec![addr]
@@ -101,14 +233,34 @@ pub fn parse_address(address: &str, resolver: &Resolver) -> Vec<IpAddr> {
     } else {
         // `address` 是一个主机名或 DNS 名称
         // 尝试默认 DNS 查询
-        match format!("{address}:80").to_socket_addrs() {
-            Ok(mut iter) => vec![iter.next().unwrap().ip()],
-            // 默认查询不起作用，因此尝试使用专用解析器再次查询
-            Err(_) => resolve_ips_from_host(address, resolver),
+        let ips = match format!("{address}:80").to_socket_addrs() {
+            Ok(iter) => family.select(iter.map(|socket| socket.ip())),
+            Err(_) => vec![],
+        };
+        if ips.is_empty() {
+            // 默认查询不起作用或者没有所需的地址族，因此尝试使用专用解析器再次查询
+            family.select(resolve_ips_from_host(address, resolver))
+        } else {
+            ips
         }
     }
 }
 
+/// 和 [`parse_address_with_family`] 一样，主机名会记录在结果中。
+fn parse_target(address: &str, resolver: &Resolver, family: AddressFamily) -> Vec<Target> {
+    let is_hostname = IpAddr::from_str(address).is_err() && IpInet::from_str(address).is_err();
+    parse_address_with_family(address, resolver, family)
+        .into_iter()
+        .map(|ip| {
+            let mut target = Target::new(ip, address);
+            if is_hostname {
+                target.hostnames.push(address.to_owned());
+            }
+            target
+        })
+        .collect()
+}
+
 /// 使用 DNS 获取与主机关联的 IP
 fn resolve_ips_from_host(source: &str, backup_resolver: &Resolver) -> Vec<IpAddr> {
     let mut ips: Vec<IpAddr> = Vec::new();
@@ -164,7 +316,13 @@ fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr>
         .collect()
 }
 
-/// 获取 DNS 解析器。
+/// 获取 DNS 解析器，配置见 [`resolver_config`]。
+fn get_resolver(resolver: &Option<String>) -> Result<Resolver> {
+    let (config, options) = resolver_config(resolver);
+    Resolver::new(config, options).map_err(Error::Resolver)
+}
+
+/// 获取 DNS 解析器的配置，供需要异步解析器的阶段使用。
 ///
 /// 1. 如果设置了 `resolver` 参数：
 ///     1. 假设该参数是一个路径并尝试读取 IP。
@@ -172,7 +330,7 @@ fn parse_single_excluded_address(addr: &str, resolver: &Resolver) -> Vec<IpCidr>
 /// 2. 如果未设置 `resolver`：
 ///    1. 尝试从系统配置中获取解析器。（例如 *nix 上的 `/etc/resolv.conf`）。
 ///    2. 最后，构建一个基于 CloudFlare 的解析器（默认行为）。
-fn get_resolver(resolver: &Option<String>) -> Resolver {
+pub fn resolver_config(resolver: &Option<String>) -> (ResolverConfig, ResolverOpts) {
     match resolver {
         Some(r) => {
             let mut config = ResolverConfig::new();
@@ -189,19 +347,15 @@ fn get_resolver(resolver: &Option<String>) -> Resolver {
                     Protocol::Udp,
                 ));
             }
-            Resolver::new(config, ResolverOpts::default()).unwrap()
+            (config, ResolverOpts::default())
         }
-        None => match Resolver::from_system_conf() {
-            Ok(resolver) => resolver,
-            Err(_) => {
-                Resolver::new(ResolverConfig::cloudflare_tls(), ResolverOpts::default()).unwrap()
-            }
-        },
+        None => read_system_conf()
+            .unwrap_or_else(|_| (ResolverConfig::cloudflare_tls(), ResolverOpts::default())),
     }
 }
 
 /// 解析用于 DNS 解析的 IP 输入文件。
-fn read_resolver_from_file(path: &str) -> Result<Vec<IpAddr>, std::io::Error> {
+fn read_resolver_from_file(path: &str) -> std::io::Result<Vec<IpAddr>> {
     let ips = fs::read_to_string(path)?
         .lines()
         .filter_map(|line| IpAddr::from_str(line.trim()).ok())
@@ -215,15 +369,16 @@ fn read_resolver_from_file(path: &str) -> Result<Vec<IpAddr>, std::io::Error> {
 fn read_ips_from_file(
     ips: &std::path::Path,
     backup_resolver: &Resolver,
-) -> Result<Vec<IpAddr>, std::io::Error> {
+    family: AddressFamily,
+) -> std::io::Result<Vec<Target>> {
     let file = File::open(ips)?;
     let reader = BufReader::new(file);
 
-    let mut ips: Vec<IpAddr> = Vec::new();
+    let mut ips: Vec<Target> = Vec::new();
 
     for address_line in reader.lines() {
         if let Ok(address) = address_line {
-            ips.extend(parse_address(&address, backup_resolver));
+            ips.extend(parse_target(&address, backup_resolver, family));
         } else {
             debug!("Line in file is not valid");
         }
@@ -234,8 +389,9 @@ fn read_ips_from_file(
 
 #[cfg(test)]
 mod tests {
-    use super::{get_resolver, parse_addresses, Opts};
-    use std::net::Ipv4Addr;
+    use super::{get_resolver, parse_addresses, parse_addresses_with_events, Opts};
+    use crate::event::{Event, EventSink};
+    use std::net::{IpAddr, Ipv4Addr};
 
     #[test]
     fn parse_correct_addresses() {
@@ -244,7 +400,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(
             ips,
@@ -265,7 +421,7 @@ mod tests {
             exclude_addresses: Some(vec!["192.168.0.1".to_owned()]),
             ..Default::default()
         };
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(
             ips,
@@ -284,7 +440,7 @@ mod tests {
             exclude_addresses: Some(vec!["192.168.0.0/30".to_owned()]),
             ..Default::default()
         };
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(
             ips,
@@ -304,7 +460,7 @@ mod tests {
             exclude_addresses: Some(vec!["192.168.0.1".to_owned()]),
             ..Default::default()
         };
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(
             ips,
@@ -323,11 +479,30 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 1);
     }
 
+    #[test]
+    fn address_family_applies_to_hostnames_only() {
+        let opts = Opts {
+            addresses: vec!["localhost".to_owned(), "::1".to_owned()],
+            ipv4: true,
+            ..Default::default()
+        };
+
+        let ips = parse_addresses(&opts).unwrap();
+
+        assert_eq!(
+            ips,
+            vec![
+                "127.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+    }
+
     #[test]
     fn parse_correct_and_incorrect_addresses() {
         let opts = Opts {
@@ -335,11 +510,39 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 1),]);
     }
 
+    #[test]
+    fn unresolved_addresses_are_sent_to_events() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Collect(Mutex<Vec<Event>>);
+
+        impl EventSink for Collect {
+            fn event(&self, event: Event) {
+                self.0.lock().unwrap().push(event);
+            }
+        }
+
+        let opts = Opts {
+            addresses: vec!["127.0.0.1".to_owned(), "im_wrong".to_owned()],
+            ..Default::default()
+        };
+        let events = Collect::default();
+
+        let ips = parse_addresses_with_events(&opts, &events).unwrap();
+
+        assert_eq!(ips, [Ipv4Addr::new(127, 0, 0, 1)]);
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec![Event::UnresolvedHost("im_wrong".to_owned())]
+        );
+    }
+
     #[test]
     fn parse_incorrect_addresses() {
         let opts = Opts {
@@ -347,7 +550,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert!(ips.is_empty());
     }
@@ -360,7 +563,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 3);
     }
@@ -373,7 +576,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 0);
     }
@@ -386,7 +589,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 0);
     }
@@ -398,7 +601,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 2_048);
     }
@@ -411,7 +614,7 @@ mod tests {
             ..Default::default()
         };
 
-        let ips = parse_addresses(&opts);
+        let ips = parse_addresses(&opts).unwrap();
 
         assert_eq!(ips.len(), 256);
     }
@@ -424,7 +627,7 @@ mod tests {
             ..Default::default()
         };
 
-        let resolver = get_resolver(&opts.resolver);
+        let resolver = get_resolver(&opts.resolver).unwrap();
         let lookup = resolver.lookup_ip("www.example.com.").unwrap();
 
         assert!(lookup.iter().next().is_some());
@@ -0,0 +1,324 @@
+//! 端口扫描之前的主机发现阶段，类似 nmap 的 `-sn`。
+//!
+//! 在稀疏的网段里，大部分 IP 上根本没有主机，对它们扫描全部端口只会白白等待超时。
+//! 这里先对每个 IP 做一次不需要特权的探测，只有存活的主机才进入完整的端口扫描：
+//!
+//! - 对少量常见端口发起 TCP 连接，连接成功或者被拒绝（RST）都说明主机存活；
+//! - 系统允许创建 ICMP 数据报套接字时（Linux 上由 `net.ipv4.ping_group_range` 控制），
+//!   同时发送 ICMP echo 请求。
+//!
+//! ```rust
+//! # use rustscan::discovery::{Discovery, DEFAULT_PING_PORTS};
+//! # use std::time::Duration;
+//! let discovery = Discovery::new(Duration::from_millis(1_000), DEFAULT_PING_PORTS.to_vec());
+//! ```
+use crate::scanner::{probe_concurrently, SourceOptions};
+use async_std::io;
+use async_std::net::UdpSocket;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::debug;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 默认用于 TCP 连接探测的端口。
+pub const DEFAULT_PING_PORTS: [u16; 5] = [80, 443, 22, 445, 3389];
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// echo 请求中携带的数据。
+const ICMP_PAYLOAD: &[u8] = b"rustscan";
+
+/// 主机是通过哪种探测被发现的。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingMethod {
+    Icmp,
+    Tcp(u16),
+}
+
+impl fmt::Display for PingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PingMethod::Icmp => write!(f, "icmp echo"),
+            PingMethod::Tcp(port) => write!(f, "tcp/{port}"),
+        }
+    }
+}
+
+/// 系统是否允许为给定的地址族创建 ICMP 数据报套接字。
+pub fn icmp_permitted(ip: IpAddr) -> bool {
+    icmp_socket(ip).is_ok()
+}
+
+fn icmp_socket(ip: IpAddr) -> io::Result<Socket> {
+    match ip {
+        IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)),
+        IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::ICMPV6)),
+    }
+}
+
+/// 主机发现。
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    timeout: Duration,
+    ports: Vec<u16>,
+    concurrency: usize,
+    source: SourceOptions,
+    /// 已经打开的套接字数，用来轮流选择源地址
+    sockets: Arc<AtomicUsize>,
+}
+
+impl Discovery {
+    /// `timeout` 是每个探测等待响应的时间，`ports` 是 TCP 连接探测使用的端口。
+    pub fn new(timeout: Duration, ports: Vec<u16>) -> Self {
+        Self {
+            timeout,
+            ports,
+            concurrency: 64,
+            source: SourceOptions::default(),
+            sockets: Arc::default(),
+        }
+    }
+
+    /// 从和端口扫描相同的源地址、源端口或网络接口发出探测，
+    /// 否则只允许特定源地址的 ACL 会丢弃探测，主机被当作不在线。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 设置同时打开的套接字数量的上限。
+    ///
+    /// 每个主机会同时发出 `ports.len() + 1` 个探测，所以同时探测的主机数会相应减少。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 探测所有 IP，按输入的顺序返回存活的主机以及发现它们的方式。
+    pub async fn run(&self, ips: &[IpAddr]) -> Vec<(IpAddr, PingMethod)> {
+        let hosts = (self.concurrency / (self.ports.len() + 1)).max(1);
+        let mut alive = probe_concurrently(ips.iter().copied(), hosts, |ip| self.ping(ip)).await;
+
+        ips.iter()
+            .filter_map(|ip| alive.remove(ip).map(|method| (*ip, method)))
+            .collect()
+    }
+
+    /// 同时发出所有探测，第一个得到响应的探测决定结果。
+    pub async fn ping(&self, ip: IpAddr) -> Option<PingMethod> {
+        let mut ftrs: FuturesUnordered<BoxFuture<'_, Option<PingMethod>>> = self
+            .ports
+            .iter()
+            .map(|&port| self.tcp_ping(SocketAddr::new(ip, port)).boxed())
+            .collect();
+        ftrs.push(self.icmp_ping(ip).boxed());
+
+        while let Some(result) = ftrs.next().await {
+            if result.is_some() {
+                debug!("{ip} is up ({:?})", result);
+                return result;
+            }
+        }
+        None
+    }
+
+    /// 连接成功或者被拒绝都说明主机在线，超时和不可达则没有结论。
+    async fn tcp_ping(&self, socket: SocketAddr) -> Option<PingMethod> {
+        let nth = self.sockets.fetch_add(1, Ordering::Relaxed);
+        match io::timeout(self.timeout, self.source.connect(socket, nth)).await {
+            Ok(_) => Some(PingMethod::Tcp(socket.port())),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                Some(PingMethod::Tcp(socket.port()))
+            }
+            Err(e) => {
+                debug!("TCP ping to {socket} failed {e}");
+                None
+            }
+        }
+    }
+
+    async fn icmp_ping(&self, ip: IpAddr) -> Option<PingMethod> {
+        match self.icmp_echo(ip).await {
+            Ok(true) => Some(PingMethod::Icmp),
+            Ok(false) => None,
+            Err(e) => {
+                debug!("ICMP echo to {ip} failed {e}");
+                None
+            }
+        }
+    }
+
+    /// 发送一个 echo 请求并在超时之前等待 echo 应答。
+    ///
+    /// ICMP 数据报套接字由内核填写标识符，并且只把属于这个套接字的应答交给我们。
+    async fn icmp_echo(&self, ip: IpAddr) -> io::Result<bool> {
+        let socket = if self.source.is_default() {
+            icmp_socket(ip)?
+        } else {
+            let protocol = match ip {
+                IpAddr::V4(_) => Protocol::ICMPV4,
+                IpAddr::V6(_) => Protocol::ICMPV6,
+            };
+            let nth = self.sockets.fetch_add(1, Ordering::Relaxed);
+            self.source.socket(ip, nth, Type::DGRAM, Some(protocol))?
+        };
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from(std::net::UdpSocket::from(socket));
+        socket.connect(SocketAddr::new(ip, 0)).await?;
+
+        let request = echo_request(ip);
+        io::timeout(self.timeout, socket.send(&request)).await?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 1500];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            match io::timeout(remaining, socket.recv(&mut buf)).await {
+                Ok(size) if is_echo_reply(ip, &buf[..size]) => return Ok(true),
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// 构造 echo 请求，标识符留给内核填写。
+fn echo_request(ip: IpAddr) -> Vec<u8> {
+    let kind = match ip {
+        IpAddr::V4(_) => ICMPV4_ECHO_REQUEST,
+        IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+    };
+    let mut packet = vec![kind, 0, 0, 0, 0, 0, 0, 1];
+    packet.extend_from_slice(ICMP_PAYLOAD);
+
+    // ICMPv6 的校验和包含伪首部，由内核计算
+    if ip.is_ipv4() {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// 一些系统（例如 macOS）会在 IPv4 的 ICMP 数据报前面带上 IP 首部。
+fn is_echo_reply(ip: IpAddr, packet: &[u8]) -> bool {
+    match ip {
+        IpAddr::V4(_) => {
+            let packet = match packet.first() {
+                Some(byte) if byte >> 4 == 4 => packet.get(usize::from(byte & 0x0f) * 4..),
+                _ => Some(packet),
+            };
+            packet.and_then(<[u8]>::first) == Some(&ICMPV4_ECHO_REPLY)
+        }
+        IpAddr::V6(_) => packet.first() == Some(&ICMPV6_ECHO_REPLY),
+    }
+}
+
+/// RFC 1071 互联网校验和。
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let sum = sum as u16;
+    !sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, echo_request, is_echo_reply, Discovery, PingMethod};
+    use crate::scanner::SourceOptions;
+    use async_std::net::TcpListener;
+    use async_std::task::block_on;
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn echo_request_has_valid_checksum() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let packet = echo_request(ip);
+        assert_eq!(packet[0], 8);
+        assert_eq!(checksum(&packet), 0);
+
+        let v6 = echo_request("::1".parse().unwrap());
+        assert_eq!(v6[0], 128);
+        assert_eq!(&v6[2..4], &[0, 0]);
+    }
+
+    #[test]
+    fn echo_reply_is_recognised() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(is_echo_reply(ip, &[0, 0, 0, 0]));
+        assert!(!is_echo_reply(ip, &[8, 0, 0, 0]));
+        // 带 20 字节 IP 首部的应答
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&[0, 0, 0, 0]);
+        assert!(is_echo_reply(ip, &with_header));
+        assert!(is_echo_reply("::1".parse().unwrap(), &[129, 0]));
+    }
+
+    #[test]
+    fn open_and_closed_ports_mark_host_up() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let open = listener.local_addr().unwrap().port();
+            let ip = "127.0.0.1".parse().unwrap();
+
+            let discovery = Discovery::new(Duration::from_millis(500), vec![open]);
+            assert_eq!(
+                discovery.tcp_ping((ip, open).into()).await,
+                Some(PingMethod::Tcp(open))
+            );
+
+            drop(listener);
+            // 端口关闭时内核回复 RST，主机同样被认为在线
+            assert_eq!(
+                discovery.tcp_ping((ip, open).into()).await,
+                Some(PingMethod::Tcp(open))
+            );
+            assert_eq!(discovery.run(&[ip]).await.len(), 1);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tcp_ping_uses_source_address() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let open = listener.local_addr().unwrap().port();
+            let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+            // 整个 127.0.0.0/8 都在本机上，可以用另一个地址作为源地址
+            let source = SourceOptions {
+                ips: vec!["127.0.0.2".parse().unwrap()],
+                ..SourceOptions::default()
+            };
+            let discovery =
+                Discovery::new(Duration::from_millis(500), vec![open]).with_source(source);
+            assert_eq!(
+                discovery.tcp_ping((ip, open).into()).await,
+                Some(PingMethod::Tcp(open))
+            );
+            let (_, peer) = listener.accept().await.unwrap();
+            assert_eq!(peer.ip(), "127.0.0.2".parse::<IpAddr>().unwrap());
+        });
+    }
+}
@@ -0,0 +1,188 @@
+//! 库 API 返回的错误类型。
+//!
+//! 库代码不会因为错误退出进程或 panic，而是返回 [`Error`]，由调用方决定如何处理。
+//! 命令行打印错误信息后以状态码 1 退出。
+use crate::scanner::BuildError;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// `Result<T, rustscan::Error>` 的简写。
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// RustScan 库可能返回的错误。
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// 无法确定配置文件或脚本目录所在的用户目录
+    #[error("could not infer the {0} path")]
+    NoConfigPath(&'static str),
+
+    /// 配置文件存在但无法读取
+    #[error("could not read configuration file {path:?}: {source}")]
+    ConfigRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// 配置文件不是有效的 TOML 或者包含未知的字段
+    #[error("found {source} in configuration file {path:?}")]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// 无法创建 DNS 解析器
+    #[error("could not create DNS resolver: {0}")]
+    Resolver(#[source] io::Error),
+
+    /// 无法创建反向 DNS 查询使用的 tokio 运行时
+    #[error("could not start the async runtime: {0}")]
+    Runtime(#[source] io::Error),
+
+    /// 扫描器的设置无效
+    #[error("invalid scan settings: {0}")]
+    Build(#[from] BuildError),
+
+    /// 打开的文件描述符达到了上限，需要减小批处理大小或者提高 ulimit
+    #[error("too many open files, reduce the batch size or raise the ulimit")]
+    TooManyOpenFiles,
+
+    /// 端口拒绝了连接
+    #[error("port closed {0}")]
+    PortClosed(SocketAddr),
+
+    /// 所有尝试都没有收到响应
+    #[error("no response for all tries {0}")]
+    NoResponse(SocketAddr),
+
+    /// 探测 socket 时出现了其它 I/O 错误
+    #[error("{source} {socket}")]
+    Probe {
+        socket: SocketAddr,
+        #[source]
+        source: io::Error,
+    },
+
+    /// 服务识别使用的探测数据库无法读取或解析
+    #[error("could not load service probes {path:?}: {reason}")]
+    ServiceProbes { path: PathBuf, reason: String },
+
+    /// 脚本目录不存在或无法读取
+    #[error("can't find scripts folder {path:?}: {source}")]
+    ScriptsDir {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// 脚本没有 `call_format` 或者无法填充占位符
+    #[error("invalid script format: {0}")]
+    ScriptFormat(String),
+
+    /// 无法启动脚本
+    #[error("could not run script {command:?}: {source}")]
+    ScriptSpawn {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// 脚本以非 0 状态退出
+    #[error("script {command:?} failed with {status}")]
+    ScriptStatus { command: String, status: ExitStatus },
+}
+
+impl Error {
+    /// 根据探测 `socket` 时出现的 I/O 错误创建错误，文件描述符耗尽时返回 [`Error::TooManyOpenFiles`]。
+    pub(crate) fn probe(socket: SocketAddr, source: io::Error) -> Self {
+        if is_fd_exhaustion(&source) {
+            Error::TooManyOpenFiles
+        } else {
+            Error::Probe { socket, source }
+        }
+    }
+
+    /// 探测没有结论、稍后重试可能得到不同结果：没有响应，或者 EAGAIN、ENOBUFS 这类暂时性的错误。
+    /// 端口关闭、主机不可达等明确的结果不会重试。
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::NoResponse(_) => true,
+            Error::Probe { source, .. } => is_transient(source),
+            _ => false,
+        }
+    }
+}
+
+/// 进程或者整个系统打开的文件描述符达到了上限。
+fn is_fd_exhaustion(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    const EXHAUSTED: [i32; 2] = [libc::EMFILE, libc::ENFILE];
+    // WSAEMFILE
+    #[cfg(not(unix))]
+    const EXHAUSTED: [i32; 1] = [10024];
+
+    error
+        .raw_os_error()
+        .is_some_and(|code| EXHAUSTED.contains(&code))
+}
+
+/// 系统暂时没有资源（发送缓冲区、本地端口）或者调用被打断。
+fn is_transient(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    const ENOBUFS: i32 = libc::ENOBUFS;
+    // WSAENOBUFS
+    #[cfg(not(unix))]
+    const ENOBUFS: i32 = 10055;
+
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::AddrNotAvailable
+    ) || error.raw_os_error() == Some(ENOBUFS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use std::io;
+
+    #[test]
+    fn fd_exhaustion_is_recognised() {
+        let socket = "127.0.0.1:80".parse().unwrap();
+
+        #[cfg(unix)]
+        assert!(matches!(
+            Error::probe(socket, io::Error::from_raw_os_error(libc::EMFILE)),
+            Error::TooManyOpenFiles
+        ));
+        // 只看错误码，不看错误信息
+        let error = Error::probe(socket, io::Error::other("too many open files"));
+        assert!(matches!(error, Error::Probe { .. }));
+
+        let error = Error::probe(socket, io::ErrorKind::AddrNotAvailable.into());
+        assert!(matches!(error, Error::Probe { .. }));
+        assert!(error.to_string().ends_with(" 127.0.0.1:80"));
+    }
+
+    #[test]
+    fn only_ambiguous_errors_are_retryable() {
+        let socket = "127.0.0.1:80".parse().unwrap();
+
+        assert!(Error::NoResponse(socket).is_retryable());
+        assert!(!Error::PortClosed(socket).is_retryable());
+        assert!(Error::probe(socket, io::ErrorKind::WouldBlock.into()).is_retryable());
+        assert!(!Error::probe(socket, io::ErrorKind::ConnectionReset.into()).is_retryable());
+        #[cfg(unix)]
+        assert!(Error::probe(socket, io::Error::from_raw_os_error(libc::ENOBUFS)).is_retryable());
+        #[cfg(unix)]
+        assert!(
+            !Error::probe(socket, io::Error::from_raw_os_error(libc::EHOSTUNREACH)).is_retryable()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! 扫描过程中产生的事件，以及处理它们的 [`EventSink`]。
+//!
+//! 库代码不直接向标准输出打印任何内容，而是把事件交给调用方提供的 `EventSink`。
+//! 命令行使用 [`CliPrinter`] 按原来的格式打印；把 RustScan 当作库使用、
+//! 标准输出另有用途的程序可以使用 [`NullSink`] 或者自己的实现。
+//!
+//! ```rust
+//! # use rustscan::event::{Event, EventSink};
+//! # use std::sync::Mutex;
+//! /// 把开放的端口收集起来，而不是打印。
+//! #[derive(Default)]
+//! struct Collect(Mutex<Vec<String>>);
+//!
+//! impl EventSink for Collect {
+//!     fn event(&self, event: Event) {
+//!         if let Event::OpenPort { socket, .. } = event {
+//!             self.0.lock().unwrap().push(socket.to_string());
+//!         }
+//!     }
+//! }
+//! ```
+use crate::progress::Progress;
+use colored::Colorize;
+use std::net::SocketAddr;
+
+/// 库代码会报告的事件。
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// 扫描发现了一个开放的端口，开启 banner 抓取时带上 banner
+    OpenPort {
+        socket: SocketAddr,
+        banner: Option<String>,
+    },
+    /// 输入的地址既不是 IP、CIDR、可以解析的主机名，也不是可读的文件
+    UnresolvedHost(String),
+    /// 扫描进度，见 [`crate::progress`]
+    Progress(Progress),
+    /// 扫描通过 [`ScanControl`](crate::scanner::ScanControl) 暂停，已经发出的探测照常完成
+    ScanPaused,
+    /// 暂停的扫描恢复
+    ScanResumed,
+}
+
+/// 接收事件的对象，扫描时可能从多个任务中同时调用。
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: Event);
+}
+
+/// 命令行的默认输出。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CliPrinter {
+    greppable: bool,
+    accessible: bool,
+}
+
+impl CliPrinter {
+    /// `greppable` 时什么都不打印，`accessible` 时不使用颜色和符号。
+    pub fn new(greppable: bool, accessible: bool) -> Self {
+        Self {
+            greppable,
+            accessible,
+        }
+    }
+}
+
+impl EventSink for CliPrinter {
+    fn event(&self, event: Event) {
+        match event {
+            Event::OpenPort { socket, banner } => {
+                if !self.greppable {
+                    let banner = banner.map(|b| format!(" {b}")).unwrap_or_default();
+                    if self.accessible {
+                        println!("Open {socket}{banner}");
+                    } else {
+                        println!("Open {}{}", socket.to_string().purple(), banner);
+                    }
+                }
+            }
+            Event::UnresolvedHost(host) => {
+                crate::warning!(
+                    format!("Host {host:?} could not be resolved."),
+                    self.greppable,
+                    self.accessible
+                );
+            }
+            Event::ScanPaused => {
+                crate::detail!(
+                    "Scan paused, no new ports will be probed until it is resumed.",
+                    self.greppable,
+                    self.accessible
+                );
+            }
+            Event::ScanResumed => {
+                crate::detail!("Scan resumed.", self.greppable, self.accessible);
+            }
+            // 进度由 ProgressPrinter 显示
+            Event::Progress(_) => {}
+        }
+    }
+}
+
+/// 丢弃所有事件。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn event(&self, _event: Event) {}
+}
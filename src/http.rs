@@ -0,0 +1,495 @@
+//! 扫描之后的 HTTP(S) 探测阶段。
+//!
+//! 大部分开放端口都是 Web 服务。这里对每个开放的 TCP 端口发送一次 `GET /`，
+//! 先尝试明文 HTTP，失败后再通过 TLS 尝试 HTTPS，
+//! 记录状态码、`Server` 头、`<title>`、重定向地址和内容长度。
+//!
+//! 重定向不会被跟随：目标可能是另一个主机，这里只记录 `Location`。
+//!
+//! ```rust
+//! # use rustscan::http::HttpProber;
+//! # use std::time::Duration;
+//! let prober = HttpProber::new(Duration::from_millis(1_500)).with_host("example.com".to_owned());
+//! ```
+use crate::scanner::{probe_concurrently, SourceOptions};
+use crate::tls::insecure_connector;
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use futures_rustls::TlsConnector;
+use log::debug;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+use rustls::ServerName;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// 默认同时探测的端口数。
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// 最多读取的响应字节数，足够覆盖响应头和页面开头的 `<title>`。
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+
+/// 标题最多保留的字符数。
+const MAX_TITLE_LEN: usize = 256;
+
+static TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+/// 一次 HTTP 请求得到的信息。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpInfo {
+    /// 是否通过 TLS 访问
+    pub https: bool,
+    pub status: u16,
+    /// 例如 `OK`、`Not Found`
+    pub reason: String,
+    pub server: Option<String>,
+    pub title: Option<String>,
+    /// 3xx 响应的 `Location` 头
+    pub location: Option<String>,
+    /// `Content-Length` 头，没有时使用实际读到的正文长度
+    pub content_length: Option<u64>,
+}
+
+impl HttpInfo {
+    /// `http` 或者 `https`
+    pub fn scheme(&self) -> &'static str {
+        if self.https {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// 解析原始响应，不是 HTTP 响应时返回 `None`。
+    ///
+    /// `complete` 表示连接已经被对方关闭，此时读到的正文就是完整的正文。
+    ///
+    /// ```rust
+    /// # use rustscan::http::HttpInfo;
+    /// let raw = b"HTTP/1.1 200 OK\r\nServer: nginx\r\n\r\n<title>Hi</title>";
+    /// let info = HttpInfo::parse(raw, false, true).unwrap();
+    /// assert_eq!(info.status, 200);
+    /// assert_eq!(info.title.as_deref(), Some("Hi"));
+    /// ```
+    pub fn parse(raw: &[u8], https: bool, complete: bool) -> Option<Self> {
+        let (head, body) = split_head(raw)?;
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+
+        let mut status_line = lines.next()?.splitn(3, ' ');
+        if !status_line.next()?.starts_with("HTTP/") {
+            return None;
+        }
+        let status = status_line.next()?.parse().ok()?;
+        let reason = status_line.next().unwrap_or_default().trim().to_owned();
+
+        let mut server = None;
+        let mut location = None;
+        let mut content_length = None;
+        let mut chunked = false;
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "server" => server = Some(value.to_owned()),
+                "location" if (300..400).contains(&status) => location = Some(value.to_owned()),
+                "content-length" => content_length = value.parse().ok(),
+                "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                _ => {}
+            }
+        }
+
+        if content_length.is_none() && complete && !chunked {
+            content_length = u64::try_from(body.len()).ok();
+        }
+
+        Some(Self {
+            https,
+            status,
+            reason,
+            server,
+            title: parse_title(body),
+            location,
+            content_length,
+        })
+    }
+}
+
+/// 按 `\r\n\r\n`（或者不规范的 `\n\n`）把响应分成响应头和正文。
+fn split_head(raw: &[u8]) -> Option<(&[u8], &[u8])> {
+    if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        return Some((&raw[..end], &raw[end + 4..]));
+    }
+    raw.windows(2)
+        .position(|w| w == b"\n\n")
+        .map(|end| (&raw[..end], &raw[end + 2..]))
+}
+
+/// 提取 `<title>`，合并空白并还原常见的 HTML 实体。
+fn parse_title(body: &[u8]) -> Option<String> {
+    let captures = TITLE.captures(body)?;
+    let raw = String::from_utf8_lossy(captures.get(1)?.as_bytes());
+    let title = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let title: String = title
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TITLE_LEN)
+        .collect();
+    (!title.is_empty()).then_some(title)
+}
+
+impl fmt::Display for HttpInfo {
+    /// 格式化为 `http 301 Moved Permanently server="nginx" location="https://example.com/" length=162`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.scheme(), self.status)?;
+        if !self.reason.is_empty() {
+            write!(f, " {}", self.reason)?;
+        }
+        if let Some(server) = &self.server {
+            write!(f, " server=\"{server}\"")?;
+        }
+        if let Some(title) = &self.title {
+            write!(f, " title=\"{title}\"")?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " location=\"{location}\"")?;
+        }
+        if let Some(length) = self.content_length {
+            write!(f, " length={length}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 对开放端口进行 HTTP(S) 探测。
+pub struct HttpProber {
+    connector: TlsConnector,
+    timeout: Duration,
+    concurrency: usize,
+    host: Option<String>,
+    source: SourceOptions,
+    /// 已经发起的连接数，用来轮流选择源地址
+    connects: AtomicUsize,
+}
+
+impl fmt::Debug for HttpProber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpProber")
+            .field("timeout", &self.timeout)
+            .field("concurrency", &self.concurrency)
+            .field("host", &self.host)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpProber {
+    /// `timeout` 同时限制建立连接和等待响应的时间。
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            connector: insecure_connector(&[b"http/1.1"]),
+            timeout,
+            concurrency: DEFAULT_CONCURRENCY,
+            host: None,
+            source: SourceOptions::default(),
+            connects: AtomicUsize::new(0),
+        }
+    }
+
+    /// 设置同时探测的端口数。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 设置请求的 Host 头，HTTPS 请求也会把它作为 SNI 发送。
+    #[must_use]
+    pub fn with_host(mut self, host: String) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// 从和端口扫描相同的源地址、源端口或网络接口发起连接。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 对所有给定的 socket 发送请求，没有返回 HTTP 响应的 socket 不会出现在结果中。
+    pub async fn probe(&self, sockets: &[SocketAddr]) -> HashMap<SocketAddr, HttpInfo> {
+        probe_concurrently(sockets.iter().copied(), self.concurrency, |socket| {
+            self.fetch(socket)
+        })
+        .await
+    }
+
+    /// 先尝试明文 HTTP，失败后再尝试 HTTPS。
+    pub async fn fetch(&self, socket: SocketAddr) -> Option<HttpInfo> {
+        match self.fetch_plain(socket).await {
+            Ok(Some(info)) => return Some(info),
+            Ok(None) => debug!("{socket} did not answer with HTTP"),
+            Err(e) => debug!("HTTP request to {socket} failed {e}"),
+        }
+
+        match self.fetch_tls(socket).await {
+            Ok(info) => info,
+            Err(e) => {
+                debug!("HTTPS request to {socket} failed {e}");
+                None
+            }
+        }
+    }
+
+    async fn fetch_plain(&self, socket: SocketAddr) -> io::Result<Option<HttpInfo>> {
+        let mut stream = io::timeout(self.timeout, self.connect(socket)).await?;
+        let (raw, complete) = self.exchange(&mut stream, socket).await?;
+        Ok(HttpInfo::parse(&raw, false, complete))
+    }
+
+    async fn fetch_tls(&self, socket: SocketAddr) -> io::Result<Option<HttpInfo>> {
+        let stream = io::timeout(self.timeout, self.connect(socket)).await?;
+        let mut tls = io::timeout(
+            self.timeout,
+            self.connector.connect(self.server_name(socket), stream),
+        )
+        .await?;
+        let (raw, complete) = self.exchange(&mut tls, socket).await?;
+        Ok(HttpInfo::parse(&raw, true, complete))
+    }
+
+    async fn connect(&self, socket: SocketAddr) -> io::Result<TcpStream> {
+        let nth = self.connects.fetch_add(1, Ordering::Relaxed);
+        self.source.connect(socket, nth).await
+    }
+
+    /// SNI 使用配置的 Host（去掉端口），不是合法域名时使用 IP。
+    fn server_name(&self, socket: SocketAddr) -> ServerName {
+        self.host
+            .as_deref()
+            .map(strip_port)
+            .and_then(|host| ServerName::try_from(host).ok())
+            .unwrap_or(ServerName::IpAddress(socket.ip()))
+    }
+
+    /// Host 头：配置的值，或者目标地址（非默认端口时带上端口）。
+    fn host_header(&self, socket: SocketAddr) -> String {
+        if let Some(host) = &self.host {
+            return host.clone();
+        }
+        let ip = match socket {
+            SocketAddr::V4(v4) => v4.ip().to_string(),
+            SocketAddr::V6(v6) => format!("[{}]", v6.ip()),
+        };
+        match socket.port() {
+            80 | 443 => ip,
+            port => format!("{ip}:{port}"),
+        }
+    }
+
+    /// 发送请求并读取响应，直到对方关闭连接、正文读完、读满上限或者超时。
+    ///
+    /// 返回读到的数据以及对方是否已经关闭连接。
+    async fn exchange<S>(&self, stream: &mut S, socket: SocketAddr) -> io::Result<(Vec<u8>, bool)>
+    where
+        S: io::Read + io::Write + Unpin,
+    {
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: {}\r\nUser-Agent: RustScan\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            self.host_header(socket)
+        );
+        io::timeout(self.timeout, stream.write_all(request.as_bytes())).await?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while response.len() < MAX_RESPONSE_LEN {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match io::timeout(remaining, stream.read(&mut chunk)).await {
+                Ok(0) => return Ok((response, true)),
+                Ok(size) => {
+                    response.extend_from_slice(&chunk[..size]);
+                    if body_is_complete(&response) {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) if response.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        response.truncate(MAX_RESPONSE_LEN);
+        Ok((response, false))
+    }
+}
+
+/// 没有遵守 `Connection: close` 的服务器：按 `Content-Length` 判断正文是否已经读完。
+fn body_is_complete(response: &[u8]) -> bool {
+    let Some(info) = HttpInfo::parse(response, false, false) else {
+        return false;
+    };
+    let (_, body) = split_head(response).unwrap_or_default();
+    info.content_length
+        .and_then(|length| usize::try_from(length).ok())
+        .is_some_and(|length| body.len() >= length)
+}
+
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_port, HttpInfo, HttpProber};
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task::{self, block_on};
+    use futures_rustls::TlsAcceptor;
+    use rustls::{Certificate, PrivateKey, ServerConfig};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_redirect_response() {
+        let raw = b"HTTP/1.1 301 Moved Permanently\r\nserver: nginx/1.25.3\r\n\
+            Location: https://example.com/\r\nContent-Length: 162\r\n\r\n\
+            <html><head><title>301 Moved Permanently</title></head></html>";
+        let info = HttpInfo::parse(raw, false, false).unwrap();
+
+        assert_eq!(info.status, 301);
+        assert_eq!(info.reason, "Moved Permanently");
+        assert_eq!(info.server.as_deref(), Some("nginx/1.25.3"));
+        assert_eq!(info.location.as_deref(), Some("https://example.com/"));
+        assert_eq!(info.content_length, Some(162));
+        assert_eq!(
+            info.to_string(),
+            "http 301 Moved Permanently server=\"nginx/1.25.3\" title=\"301 Moved Permanently\" \
+             location=\"https://example.com/\" length=162"
+        );
+    }
+
+    #[test]
+    fn title_is_cleaned_up() {
+        let raw = b"HTTP/1.0 200 OK\r\n\r\n<TITLE lang=\"en\">\n  Tom &amp; Jerry\r\n\t</TITLE>";
+        let info = HttpInfo::parse(raw, true, true).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(info.content_length, Some(46));
+        assert!(info.location.is_none());
+        assert!(info.to_string().starts_with("https 200 OK"));
+    }
+
+    #[test]
+    fn non_http_response_is_ignored() {
+        assert!(HttpInfo::parse(b"SSH-2.0-OpenSSH_9.6\r\n\r\n", false, true).is_none());
+        assert!(HttpInfo::parse(b"HTTP/1.1 200 OK\r\nServer: x", false, false).is_none());
+        assert!(HttpInfo::parse(b"HTTP/1.1 abc\r\n\r\n", false, true).is_none());
+    }
+
+    #[test]
+    fn host_port_is_stripped() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("::1"), "::1");
+    }
+
+    #[test]
+    fn fetches_plain_http_with_host_header() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let socket = listener.local_addr().unwrap();
+            task::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let size = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..size]).into_owned();
+                let host = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Host: "))
+                    .unwrap()
+                    .to_owned();
+                let body = format!("<title>{host}</title>");
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nServer: test\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                // 保持连接，检查按 Content-Length 结束读取
+                task::sleep(Duration::from_secs(5)).await;
+            });
+
+            let prober = HttpProber::new(Duration::from_secs(2)).with_host("intranet.local".into());
+            let info = prober.probe(&[socket]).await.remove(&socket).unwrap();
+
+            assert!(!info.https);
+            assert_eq!(info.status, 200);
+            assert_eq!(info.server.as_deref(), Some("test"));
+            assert_eq!(info.title.as_deref(), Some("intranet.local"));
+        });
+    }
+
+    #[test]
+    fn falls_back_to_https() {
+        block_on(async {
+            let config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![Certificate(
+                        include_bytes!("../fixtures/tls/cert.der").to_vec(),
+                    )],
+                    PrivateKey(include_bytes!("../fixtures/tls/key.der").to_vec()),
+                )
+                .unwrap();
+            let acceptor = TlsAcceptor::from(Arc::new(config));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let socket = listener.local_addr().unwrap();
+            task::spawn(async move {
+                let mut incoming = listener.incoming();
+                while let Some(Ok(stream)) = incoming.next().await {
+                    // 明文请求会让握手失败，继续等待下一个连接
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        continue;
+                    };
+                    let mut buf = [0u8; 1024];
+                    let _ = tls.read(&mut buf).await;
+                    let _ = tls
+                        .write_all(b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\nnope")
+                        .await;
+                    let _ = futures::AsyncWriteExt::close(&mut tls).await;
+                    break;
+                }
+            });
+
+            let prober = HttpProber::new(Duration::from_secs(2));
+            let info = prober.fetch(socket).await.unwrap();
+
+            assert!(info.https);
+            assert_eq!(info.status, 404);
+            assert_eq!(info.content_length, Some(4));
+        });
+    }
+}
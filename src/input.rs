@@ -1,11 +1,26 @@
 //! 提供一种读取、解析和保存扫描配置选项的方法。
-use clap::{Parser, ValueEnum};
-use serde_derive::{Deserialize, Serialize};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde_derive::Deserialize;
 use std::fs;
+use std::net::IpAddr;
 use std::path::PathBuf;
 
+use crate::address::AddressFamily;
+use crate::scanner::Proxy;
+use crate::Error;
+
 const LOWEST_PORT_NUMBER: u16 = 1;
 const TOP_PORT_NUMBER: u16 = 65535;
+/// 时间模板设置的选项，在命令行上明确给出时不被模板覆盖。
+const TIMING_FIELDS: [&str; 6] = [
+    "batch_size",
+    "timeout",
+    "tries",
+    "ulimit",
+    "max_rate",
+    "scan_delay",
+];
 
 /// 表示端口扫描运行的策略。
 ///   - Serial 将从开始到结束运行，例如 1 到 1_000。
@@ -27,6 +42,108 @@ pub enum ScriptsRequired {
     Custom,
 }
 
+/// 表示扫描进度的显示方式，进度总是写到 stderr。
+///   - auto 在终端上显示状态行，否则定期输出 NDJSON；无障碍模式下不显示。
+///   - line 显示一行不断刷新的状态。
+///   - ndjson 定期输出一行 JSON，适合 CI 日志。
+///   - off 不显示进度。
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    Auto,
+    Line,
+    Ndjson,
+    Off,
+}
+
+/// 和 nmap 的 `-T0` 到 `-T5` 对应的时间模板，一次设置批处理大小、超时、重试次数、
+/// 文件描述符限制、速度限制和探测间隔，命令行上明确给出的选项优先。
+///
+/// | 模板           | 批处理 | 超时 (ms) | 尝试次数 | ulimit | 速度限制 | 探测间隔 (ms) |
+/// |----------------|--------|-----------|----------|--------|----------|---------------|
+/// | 0 `paranoid`   | 1      | 5000      | 2        |        |          | 300000        |
+/// | 1 `sneaky`     | 1      | 5000      | 2        |        |          | 15000         |
+/// | 2 `polite`     | 100    | 3000      | 2        |        | 100/s    |               |
+/// | 3 `normal`     | 4500   | 1500      | 1        |        |          |               |
+/// | 4 `aggressive` | 10000  | 1000      | 1        | 15000  |          |               |
+/// | 5 `insane`     | 65535  | 500       | 1        | 70000  |          |               |
+///
+/// `normal` 和不使用模板时的默认值相同。
+#[derive(Deserialize, Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    #[value(alias = "0")]
+    Paranoid,
+    #[value(alias = "1")]
+    Sneaky,
+    #[value(alias = "2")]
+    Polite,
+    #[value(alias = "3")]
+    Normal,
+    #[value(alias = "4")]
+    Aggressive,
+    #[value(alias = "5")]
+    Insane,
+}
+
+/// 时间模板设置的选项，单位和 [`Opts`] 中的同名字段相同。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingProfile {
+    pub batch_size: usize,
+    pub timeout: u32,
+    pub tries: u8,
+    pub ulimit: Option<usize>,
+    pub max_rate: Option<u32>,
+    pub scan_delay: Option<u64>,
+}
+
+impl Timing {
+    pub fn profile(self) -> TimingProfile {
+        let profile = TimingProfile {
+            batch_size: 4500,
+            timeout: 1500,
+            tries: 1,
+            ulimit: None,
+            max_rate: None,
+            scan_delay: None,
+        };
+        match self {
+            Timing::Paranoid => TimingProfile {
+                batch_size: 1,
+                timeout: 5000,
+                tries: 2,
+                scan_delay: Some(300_000),
+                ..profile
+            },
+            Timing::Sneaky => TimingProfile {
+                batch_size: 1,
+                timeout: 5000,
+                tries: 2,
+                scan_delay: Some(15_000),
+                ..profile
+            },
+            Timing::Polite => TimingProfile {
+                batch_size: 100,
+                timeout: 3000,
+                tries: 2,
+                max_rate: Some(100),
+                ..profile
+            },
+            Timing::Normal => profile,
+            Timing::Aggressive => TimingProfile {
+                batch_size: 10_000,
+                timeout: 1000,
+                ulimit: Some(15_000),
+                ..profile
+            },
+            Timing::Insane => TimingProfile {
+                batch_size: 65_535,
+                timeout: 500,
+                ulimit: Some(70_000),
+                ..profile
+            },
+        }
+    }
+}
+
 /// 表示要扫描的端口范围。
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PortRange {
@@ -68,6 +185,7 @@ fn parse_range(input: &str) -> Result<PortRange, String> {
 #[allow(clippy::struct_excessive_bools)]
 /// 用 Rust 构建的快速端口扫描器。
 /// 警告：不要对敏感的基础设施使用此程序，因为指定的服务器可能无法同时处理这么多 socket 连接。
+/// 扫描过程中发送 SIGUSR1 暂停扫描，发送 SIGUSR2 恢复扫描。
 /// - Discord  <http://discord.skerritt.blog>
 /// - GitHub <https://github.com/RustScan/RustScan>
 pub struct Opts {
@@ -107,6 +225,22 @@ pub struct Opts {
     #[arg(long)]
     pub resolver: Option<String>,
 
+    /// 主机名只扫描解析出的 IPv4 地址。
+    #[arg(short = '4', long, conflicts_with_all = ["ipv6", "all_addresses"])]
+    pub ipv4: bool,
+
+    /// 主机名只扫描解析出的 IPv6 地址。
+    #[arg(short = '6', long, conflicts_with = "all_addresses")]
+    pub ipv6: bool,
+
+    /// 扫描主机名解析出的所有地址，默认只扫描系统解析器返回的第一个地址。
+    #[arg(long)]
+    pub all_addresses: bool,
+
+    /// 对发现了开放端口的主机查询反向 DNS（PTR）记录，使用 `--resolver` 指定的解析器。
+    #[arg(long)]
+    pub reverse_dns: bool,
+
     /// 端口扫描的批处理大小，它会增加或减慢扫描速度。
     /// 取决于操作系统的打开文件限制。如果你设置为 65535，
     /// 它将同时扫描每个端口。虽然你的操作系统可能不支持这一点。
@@ -119,9 +253,41 @@ pub struct Opts {
 
     /// 在假定端口关闭之前的重试次数。
     /// 如果设置为 0，rustscan 将将其更正为 1。
+    /// 只有超时和暂时性的错误才会重试，连接被拒绝等明确的结果不会重试。
     #[arg(long, default_value = "1")]
     pub tries: u8,
 
+    /// 第一次重试之前等待的毫秒数，之后每次翻倍，最多 10 秒。默认 100。
+    #[arg(long)]
+    pub retry_backoff: Option<u64>,
+
+    /// 在重试等待时间上随机增加的最大百分比，避免重试同时发出。默认 20。
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub retry_jitter: Option<u8>,
+
+    /// 从这些本机地址发出探测，逗号分隔，多个时轮流使用。
+    /// 高并发扫描同一个目标时可以避免临时端口耗尽。
+    #[arg(long, value_delimiter = ',')]
+    pub source_ip: Option<Vec<IpAddr>>,
+
+    /// 从这个网络接口发出探测，例如 eth1，仅支持 Linux。
+    #[arg(long)]
+    pub interface: Option<String>,
+
+    /// 所有探测使用的源端口，例如 53，用来通过按源端口放行的防火墙。
+    #[arg(long)]
+    pub source_port: Option<u16>,
+
+    /// 连接用 RST 关闭（SO_LINGER 为 0），不在 TIME_WAIT 中占用端口。
+    #[arg(long)]
+    pub linger_zero: bool,
+
+    /// 通过代理进行连接扫描和 banner 抓取，例如 socks5://127.0.0.1:1080 或 http://127.0.0.1:3128。
+    /// 不能和 UDP、SYN、主机发现以及服务、TLS、HTTP 探测一起使用，它们会直接连接目标；
+    /// 脚本也不经过代理。
+    #[arg(long)]
+    pub proxy: Option<Proxy>,
+
     /// 自动使用你提供的值提高 ULIMIT。
     #[arg(short, long)]
     pub ulimit: Option<usize>,
@@ -158,12 +324,160 @@ pub struct Opts {
     /// UDP 扫描模式，查找发回响应的 UDP 端口
     #[arg(long)]
     pub udp: bool,
+
+    /// 对开放的 TCP 端口抓取 banner（例如 SSH、FTP、SMTP 的版本信息）。
+    #[arg(long)]
+    pub banner: bool,
+
+    /// 抓取 banner 时等待服务响应的时间（以毫秒为单位）。
+    #[arg(long, default_value = "1000")]
+    pub banner_timeout: u32,
+
+    /// 连接成功后发送的探测数据，支持 \r \n \t \0 \xNN 转义。
+    /// 未设置时不发送任何数据，只等待服务主动发送 banner。
+    #[arg(long, requires = "banner")]
+    pub banner_probe: Option<String>,
+
+    /// 在扫描结束后对开放端口进行服务和版本识别，不需要安装 nmap。
+    #[arg(long)]
+    pub service_detection: bool,
+
+    /// nmap-service-probes 格式的探测数据库路径。
+    /// 默认使用系统中 nmap 自带的文件，找不到时使用内置的精简数据库。
+    #[arg(long, value_parser, requires = "service_detection")]
+    pub service_probes: Option<PathBuf>,
+
+    /// 服务识别的强度（0-9），越高会发送越多稀有的探测。
+    #[arg(long, default_value = "7", value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub version_intensity: u8,
+
+    /// 扫描结束后对开放的 TCP 端口进行 TLS 握手，
+    /// 记录协议版本、加密套件、ALPN 以及证书的主题、SAN、签发者和过期时间。
+    #[arg(long)]
+    pub tls: bool,
+
+    /// 扫描结束后对开放的 TCP 端口发送 `GET /`，
+    /// 记录状态码、Server 头、页面标题、重定向地址和内容长度。
+    #[arg(long)]
+    pub http: bool,
+
+    /// HTTP 探测时使用的 Host 头，默认使用目标的 IP 地址。
+    #[arg(long, requires = "http")]
+    pub http_host: Option<String>,
+
+    /// 开放端口占扫描端口的百分比达到多少时认为主机是 tarpit（接受所有连接的防火墙），默认 90。
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub tarpit_threshold: Option<u8>,
+
+    /// 每个主机抽样连接多少个开放端口并发送数据，接受连接之后从不响应的主机同样标记为 tarpit。
+    /// 每个主机最多多等一个超时时间，默认不抽样，只按开放端口的比例判断。
+    #[arg(long)]
+    pub tarpit_sample: Option<usize>,
+
+    /// 不对被认为是 tarpit 的主机运行脚本。
+    #[arg(long)]
+    pub skip_tarpits: bool,
+
+    /// 扫描结束后用更长的超时时间和较低的并发重新探测开放的端口，
+    /// 没有再次确认的端口单独列出，不会交给脚本。开放的端口在确认之后才会打印。
+    #[arg(long)]
+    pub verify: bool,
+
+    /// 确认开放端口时的超时时间（毫秒），默认是 --timeout 的三倍。
+    #[arg(long, requires = "verify")]
+    pub verify_timeout: Option<u32>,
+
+    /// 确认开放端口时同时进行的探测数，默认 32。
+    #[arg(long, requires = "verify")]
+    pub verify_concurrency: Option<usize>,
+
+    /// 在端口扫描之前进行主机发现，只扫描存活的主机。
+    /// 使用 TCP 连接探测，系统允许时同时发送 ICMP echo。
+    #[arg(long)]
+    pub discover: bool,
+
+    /// 只进行主机发现并列出存活的主机，不扫描端口。
+    #[arg(long)]
+    pub ping_only: bool,
+
+    /// 主机发现时用于 TCP 连接探测的逗号分隔端口列表，默认为 80,443,22,445,3389。
+    #[arg(long, value_delimiter = ',')]
+    pub ping_ports: Option<Vec<u16>>,
+
+    /// 使用原始套接字进行 SYN 扫描（仅 Linux，需要 root 或 CAP_NET_RAW）。
+    /// 不完成 TCP 握手，也不为每个端口占用文件描述符；权限不足时退回连接扫描。
+    #[arg(long, conflicts_with = "udp")]
+    pub syn: bool,
+
+    /// 整个端口扫描最多运行的毫秒数，到时间后停止并输出已经得到的结果。
+    #[arg(long)]
+    pub max_scan_time: Option<u64>,
+
+    /// 每个主机最多扫描的毫秒数，到时间后跳过它剩下的端口。
+    #[arg(long)]
+    pub host_timeout: Option<u64>,
+
+    /// 在 stderr 上显示扫描进度（已完成/总数、速度、开放端口数和剩余时间）。
+    #[arg(long, value_enum, ignore_case = true, default_value = "auto")]
+    pub progress: ProgressMode,
+
+    /// 时间模板，从慢到快为 paranoid (0)、sneaky (1)、polite (2)、normal (3)、aggressive (4)、insane (5)。
+    /// 一次设置批处理大小、超时、重试次数、ulimit、速度限制和探测间隔，明确给出的选项优先。示例：-T4。
+    #[arg(short = 'T', long, value_enum, ignore_case = true)]
+    pub timing: Option<Timing>,
+
+    /// 每秒最多发起的探测数。
+    #[arg(long)]
+    pub max_rate: Option<u32>,
+
+    /// 两次发起探测之间至少间隔的毫秒数。
+    #[arg(long)]
+    pub scan_delay: Option<u64>,
+
+    /// 每个主机同时进行的探测数上限，保护嵌入式设备、老旧防火墙等脆弱的主机，
+    /// 其它主机仍然使用完整的批处理大小。
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_host_concurrency: Option<u32>,
+
+    /// 每个主机每秒最多发起的探测数。
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_host_rate: Option<u32>,
+
+    /// 使用交互式的全屏界面显示开放端口和进度，可以暂停扫描或者调整速度。
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with = "greppable")]
+    pub tui: bool,
+}
+
+/// 在命令行上明确给出的、受时间模板影响的选项，由 [`Opts::read`] 返回，
+/// [`Opts::merge`] 合并配置文件之后应用时间模板时不覆盖它们。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExplicitArgs(Vec<&'static str>);
+
+impl ExplicitArgs {
+    /// 记录命令行上明确给出的、受时间模板影响的选项。
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self(
+            TIMING_FIELDS
+                .iter()
+                .copied()
+                .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+                .collect(),
+        )
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.0.contains(&id)
+    }
 }
 
 #[cfg(not(tarpaulin_include))]
 impl Opts {
-    pub fn read() -> Self {
-        let mut opts = Opts::parse();
+    /// 读取命令行参数，同时返回明确给出的选项，合并配置文件时传给 [`Opts::merge`]。
+    pub fn read() -> (Self, ExplicitArgs) {
+        let matches = Opts::command().get_matches();
+        let mut opts = Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let explicit = ExplicitArgs::from_matches(&matches);
 
         if opts.ports.is_none() && opts.range.is_none() {
             opts.range = Some(PortRange {
@@ -172,15 +486,59 @@ impl Opts {
             });
         }
 
-        opts
+        (opts, explicit)
+    }
+
+    /// 主机名解析出多个地址时扫描哪些地址。
+    pub fn address_family(&self) -> AddressFamily {
+        if self.ipv4 {
+            AddressFamily::V4
+        } else if self.ipv6 {
+            AddressFamily::V6
+        } else if self.all_addresses {
+            AddressFamily::All
+        } else {
+            AddressFamily::First
+        }
     }
 
-    /// 将命令行参数读取到 Opts 结构中，并合并在用户配置文件中找到的值。
-    pub fn merge(&mut self, config: &Config) {
+    /// 按时间模板设置没有在命令行上明确给出的选项，配置文件中的值同样会被模板覆盖。
+    /// 模板没有设置的可选项（例如 `normal` 的速度限制）保留原来的值。
+    fn apply_timing(&mut self, explicit: &ExplicitArgs) {
+        let Some(timing) = self.timing else {
+            return;
+        };
+        let profile = timing.profile();
+        let explicit = |id: &str| explicit.contains(id);
+
+        if !explicit("batch_size") {
+            self.batch_size = profile.batch_size;
+        }
+        if !explicit("timeout") {
+            self.timeout = profile.timeout;
+        }
+        if !explicit("tries") {
+            self.tries = profile.tries;
+        }
+        if !explicit("ulimit") {
+            self.ulimit = profile.ulimit.or(self.ulimit);
+        }
+        if !explicit("max_rate") {
+            self.max_rate = profile.max_rate.or(self.max_rate);
+        }
+        if !explicit("scan_delay") {
+            self.scan_delay = profile.scan_delay.or(self.scan_delay);
+        }
+    }
+
+    /// 将命令行参数读取到 Opts 结构中，并合并在用户配置文件中找到的值，最后应用时间模板。
+    /// `explicit` 中的选项不被时间模板覆盖，不是从命令行读取的 Opts 可以传入默认值。
+    pub fn merge(&mut self, config: &Config, explicit: &ExplicitArgs) {
         if !self.no_config {
             self.merge_required(config);
             self.merge_optional(config);
         }
+        self.apply_timing(explicit);
     }
 
     fn merge_required(&mut self, config: &Config) {
@@ -195,8 +553,30 @@ impl Opts {
         }
 
         merge_required!(
-            addresses, greppable, accessible, batch_size, timeout, tries, scan_order, scripts,
-            command, udp, no_banner
+            addresses,
+            greppable,
+            accessible,
+            batch_size,
+            timeout,
+            tries,
+            scan_order,
+            scripts,
+            command,
+            udp,
+            no_banner,
+            banner,
+            banner_timeout,
+            service_detection,
+            version_intensity,
+            tls,
+            http,
+            discover,
+            syn,
+            linger_zero,
+            skip_tarpits,
+            verify,
+            reverse_dns,
+            progress
         );
     }
 
@@ -211,12 +591,40 @@ impl Opts {
             }
         }
 
+        // 命令行上的时间模板优先于配置文件中的
+        self.timing = self.timing.or(config.timing);
+
         // 仅当用户要求时才使用 top 端口
         if self.top && config.ports.is_some() {
             self.ports = config.ports.clone();
         }
 
-        merge_optional!(range, resolver, ulimit, exclude_ports, exclude_addresses);
+        merge_optional!(
+            range,
+            resolver,
+            ulimit,
+            exclude_ports,
+            exclude_addresses,
+            banner_probe,
+            service_probes,
+            http_host,
+            tarpit_threshold,
+            tarpit_sample,
+            verify_timeout,
+            verify_concurrency,
+            ping_ports,
+            max_scan_time,
+            host_timeout,
+            max_rate,
+            scan_delay,
+            max_host_concurrency,
+            max_host_rate,
+            retry_backoff,
+            retry_jitter,
+            source_ip,
+            interface,
+            source_port
+        );
     }
 }
 
@@ -234,6 +642,10 @@ impl Default for Opts {
             command: vec![],
             accessible: false,
             resolver: None,
+            ipv4: false,
+            ipv6: false,
+            all_addresses: false,
+            reverse_dns: false,
             scan_order: ScanOrder::Serial,
             no_config: true,
             no_banner: false,
@@ -243,6 +655,42 @@ impl Default for Opts {
             exclude_ports: None,
             exclude_addresses: None,
             udp: false,
+            banner: false,
+            banner_timeout: 1_000,
+            banner_probe: None,
+            service_detection: false,
+            service_probes: None,
+            version_intensity: 0,
+            tls: false,
+            http: false,
+            http_host: None,
+            tarpit_threshold: None,
+            tarpit_sample: None,
+            skip_tarpits: false,
+            verify: false,
+            verify_timeout: None,
+            verify_concurrency: None,
+            discover: false,
+            ping_only: false,
+            ping_ports: None,
+            syn: false,
+            linger_zero: false,
+            proxy: None,
+            max_scan_time: None,
+            host_timeout: None,
+            progress: ProgressMode::Off,
+            timing: None,
+            max_rate: None,
+            scan_delay: None,
+            max_host_concurrency: None,
+            max_host_rate: None,
+            retry_backoff: None,
+            retry_jitter: None,
+            source_ip: None,
+            interface: None,
+            source_port: None,
+            #[cfg(feature = "tui")]
+            tui: false,
         }
     }
 }
@@ -269,11 +717,43 @@ pub struct Config {
     exclude_addresses: Option<Vec<String>>,
     udp: Option<bool>,
     no_banner: Option<bool>,
+    banner: Option<bool>,
+    banner_timeout: Option<u32>,
+    banner_probe: Option<String>,
+    service_detection: Option<bool>,
+    service_probes: Option<PathBuf>,
+    version_intensity: Option<u8>,
+    tls: Option<bool>,
+    http: Option<bool>,
+    http_host: Option<String>,
+    tarpit_threshold: Option<u8>,
+    tarpit_sample: Option<usize>,
+    skip_tarpits: Option<bool>,
+    verify: Option<bool>,
+    verify_timeout: Option<u32>,
+    verify_concurrency: Option<usize>,
+    reverse_dns: Option<bool>,
+    discover: Option<bool>,
+    ping_ports: Option<Vec<u16>>,
+    syn: Option<bool>,
+    linger_zero: Option<bool>,
+    max_scan_time: Option<u64>,
+    host_timeout: Option<u64>,
+    progress: Option<ProgressMode>,
+    timing: Option<Timing>,
+    max_rate: Option<u32>,
+    scan_delay: Option<u64>,
+    max_host_concurrency: Option<u32>,
+    max_host_rate: Option<u32>,
+    retry_backoff: Option<u64>,
+    retry_jitter: Option<u8>,
+    source_ip: Option<Vec<IpAddr>>,
+    interface: Option<String>,
+    source_port: Option<u16>,
 }
 
 #[cfg(not(tarpaulin_include))]
 #[allow(clippy::doc_link_with_quotes)]
-#[allow(clippy::manual_unwrap_or_default)]
 impl Config {
     /// 读取 TOML 格式的配置文件并将其解析为 Config 结构。
     ///
@@ -285,60 +765,86 @@ impl Config {
     /// scan_order = "Serial"
     /// exclude_ports = [8080, 9090, 80]
     /// udp = false
+    /// banner = true
+    /// banner_probe = "\\r\\n"
+    /// service_detection = true
+    /// version_intensity = 7
+    /// http = true
+    /// http_host = "example.com"
+    /// tarpit_threshold = 80
+    /// tarpit_sample = 5
+    /// skip_tarpits = true
+    /// verify = true
+    /// verify_timeout = 5000
+    /// verify_concurrency = 16
+    /// reverse_dns = true
+    /// discover = true
+    /// ping_ports = [80, 443, 22]
+    /// max_scan_time = 600000
+    /// host_timeout = 60000
+    /// progress = "Ndjson"
+    /// timing = "Aggressive"
+    /// max_rate = 1000
+    /// scan_delay = 10
+    /// max_host_concurrency = 50
+    /// max_host_rate = 100
+    /// retry_backoff = 200
+    /// retry_jitter = 20
+    /// source_ip = ["192.0.2.10", "192.0.2.11"]
+    /// interface = "eth1"
+    /// source_port = 53
+    /// linger_zero = true
     ///
-    pub fn read(custom_config_path: Option<PathBuf>) -> Self {
-        let mut content = String::new();
-        let config_path = custom_config_path.unwrap_or_else(|| {
-            let path = default_config_path();
-            match path.exists() {
-                true => path,
-                false => old_default_config_path(),
+    pub fn read(custom_config_path: Option<PathBuf>) -> Result<Self, Error> {
+        let config_path = match custom_config_path {
+            Some(path) => path,
+            None => {
+                let path = default_config_path()?;
+                match path.exists() {
+                    true => path,
+                    false => old_default_config_path()?,
+                }
             }
-        });
+        };
 
+        let mut content = String::new();
         if config_path.exists() {
-            content = match fs::read_to_string(config_path) {
-                Ok(content) => content,
-                Err(_) => String::new(),
-            }
+            content = fs::read_to_string(&config_path).map_err(|source| Error::ConfigRead {
+                path: config_path.clone(),
+                source,
+            })?;
         }
 
-        let config: Config = match toml::from_str(&content) {
-            Ok(config) => config,
-            Err(e) => {
-                println!("Found {e} in configuration file.\nAborting scan.\n");
-                std::process::exit(1);
-            }
-        };
-
-        config
+        toml::from_str(&content).map_err(|source| Error::ConfigParse {
+            path: config_path,
+            source,
+        })
     }
 }
 
 /// 构造 config toml 的默认路径
-pub fn default_config_path() -> PathBuf {
-    let Some(mut config_path) = dirs::config_dir() else {
-        panic!("Could not infer config file path.");
-    };
+pub fn default_config_path() -> Result<PathBuf, Error> {
+    let mut config_path = dirs::config_dir().ok_or(Error::NoConfigPath("config file"))?;
     config_path.push(".rustscan.toml");
-    config_path
+    Ok(config_path)
 }
 
 /// 返回用于向后兼容的已弃用的主目录配置路径。
-pub fn old_default_config_path() -> PathBuf {
-    let Some(mut config_path) = dirs::home_dir() else {
-        panic!("Could not infer config file path.");
-    };
+pub fn old_default_config_path() -> Result<PathBuf, Error> {
+    let mut config_path = dirs::home_dir().ok_or(Error::NoConfigPath("config file"))?;
     config_path.push(".rustscan.toml");
-    config_path
+    Ok(config_path)
 }
 
 #[cfg(test)]
 mod tests {
-    use clap::{CommandFactory, Parser};
+    use clap::{CommandFactory, FromArgMatches, Parser};
     use parameterized::parameterized;
 
-    use super::{Config, Opts, PortRange, ScanOrder, ScriptsRequired};
+    use super::{
+        Config, ExplicitArgs, Opts, PortRange, ProgressMode, ScanOrder, ScriptsRequired, Timing,
+    };
+    use crate::Error;
 
     impl Config {
         fn default() -> Self {
@@ -360,6 +866,39 @@ mod tests {
                 exclude_addresses: None,
                 udp: Some(false),
                 no_banner: None,
+                banner: Some(true),
+                banner_timeout: Some(500),
+                banner_probe: None,
+                service_detection: Some(true),
+                service_probes: None,
+                version_intensity: Some(9),
+                tls: Some(true),
+                http: Some(true),
+                http_host: None,
+                tarpit_threshold: Some(80),
+                tarpit_sample: None,
+                skip_tarpits: Some(true),
+                verify: Some(true),
+                verify_timeout: None,
+                verify_concurrency: None,
+                reverse_dns: Some(true),
+                discover: Some(true),
+                ping_ports: None,
+                syn: Some(true),
+                linger_zero: Some(true),
+                max_scan_time: None,
+                host_timeout: None,
+                progress: Some(ProgressMode::Ndjson),
+                timing: None,
+                max_rate: Some(1_000),
+                scan_delay: None,
+                max_host_concurrency: Some(50),
+                max_host_rate: None,
+                retry_backoff: Some(200),
+                retry_jitter: None,
+                source_ip: Some(vec!["192.0.2.10".parse().unwrap()]),
+                interface: None,
+                source_port: None,
             }
         }
     }
@@ -389,12 +928,18 @@ mod tests {
         assert_eq!(command, opts.command);
     }
 
+    #[test]
+    fn default_banner_timeout_matches_cli() {
+        let cli = Opts::parse_from(["rustscan", "-a", "127.0.0.1"]);
+        assert_eq!(Opts::default().banner_timeout, cli.banner_timeout);
+    }
+
     #[test]
     fn opts_no_merge_when_config_is_ignored() {
         let mut opts = Opts::default();
         let config = Config::default();
 
-        opts.merge(&config);
+        opts.merge(&config, &ExplicitArgs::default());
 
         assert_eq!(opts.addresses, vec![] as Vec<String>);
         assert!(opts.greppable);
@@ -418,6 +963,19 @@ mod tests {
         assert_eq!(opts.accessible, config.accessible.unwrap());
         assert_eq!(opts.scan_order, config.scan_order.unwrap());
         assert_eq!(opts.scripts, ScriptsRequired::Default);
+        assert_eq!(opts.banner, config.banner.unwrap());
+        assert_eq!(opts.banner_timeout, config.banner_timeout.unwrap());
+        assert_eq!(opts.service_detection, config.service_detection.unwrap());
+        assert_eq!(opts.version_intensity, config.version_intensity.unwrap());
+        assert_eq!(opts.tls, config.tls.unwrap());
+        assert_eq!(opts.http, config.http.unwrap());
+        assert_eq!(opts.discover, config.discover.unwrap());
+        assert_eq!(opts.syn, config.syn.unwrap());
+        assert_eq!(opts.linger_zero, config.linger_zero.unwrap());
+        assert_eq!(opts.skip_tarpits, config.skip_tarpits.unwrap());
+        assert_eq!(opts.verify, config.verify.unwrap());
+        assert_eq!(opts.reverse_dns, config.reverse_dns.unwrap());
+        assert_eq!(opts.progress, config.progress.unwrap());
     }
 
     #[test]
@@ -430,11 +988,117 @@ mod tests {
         });
         config.ulimit = Some(1_000);
         config.resolver = Some("1.1.1.1".to_owned());
+        config.max_scan_time = Some(60_000);
+        config.tarpit_sample = Some(5);
+        config.verify_timeout = Some(5_000);
 
         opts.merge_optional(&config);
 
         assert_eq!(opts.range, config.range);
         assert_eq!(opts.ulimit, config.ulimit);
         assert_eq!(opts.resolver, config.resolver);
+        assert_eq!(opts.max_scan_time, config.max_scan_time);
+        assert_eq!(opts.max_rate, config.max_rate);
+        assert_eq!(opts.retry_backoff, config.retry_backoff);
+        assert_eq!(opts.source_ip, config.source_ip);
+        assert_eq!(opts.max_host_concurrency, config.max_host_concurrency);
+        assert_eq!(opts.tarpit_sample, config.tarpit_sample);
+        assert_eq!(opts.verify_timeout, config.verify_timeout);
+    }
+
+    fn parse_with_timing(args: &[&str]) -> Opts {
+        let matches = Opts::command().try_get_matches_from(args).unwrap();
+        let mut opts = Opts::from_arg_matches(&matches).unwrap();
+        opts.apply_timing(&ExplicitArgs::from_matches(&matches));
+        opts
+    }
+
+    #[test]
+    fn timing_template_sets_defaults() {
+        let opts = parse_with_timing(&["rustscan", "-a", "127.0.0.1", "-T4"]);
+        assert_eq!(opts.timing, Some(Timing::Aggressive));
+        assert_eq!(opts.batch_size, 10_000);
+        assert_eq!(opts.timeout, 1_000);
+        assert_eq!(opts.ulimit, Some(15_000));
+
+        let opts = parse_with_timing(&["rustscan", "-a", "127.0.0.1", "--timing", "polite"]);
+        assert_eq!(opts.max_rate, Some(100));
+        assert_eq!(opts.tries, 2);
+
+        // normal 和没有模板时一样
+        let normal = parse_with_timing(&["rustscan", "-a", "127.0.0.1", "-T3"]);
+        let none = parse_with_timing(&["rustscan", "-a", "127.0.0.1"]);
+        assert_eq!(
+            (
+                normal.batch_size,
+                normal.timeout,
+                normal.tries,
+                normal.ulimit
+            ),
+            (none.batch_size, none.timeout, none.tries, none.ulimit)
+        );
+    }
+
+    #[test]
+    fn explicit_flags_override_timing_template() {
+        let opts = parse_with_timing(&[
+            "rustscan",
+            "-a",
+            "127.0.0.1",
+            "-T1",
+            "-b",
+            "50",
+            "-t",
+            "100",
+            "--scan-delay",
+            "5",
+        ]);
+        assert_eq!(opts.batch_size, 50);
+        assert_eq!(opts.timeout, 100);
+        assert_eq!(opts.tries, 2);
+        assert_eq!(opts.scan_delay, Some(5));
+    }
+
+    #[test]
+    fn timing_template_is_applied_after_config() {
+        let mut config = Config::default();
+        config.batch_size = Some(25_000);
+        config.timeout = Some(1_000);
+
+        // 命令行上的模板覆盖配置文件中的值
+        let matches = Opts::command()
+            .try_get_matches_from(["rustscan", "-a", "127.0.0.1", "-T5", "-t", "300"])
+            .unwrap();
+        let mut opts = Opts::from_arg_matches(&matches).unwrap();
+        opts.no_config = false;
+        opts.merge(&config, &ExplicitArgs::from_matches(&matches));
+        assert_eq!(opts.batch_size, 65_535);
+        // 只有命令行上明确给出的选项优先于模板
+        assert_eq!(opts.timeout, 1_000);
+
+        // 配置文件也可以设置模板，命令行上的模板优先
+        config.timing = Some(Timing::Polite);
+        let mut opts = parse_with_timing(&["rustscan", "-a", "127.0.0.1"]);
+        opts.no_config = false;
+        opts.merge(&config, &ExplicitArgs::default());
+        assert_eq!(opts.timing, Some(Timing::Polite));
+        assert_eq!(opts.max_rate, Some(100));
+        let mut opts = parse_with_timing(&["rustscan", "-a", "127.0.0.1", "-T4"]);
+        opts.no_config = false;
+        opts.merge(&config, &ExplicitArgs::default());
+        assert_eq!(opts.timing, Some(Timing::Aggressive));
+    }
+
+    #[test]
+    fn config_read_reports_errors() {
+        assert!(Config::read(Some("fixtures/does_not_exist.toml".into())).is_ok());
+        assert!(matches!(
+            Config::read(Some("fixtures/invalid_config.toml".into())),
+            Err(Error::ConfigParse { .. })
+        ));
+        assert!(matches!(
+            Config::read(Some("fixtures".into())),
+            Err(Error::ConfigRead { .. })
+        ));
     }
 }
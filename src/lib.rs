@@ -2,7 +2,8 @@
 //!
 //! ## 示例：对 localhost 进行扫描
 //!
-//! 核心扫描行为由 [`Scanner`](crate::scanner::Scanner) 管理，它反过来需要一个
+//! 核心扫描行为由 [`Scanner`](crate::scanner::Scanner) 管理，它通过
+//! [`Scanner::builder`](crate::scanner::Scanner::builder) 创建，并需要一个
 //! [`PortStrategy`](crate::port_strategy::PortStrategy)：
 //!
 //! ```rust
@@ -20,19 +21,20 @@
 //!         end: 1_000,
 //!     };
 //!     let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random); // 可以是顺序的、随机的或手动的 https://github.com/RustScan/RustScan/blob/master/src/port_strategy/mod.rs
-//!     let scanner = Scanner::new(
-//!         &addrs, // 要扫描的地址
-//!         10, // batch_size 是一次扫描多少个端口
-//!         Duration::from_millis(100), // Timeout 是 RustScan 在声明端口关闭之前等待的时间。数据类型为 Duration。
-//!         1, // Tries，RustScan 应该重试多少次？
-//!         true, // greppable 是 RustScan 是否应该打印内容，或者等到最后只打印 ip
-//!         strategy, // 使用的端口策略
-//!         true, // accessible，输出是否应该符合 A11Y 标准？
-//!         vec![9000], // RustScan 应该排除哪些端口？
-//!         false, // 这是 UDP 扫描吗？
-//!     );
+//!     let scanner = Scanner::builder()
+//!         .ips(addrs) // 要扫描的地址
+//!         .batch_size(10) // batch_size 是一次扫描多少个端口
+//!         .timeout(Duration::from_millis(100)) // Timeout 是 RustScan 在声明端口关闭之前等待的时间。数据类型为 Duration。
+//!         .tries(1) // Tries，RustScan 应该重试多少次？
+//!         .greppable(true) // greppable 是 RustScan 是否应该打印内容，或者等到最后只打印 ip
+//!         .port_strategy(strategy) // 使用的端口策略
+//!         .accessible(true) // accessible，输出是否应该符合 A11Y 标准？
+//!         .exclude_ports(vec![9000]) // RustScan 应该排除哪些端口？
+//!         .udp(false) // 这是 UDP 扫描吗？
+//!         .build()
+//!         .expect("invalid scanner settings");
 //!
-//!     let scan_result = block_on(scanner.run());
+//!     let scan_result = block_on(scanner.run()).expect("scan failed");
 //!
 //!     println!("{:?}", scan_result);
 //! }
@@ -43,6 +45,13 @@ pub mod tui;
 
 pub mod input;
 
+pub mod error;
+pub use error::{Error, Result};
+
+pub mod event;
+
+pub mod progress;
+
 pub mod scanner;
 
 pub mod port_strategy;
@@ -53,4 +62,18 @@ pub mod scripts;
 
 pub mod address;
 
+pub mod discovery;
+
+pub mod report;
+
+pub mod service;
+
+pub mod tls;
+
+pub mod http;
+
+pub mod tarpit;
+
+pub mod rdns;
+
 pub mod generated;
@@ -3,20 +3,36 @@
 #![allow(clippy::doc_markdown, clippy::if_not_else, clippy::non_ascii_literal)]
 
 use rustscan::benchmark::{Benchmark, NamedTimer};
-use rustscan::input::{self, Config, Opts, ScriptsRequired};
+use rustscan::discovery::{icmp_permitted, Discovery, DEFAULT_PING_PORTS};
+use rustscan::event::{CliPrinter, EventSink};
+use rustscan::http::HttpProber;
+use rustscan::input::{self, Config, Opts, ProgressMode, ScriptsRequired};
 use rustscan::port_strategy::PortStrategy;
-use rustscan::scanner::Scanner;
+use rustscan::progress::{ProgressPrinter, ProgressStyle};
+use rustscan::rdns::ReverseDns;
+use rustscan::report::{annotate, group_outcome, HostReport};
+#[cfg(target_os = "linux")]
+use rustscan::scanner::SynScanner;
+use rustscan::scanner::{
+    parse_banner_probe, BannerOptions, RetryBackoff, ScanControl, ScanStatus, Scanner,
+    SourceOptions,
+};
 use rustscan::scripts::{init_scripts, Script, ScriptFile};
+use rustscan::service::{load_probes, Protocol, ServiceDetector};
+use rustscan::tarpit::TarpitDetector;
+use rustscan::tls::TlsProber;
+#[cfg(feature = "tui")]
+use rustscan::tui::dashboard::Dashboard;
 use rustscan::{detail, funny_opening, output, warning};
 
 use colorful::{Color, Colorful};
 use futures::executor::block_on;
-use std::collections::HashMap;
-use std::net::IpAddr;
-use std::string::ToString;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 
-use rustscan::address::parse_addresses;
+use rustscan::address::{parse_targets, resolver_config, Target};
 
 extern crate colorful;
 extern crate dirs;
@@ -45,14 +61,42 @@ fn main() {
     let mut rustscan_bench = NamedTimer::start("RustScan");
 
     // 读取命令行参数
-    let mut opts: Opts = Opts::read();
+    let (mut opts, explicit) = Opts::read();
     // 读取配置文件
-    let config = Config::read(opts.config_path.clone());
+    let config = match Config::read(opts.config_path.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            warning!(
+                format!("{e}\nAborting scan."),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    };
     // 将配置文件中的选项合并到命令行参数中
-    opts.merge(&config);
+    opts.merge(&config, &explicit);
 
     debug!("Main() `opts` arguments are {opts:?}");
 
+    // 这些阶段会绕过代理直接连接目标
+    if opts.proxy.is_some()
+        && (opts.udp
+            || opts.syn
+            || opts.discover
+            || opts.ping_only
+            || opts.service_detection
+            || opts.tls
+            || opts.http)
+    {
+        warning!(
+            "--proxy only supports TCP connect scans and banner grabs, remove --udp, --syn, --discover, --ping-only, --service-detection, --tls and --http.",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+
     // 初始化脚本
     let scripts_to_run: Vec<ScriptFile> = match init_scripts(&opts.scripts) {
         Ok(scripts_to_run) => scripts_to_run,
@@ -73,8 +117,15 @@ fn main() {
         print_opening(&opts);
     }
 
-    // 解析目标 IP 地址
-    let ips: Vec<IpAddr> = parse_addresses(&opts);
+    // 解析目标 IP 地址，保留它们来自的主机名
+    let mut targets: Vec<Target> = match parse_targets(&opts) {
+        Ok(targets) => targets,
+        Err(e) => {
+            warning!(format!("{e}"), opts.greppable, opts.accessible);
+            std::process::exit(1);
+        }
+    };
+    let mut ips: Vec<IpAddr> = targets.iter().map(|target| target.ip).collect();
 
     if ips.is_empty() {
         warning!(
@@ -92,50 +143,416 @@ fn main() {
     #[cfg(not(unix))]
     let batch_size: usize = AVERAGE_BATCH_SIZE;
 
+    let source = SourceOptions {
+        ips: opts.source_ip.clone().unwrap_or_default(),
+        port: opts.source_port,
+        interface: opts.interface.clone(),
+        linger_zero: opts.linger_zero,
+    };
+    #[cfg(not(target_os = "linux"))]
+    if source.interface.is_some() {
+        warning!(
+            "--interface is only supported on Linux.",
+            opts.greppable,
+            opts.accessible
+        );
+        std::process::exit(1);
+    }
+
+    // 主机发现，只有存活的主机才进入端口扫描
+    if opts.discover || opts.ping_only {
+        let mut discovery_bench = NamedTimer::start("Discovery");
+        if !ips.iter().any(|ip| icmp_permitted(*ip)) {
+            detail!(
+                "ICMP echo is not permitted, using TCP connect pings only.",
+                opts.greppable,
+                opts.accessible
+            );
+        }
+        let ports = opts
+            .ping_ports
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PING_PORTS.to_vec());
+        let discovery = Discovery::new(Duration::from_millis(opts.timeout.into()), ports)
+            .with_concurrency(batch_size)
+            .with_source(source.clone());
+        let alive = block_on(discovery.run(&ips));
+        discovery_bench.end();
+        benchmarks.push(discovery_bench);
+
+        if opts.ping_only {
+            let by_ip: HashMap<IpAddr, &Target> =
+                targets.iter().map(|target| (target.ip, target)).collect();
+            for (ip, method) in &alive {
+                if opts.greppable {
+                    println!("{ip}");
+                } else {
+                    output!(
+                        format!("Host {} is up ({method})", by_ip[ip]),
+                        false,
+                        opts.accessible
+                    );
+                }
+            }
+            rustscan_bench.end();
+            benchmarks.push(rustscan_bench);
+            info!("{}", benchmarks.summary());
+            return;
+        }
+
+        detail!(
+            format!("{} of {} hosts are up.", alive.len(), ips.len()),
+            opts.greppable,
+            opts.accessible
+        );
+        ips = alive.into_iter().map(|(ip, _)| ip).collect();
+        let up: HashSet<IpAddr> = ips.iter().copied().collect();
+        targets.retain(|target| up.contains(&target.ip));
+        if ips.is_empty() {
+            warning!(
+                "No hosts are up, aborting scan.",
+                opts.greppable,
+                opts.accessible
+            );
+            return;
+        }
+    }
+
+    // SYN 扫描不为每个端口占用文件描述符，批处理大小不受 ulimit 限制
+    #[cfg(target_os = "linux")]
+    let syn = if opts.syn && !opts.udp {
+        match SynScanner::with_source(&source) {
+            Ok(syn) => Some(syn),
+            Err(e) => {
+                warning!(
+                    format!(
+                        "SYN scan needs root or CAP_NET_RAW ({e}), falling back to connect scan."
+                    ),
+                    opts.greppable,
+                    opts.accessible
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg(target_os = "linux")]
+    let scan_batch_size = if syn.is_some() {
+        opts.batch_size
+    } else {
+        batch_size
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    if opts.syn {
+        warning!(
+            "SYN scan is only supported on Linux, falling back to connect scan.",
+            opts.greppable,
+            opts.accessible
+        );
+    }
+    #[cfg(not(target_os = "linux"))]
+    let scan_batch_size = batch_size;
+
+    let port_strategy = PortStrategy::pick(&opts.range, opts.ports.clone(), opts.scan_order);
+    let exclude_ports = opts.exclude_ports.clone().unwrap_or_default();
+    // 每个主机扫描的端口数，用来计算开放端口的比例
+    let scanned_ports = port_strategy
+        .order()
+        .iter()
+        .filter(|port| !exclude_ports.contains(port))
+        .count();
+
     // 创建扫描器实例
-    let scanner = Scanner::new(
-        &ips,
-        batch_size,
-        Duration::from_millis(opts.timeout.into()),
-        opts.tries,
-        opts.greppable,
-        PortStrategy::pick(&opts.range, opts.ports, opts.scan_order),
-        opts.accessible,
-        opts.exclude_ports.unwrap_or_default(),
-        opts.udp,
-    );
+    let mut builder = Scanner::builder()
+        .ips(ips.clone())
+        .batch_size(scan_batch_size)
+        .timeout(Duration::from_millis(opts.timeout.into()))
+        .tries(opts.tries.max(1))
+        .greppable(opts.greppable)
+        .events(scan_events(&opts))
+        .port_strategy(port_strategy)
+        .accessible(opts.accessible)
+        .exclude_ports(exclude_ports)
+        .udp(opts.udp);
+    if opts.banner {
+        let probe = opts
+            .banner_probe
+            .as_deref()
+            .map(parse_banner_probe)
+            .unwrap_or_default();
+        builder = builder.banner(BannerOptions::new(
+            Duration::from_millis(opts.banner_timeout.into()),
+            probe,
+        ));
+    }
+    if let Some(max_scan_time) = opts.max_scan_time {
+        builder = builder.max_scan_time(Duration::from_millis(max_scan_time));
+    }
+    if let Some(host_timeout) = opts.host_timeout {
+        builder = builder.host_timeout(Duration::from_millis(host_timeout));
+    }
+    if let Some(scan_delay) = opts.scan_delay {
+        builder = builder.scan_delay(Duration::from_millis(scan_delay));
+    }
+    if let Some(max_host_concurrency) = opts.max_host_concurrency {
+        builder = builder.max_host_concurrency(max_host_concurrency as usize);
+    }
+    if let Some(max_host_rate) = opts.max_host_rate {
+        builder = builder.max_host_rate(max_host_rate);
+    }
+    if opts.retry_backoff.is_some() || opts.retry_jitter.is_some() {
+        let default = RetryBackoff::default();
+        builder = builder.retry_backoff(RetryBackoff::new(
+            opts.retry_backoff
+                .map_or(default.base, Duration::from_millis),
+            opts.retry_jitter.unwrap_or(default.jitter),
+        ));
+    }
+    if opts.verify {
+        let timeout = opts
+            .verify_timeout
+            .map_or(u64::from(opts.timeout) * 3, u64::from);
+        builder = builder.verify(Duration::from_millis(timeout));
+        if let Some(concurrency) = opts.verify_concurrency {
+            builder = builder.verify_concurrency(concurrency);
+        }
+    }
+    if !source.is_default() {
+        builder = builder.source(source.clone());
+    }
+    if let Some(proxy) = opts.proxy {
+        builder = builder.proxy(proxy);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(syn) = syn {
+        builder = builder.syn(syn);
+    }
+    // 收到 SIGUSR1 时暂停扫描，收到 SIGUSR2 时恢复
+    let control = ScanControl::new();
+    control.set_rate(opts.max_rate);
+    #[cfg(unix)]
+    if let Err(e) = control.listen_for_signals() {
+        debug!("Could not listen for pause and resume signals: {e}");
+    }
+    builder = builder.control(control.clone());
+    // 交互界面接管扫描事件，并通过控制句柄暂停、调速或者停止扫描
+    #[cfg(feature = "tui")]
+    let dashboard = if opts.tui {
+        let dashboard = Dashboard::new(control);
+        builder = builder
+            .events(dashboard.events())
+            .cancel_handle(dashboard.cancel_handle());
+        Some(dashboard)
+    } else {
+        None
+    };
+    let scanner = match builder.build() {
+        Ok(scanner) => scanner,
+        Err(e) => {
+            warning!(
+                format!("Invalid scan settings: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    };
     debug!("Scanner finished building: {scanner:?}");
 
+    #[cfg(feature = "tui")]
+    let dashboard = match dashboard.map(Dashboard::start).transpose() {
+        Ok(dashboard) => dashboard,
+        Err(e) => {
+            warning!(
+                format!("Could not start the interactive UI: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    };
+
     // 开始端口扫描基准测试计时
     let mut portscan_bench = NamedTimer::start("Portscan");
     // 运行扫描器并等待结果
-    let scan_result = block_on(scanner.run());
+    let outcome = block_on(scanner.run_detailed());
+    // 先恢复终端，之后的输出照常打印
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        if let Err(e) = dashboard.finish() {
+            warning!(
+                format!("The interactive UI failed: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            warning!(
+                format!("Scan aborted: {e}"),
+                opts.greppable,
+                opts.accessible
+            );
+            std::process::exit(1);
+        }
+    };
     portscan_bench.end();
     benchmarks.push(portscan_bench);
 
-    // 用于存储每个 IP 对应的开放端口列表
-    let mut ports_per_ip = HashMap::new();
+    if outcome.status != ScanStatus::Complete {
+        warning!(
+            format!(
+                "Scan {} before all ports were scanned, results are partial.",
+                outcome.status
+            ),
+            opts.greppable,
+            opts.accessible
+        );
+        if outcome.status == ScanStatus::TooManyOpenFiles {
+            warning!(
+                rustscan::Error::TooManyOpenFiles.to_string(),
+                opts.greppable,
+                opts.accessible
+            );
+        }
+    }
+
+    // 整理扫描结果，按 IP 分组，没有扫描完的主机即使没有开放端口也会保留下来以输出状态
+    let mut hosts = group_outcome(outcome, &targets);
+
+    // 反向 DNS，只查询发现了开放端口的主机
+    if opts.reverse_dns {
+        let mut rdns_bench = NamedTimer::start("Reverse DNS");
+        let (config, options) = resolver_config(&opts.resolver);
+        match ReverseDns::new(config, options, Duration::from_millis(opts.timeout.into())) {
+            Ok(rdns) => {
+                let ips: Vec<IpAddr> = hosts
+                    .iter()
+                    .filter(|host| !host.ports.is_empty())
+                    .map(|host| host.ip)
+                    .collect();
+                let mut names = block_on(rdns.lookup(ips));
+                for host in &mut hosts {
+                    host.ptr_names = names.remove(&host.ip).unwrap_or_default();
+                }
+            }
+            Err(e) => {
+                warning!(
+                    format!("Reverse DNS failed!\n{e}"),
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+        }
+        rdns_bench.end();
+        benchmarks.push(rdns_bench);
+    }
+
+    // 识别接受所有连接的主机；只有要求抽样时才建立连接，UDP 扫描和经过代理时只按开放端口的比例判断
+    let mut detector = TarpitDetector::new(Duration::from_millis(opts.timeout.into()))
+        .with_concurrency(batch_size)
+        .with_source(source.clone());
+    if let Some(threshold) = opts.tarpit_threshold {
+        detector = detector.with_threshold(threshold);
+    }
+    if let Some(sample) = opts
+        .tarpit_sample
+        .filter(|_| !opts.udp && opts.proxy.is_none())
+    {
+        detector = detector.with_sample(sample);
+    }
+    let tarpits = block_on(detector.detect(&hosts, scanned_ports));
+    for host in &mut hosts {
+        host.tarpit = tarpits.get(&host.ip).copied();
+    }
+
+    // 不依赖 nmap 的服务识别
+    if opts.service_detection {
+        let mut service_bench = NamedTimer::start("Services");
+        match load_probes(opts.service_probes.as_deref()) {
+            Ok(probes) => {
+                let detector = ServiceDetector::new(
+                    probes,
+                    Duration::from_millis(opts.timeout.into()),
+                    opts.version_intensity,
+                )
+                .with_concurrency(batch_size)
+                .with_source(source.clone());
+                let sockets: Vec<SocketAddr> = hosts.iter().flat_map(HostReport::sockets).collect();
+                let protocol = if opts.udp {
+                    Protocol::Udp
+                } else {
+                    Protocol::Tcp
+                };
+                let services = block_on(detector.detect(&sockets, protocol));
+                annotate(&mut hosts, services, |port, service| {
+                    port.service = Some(service);
+                });
+            }
+            Err(e) => {
+                warning!(
+                    format!("Service detection failed!\n{e:#}"),
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+        }
+        service_bench.end();
+        benchmarks.push(service_bench);
+    }
+
+    // TLS 握手探测，UDP 扫描没有 TLS 可言
+    if opts.tls && !opts.udp {
+        let mut tls_bench = NamedTimer::start("TLS");
+        let prober = TlsProber::new(Duration::from_millis(opts.timeout.into()))
+            .with_concurrency(batch_size)
+            .with_source(source.clone());
+        let sockets: Vec<SocketAddr> = hosts.iter().flat_map(HostReport::sockets).collect();
+        let sessions = block_on(prober.probe(&sockets));
+        annotate(&mut hosts, sessions, |port, tls| port.tls = Some(tls));
+        tls_bench.end();
+        benchmarks.push(tls_bench);
+    }
 
-    // 整理扫描结果，按 IP 分组
-    for socket in scan_result {
-        ports_per_ip
-            .entry(socket.ip())
-            .or_insert_with(Vec::new)
-            .push(socket.port());
+    // HTTP(S) 探测
+    if opts.http && !opts.udp {
+        let mut http_bench = NamedTimer::start("HTTP");
+        let mut prober = HttpProber::new(Duration::from_millis(opts.timeout.into()))
+            .with_concurrency(batch_size)
+            .with_source(source.clone());
+        if let Some(host) = &opts.http_host {
+            prober = prober.with_host(host.clone());
+        }
+        let sockets: Vec<SocketAddr> = hosts.iter().flat_map(HostReport::sockets).collect();
+        let responses = block_on(prober.probe(&sockets));
+        annotate(&mut hosts, responses, |port, http| port.http = Some(http));
+        http_bench.end();
+        benchmarks.push(http_bench);
     }
 
+    // 没有扫描完或者只有未确认端口的主机也在 hosts 中，它们同样需要提示
+    let hosts_with_open_ports: HashSet<IpAddr> = hosts
+        .iter()
+        .filter(|host| !host.ports.is_empty())
+        .map(|host| host.ip)
+        .collect();
+
     // 检查是否有 IP 没有发现开放端口，并给出提示
-    for ip in ips {
-        if ports_per_ip.contains_key(&ip) {
+    for target in &targets {
+        if hosts_with_open_ports.contains(&target.ip) {
             continue;
         }
 
         // 如果执行到这里，说明在 HashMap 中没有找到该 IP，这意味着扫描没有发现该 IP 的任何开放端口。
 
-        let x = format!("Looks like I didn't find any open ports for {:?}. This is usually caused by a high batch size.
+        let x = format!("Looks like I didn't find any open ports for {}. This is usually caused by a high batch size.
         \n*I used {} batch size, consider lowering it with {} or a comfortable number for your system.
         \n Alternatively, increase the timeout if your ping is high. Rustscan -t 2000 for 2000 milliseconds (2s) timeout.\n",
-        ip,
+        target,
         opts.batch_size,
         "'rustscan -b <batch_size> -a <ip address>'");
         warning!(x, opts.greppable, opts.accessible);
@@ -143,17 +560,41 @@ fn main() {
 
     // 开始脚本执行基准测试计时
     let mut script_bench = NamedTimer::start("Scripts");
-    for (ip, ports) in &ports_per_ip {
-        let vec_str_ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
-
-        // nmap 端口样式是 80,443。逗号分隔，无空格。
-        let ports_str = vec_str_ports.join(",");
+    for host in &hosts {
+        let ip = &host.ip;
+        let ports = host.port_numbers();
 
         // 如果 scripts 选项为 none，则不生成任何脚本
-        if opts.greppable || opts.scripts == ScriptsRequired::None {
-            println!("{} -> [{}]", &ip, ports_str);
+        let skip_tarpit = opts.skip_tarpits && host.tarpit.is_some();
+        if opts.greppable
+            || opts.scripts == ScriptsRequired::None
+            || ports.is_empty()
+            || skip_tarpit
+        {
+            if skip_tarpit && !opts.greppable {
+                detail!(
+                    format!("Skipping scripts for {ip}, it looks like a tarpit."),
+                    opts.greppable,
+                    opts.accessible
+                );
+            }
+            // greppable 模式每个有开放端口或者没有扫描完的主机只输出一行，
+            // 附加信息只在给人看的输出中列出
+            if opts.greppable {
+                if !ports.is_empty() || host.status != ScanStatus::Complete {
+                    println!("{}", host.greppable());
+                }
+            } else {
+                println!("{}", host.summary());
+                for line in host.details() {
+                    println!("{line}");
+                }
+            }
             continue;
         }
+        for line in host.details() {
+            output!(line, opts.greppable, opts.accessible);
+        }
         detail!("Starting Script(s)", opts.greppable, opts.accessible);
 
         // 运行我们根据脚本配置文件 tags 字段找到并解析的所有脚本。
@@ -185,7 +626,9 @@ fn main() {
                 script_f.ports_separator,
                 script_f.tags,
                 script_f.call_format,
-            );
+            )
+            .with_http(host.http())
+            .with_hostname(host.hostname());
             match script.run() {
                 Ok(script_result) => {
                     detail!(script_result.clone(), opts.greppable, opts.accessible);
@@ -206,6 +649,22 @@ fn main() {
     info!("{}", benchmarks.summary());
 }
 
+/// 扫描期间的输出：开放端口照常打印到 stdout，按照 `--progress` 在 stderr 上显示进度。
+fn scan_events(opts: &Opts) -> Arc<dyn EventSink> {
+    let style = match opts.progress {
+        ProgressMode::Auto if opts.accessible => None,
+        ProgressMode::Auto => Some(ProgressStyle::detect()),
+        ProgressMode::Line => Some(ProgressStyle::Line),
+        ProgressMode::Ndjson => Some(ProgressStyle::Ndjson),
+        ProgressMode::Off => None,
+    };
+    let printer = CliPrinter::new(opts.greppable, opts.accessible);
+    match style {
+        Some(style) => Arc::new(ProgressPrinter::new(printer, style)),
+        None => Arc::new(printer),
+    }
+}
+
 /// Prints the opening title of RustScan
 #[allow(clippy::items_after_statements, clippy::needless_raw_string_hashes)]
 fn print_opening(opts: &Opts) {
@@ -227,16 +686,20 @@ The Modern Day Port Scanner."#;
     let config_path = opts
         .config_path
         .clone()
-        .unwrap_or_else(input::default_config_path);
+        .or_else(|| input::default_config_path().ok());
 
-    detail!(
-        format!("The config file is expected to be at {config_path:?}"),
-        opts.greppable,
-        opts.accessible
-    );
+    if let Some(config_path) = config_path {
+        detail!(
+            format!("The config file is expected to be at {config_path:?}"),
+            opts.greppable,
+            opts.accessible
+        );
+    }
 
     if opts.config_path.is_none() {
-        let old_config_path = input::old_default_config_path();
+        let Ok(old_config_path) = input::old_default_config_path() else {
+            return;
+        };
         detail!(
             format!(
                 "For backwards compatibility, the config file may also be at {old_config_path:?}"
@@ -0,0 +1,249 @@
+//! 扫描进度以及它在 stderr 上的显示。
+//!
+//! 扫描器每隔 [`PROGRESS_INTERVAL`] 发出一个 [`Event::Progress`]，扫描结束时再发出一个
+//! `finished` 为 `true` 的事件。[`ProgressPrinter`] 包裹另一个 [`EventSink`]，
+//! 在终端上把进度显示成一行不断刷新的状态，在 CI 等非终端环境下定期输出一行 NDJSON：
+//!
+//! ```text
+//! {"type":"progress","done":65535,"total":131070,"open":3,"errors":0,"rate":8123.4,"elapsed_secs":8.1,"eta_secs":8.1,"finished":false}
+//! ```
+//!
+//! 进度只写到 stderr，不会和 stdout 上的扫描结果混在一起。
+use crate::event::{Event, EventSink};
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 扫描器发出进度事件的间隔。
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// NDJSON 进度之间的最小间隔，避免日志被刷屏。
+const NDJSON_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 某一时刻的扫描进度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// 已经探测完的 socket 数
+    pub done: u64,
+    /// 需要探测的 socket 总数
+    pub total: u64,
+    /// 目前发现的开放端口数
+    pub open: u64,
+    /// 探测本身出错（而不是端口关闭或没有响应）的 socket 数
+    pub errors: u64,
+    /// 从扫描开始到现在的时间
+    pub elapsed: Duration,
+    /// 扫描已经结束，这是最后一个进度事件
+    pub finished: bool,
+}
+
+impl Progress {
+    /// 平均每秒探测的 socket 数。
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.done as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// 按照目前的平均速度估计的剩余时间，还没有完成任何探测时返回 `None`。
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.rate();
+        if rate > 0.0 {
+            let remaining = self.total.saturating_sub(self.done) as f64;
+            Some(Duration::from_secs_f64(remaining / rate))
+        } else {
+            None
+        }
+    }
+
+    /// 已经完成的百分比。
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.done as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    /// 单行 NDJSON 格式。
+    pub fn to_ndjson(&self) -> String {
+        let eta = self.eta().map_or_else(
+            || "null".to_owned(),
+            |eta| format!("{:.1}", eta.as_secs_f64()),
+        );
+        format!(
+            "{{\"type\":\"progress\",\"done\":{},\"total\":{},\"open\":{},\"errors\":{},\"rate\":{:.1},\"elapsed_secs\":{:.1},\"eta_secs\":{},\"finished\":{}}}",
+            self.done,
+            self.total,
+            self.open,
+            self.errors,
+            self.rate(),
+            self.elapsed.as_secs_f64(),
+            eta,
+            self.finished
+        )
+    }
+
+    /// 状态行格式，例如 `65535/131070 (50.0%) 8123/s, 3 open, ETA 0:08`。
+    pub fn to_line(&self) -> String {
+        let eta = self.eta().map_or_else(|| "?".to_owned(), format_duration);
+        format!(
+            "{}/{} ({:.1}%) {:.0}/s, {} open, ETA {}",
+            self.done,
+            self.total,
+            self.percent(),
+            self.rate(),
+            self.open,
+            eta
+        )
+    }
+}
+
+/// 格式化为 `m:ss` 或 `h:mm:ss`。
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// 进度的显示方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStyle {
+    /// 在 stderr 上不断刷新的一行状态
+    Line,
+    /// 定期向 stderr 输出一行 JSON
+    Ndjson,
+}
+
+impl ProgressStyle {
+    /// stderr 是终端时使用状态行，否则使用 NDJSON。
+    pub fn detect() -> Self {
+        if io::stderr().is_terminal() {
+            ProgressStyle::Line
+        } else {
+            ProgressStyle::Ndjson
+        }
+    }
+}
+
+/// 在 stderr 上显示 [`Event::Progress`]，其它事件交给 `inner`。
+///
+/// 使用状态行时，`inner` 打印之前会先清除状态行，打印之后再重新显示，避免输出混在一起。
+#[derive(Debug)]
+pub struct ProgressPrinter<S> {
+    inner: S,
+    style: ProgressStyle,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// 当前显示的状态行，清除之后为 `None`
+    line: Option<String>,
+    last_ndjson: Option<Instant>,
+}
+
+impl<S: EventSink> ProgressPrinter<S> {
+    pub fn new(inner: S, style: ProgressStyle) -> Self {
+        Self {
+            inner,
+            style,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn show(&self, state: &mut State, progress: &Progress) {
+        let mut stderr = io::stderr().lock();
+        match self.style {
+            ProgressStyle::Line => {
+                if progress.finished {
+                    let _ = write!(stderr, "\r\x1b[2K");
+                    state.line = None;
+                } else {
+                    let line = progress.to_line();
+                    let _ = write!(stderr, "\r\x1b[2K{line}");
+                    state.line = Some(line);
+                }
+            }
+            ProgressStyle::Ndjson => {
+                let due = state
+                    .last_ndjson
+                    .is_none_or(|last| last.elapsed() >= NDJSON_INTERVAL);
+                if due || progress.finished {
+                    let _ = writeln!(stderr, "{}", progress.to_ndjson());
+                    state.last_ndjson = Some(Instant::now());
+                }
+            }
+        }
+        let _ = stderr.flush();
+    }
+}
+
+impl<S: EventSink> EventSink for ProgressPrinter<S> {
+    fn event(&self, event: Event) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Event::Progress(progress) = event {
+            self.show(&mut state, &progress);
+            return;
+        }
+
+        let line = state.line.take();
+        if line.is_some() {
+            let _ = write!(io::stderr(), "\r\x1b[2K");
+        }
+        self.inner.event(event);
+        if let Some(line) = line {
+            let _ = write!(io::stderr(), "{line}");
+            state.line = Some(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_duration, Progress};
+    use std::time::Duration;
+
+    fn progress(done: u64, secs: u64) -> Progress {
+        Progress {
+            done,
+            total: 1_000,
+            open: 2,
+            errors: 0,
+            elapsed: Duration::from_secs(secs),
+            finished: false,
+        }
+    }
+
+    #[test]
+    fn rate_and_eta() {
+        let p = progress(250, 5);
+        assert!((p.rate() - 50.0).abs() < f64::EPSILON);
+        assert_eq!(p.eta(), Some(Duration::from_secs(15)));
+        assert_eq!(p.to_line(), "250/1000 (25.0%) 50/s, 2 open, ETA 0:15");
+
+        assert_eq!(progress(0, 0).eta(), None);
+    }
+
+    #[test]
+    fn ndjson_is_one_line() {
+        assert_eq!(
+            progress(250, 5).to_ndjson(),
+            r#"{"type":"progress","done":250,"total":1000,"open":2,"errors":0,"rate":50.0,"elapsed_secs":5.0,"eta_secs":15.0,"finished":false}"#
+        );
+        assert!(progress(0, 0).to_ndjson().contains("\"eta_secs\":null"));
+    }
+
+    #[test]
+    fn durations_are_formatted() {
+        assert_eq!(format_duration(Duration::from_secs(75)), "1:15");
+        assert_eq!(format_duration(Duration::from_secs(3_725)), "1:02:05");
+    }
+}
@@ -0,0 +1,202 @@
+//! 扫描之后的反向 DNS（PTR）查询阶段。
+//!
+//! 对发现了开放端口的主机查询 PTR 记录，解析器的配置和解析目标地址时相同（见
+//! [`resolver_config`](crate::address::resolver_config)，会使用 `--resolver`）。
+//! hickory 的异步解析器需要 tokio，查询在这里自带的单线程运行时上进行，
+//! 由 async-std 的阻塞线程池驱动，调用方可以在任何执行器中等待结果。
+//!
+//! ```rust
+//! # use rustscan::address::resolver_config;
+//! # use rustscan::rdns::ReverseDns;
+//! # use std::time::Duration;
+//! let (config, options) = resolver_config(&None);
+//! let rdns = ReverseDns::new(config, options, Duration::from_millis(1_500))
+//!     .unwrap()
+//!     .with_concurrency(16);
+//! ```
+use crate::scanner::probe_concurrently;
+use crate::{Error, Result};
+use async_std::io;
+use async_std::task;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use log::debug;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// 默认同时查询的 IP 数，DNS 服务器通常会限制单个客户端的查询速率。
+const DEFAULT_CONCURRENCY: usize = 32;
+
+/// 查询主机的 PTR 记录。
+#[derive(Debug)]
+pub struct ReverseDns {
+    resolver: TokioAsyncResolver,
+    runtime: Arc<Runtime>,
+    timeout: Duration,
+    concurrency: usize,
+}
+
+impl ReverseDns {
+    /// `timeout` 限制每个 IP 的查询时间。无法创建运行时时返回 [`Error::Runtime`]。
+    pub fn new(
+        config: ResolverConfig,
+        mut options: ResolverOpts,
+        timeout: Duration,
+    ) -> Result<Self> {
+        options.timeout = timeout;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+        let resolver = {
+            let _guard = runtime.enter();
+            TokioAsyncResolver::tokio(config, options)
+        };
+        Ok(Self {
+            resolver,
+            runtime: Arc::new(runtime),
+            timeout,
+            concurrency: DEFAULT_CONCURRENCY,
+        })
+    }
+
+    /// 设置同时查询的 IP 数。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 查询所有 IP，只返回查到了名字的 IP，名字去掉了末尾的点。
+    pub async fn lookup(&self, ips: Vec<IpAddr>) -> HashMap<IpAddr, Vec<String>> {
+        let resolver = self.resolver.clone();
+        let timeout = self.timeout;
+        let concurrency = self.concurrency;
+        let runtime = Arc::clone(&self.runtime);
+        // 单线程运行时只在 block_on 中运行任务，在阻塞线程上驱动它，不占用调用方的执行器
+        task::spawn_blocking(move || {
+            runtime.block_on(probe_concurrently(ips, concurrency, |ip| {
+                let resolver = &resolver;
+                async move {
+                    let lookup = io::timeout(timeout, async {
+                        resolver.reverse_lookup(ip).await.map_err(io::Error::other)
+                    })
+                    .await;
+                    match lookup {
+                        Ok(lookup) => {
+                            let names: Vec<String> = lookup
+                                .iter()
+                                .map(|ptr| ptr.to_utf8().trim_end_matches('.').to_owned())
+                                .collect();
+                            (!names.is_empty()).then_some(names)
+                        }
+                        Err(e) => {
+                            debug!("Reverse lookup of {ip} failed {e}");
+                            None
+                        }
+                    }
+                }
+            }))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReverseDns;
+    use async_std::task::block_on;
+    use hickory_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+    use std::collections::HashMap;
+    use std::net::{IpAddr, SocketAddr, UdpSocket};
+    use std::thread;
+    use std::time::Duration;
+
+    /// 在后台线程中回答 PTR 查询：`names` 中有的 IP 回答对应的名字，其它的回答 NXDOMAIN。
+    fn stub_dns(names: HashMap<&'static str, &'static str>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                let query = &buf[..len];
+                // 问题部分从第 12 字节开始，是一串以 0 结尾的标签，之后是类型和类别
+                let mut end = 12;
+                let mut labels = Vec::new();
+                while query[end] != 0 {
+                    let len = usize::from(query[end]);
+                    labels
+                        .push(String::from_utf8_lossy(&query[end + 1..end + 1 + len]).into_owned());
+                    end += len + 1;
+                }
+                end += 5;
+                // 1.2.0.192.in-addr.arpa
+                let ip: Vec<&str> = labels[..4].iter().rev().map(String::as_str).collect();
+                let name = names.get(ip.join(".").as_str());
+
+                let mut reply = query[..2].to_vec();
+                reply.extend_from_slice(if name.is_some() {
+                    &[0x81, 0x80]
+                } else {
+                    &[0x81, 0x83]
+                });
+                reply.extend_from_slice(&[0, 1, 0, u8::from(name.is_some()), 0, 0, 0, 0]);
+                reply.extend_from_slice(&query[12..end]);
+                if let Some(name) = name {
+                    let mut rdata = Vec::new();
+                    for label in name.split('.') {
+                        rdata.push(label.len() as u8);
+                        rdata.extend_from_slice(label.as_bytes());
+                    }
+                    rdata.push(0);
+                    // 指向问题中的名字，类型 PTR，类别 IN，TTL 60
+                    reply.extend_from_slice(&[0xc0, 12, 0, 12, 0, 1, 0, 0, 0, 60]);
+                    reply.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                    reply.extend_from_slice(&rdata);
+                }
+                let _ = socket.send_to(&reply, peer);
+            }
+        });
+        addr
+    }
+
+    /// 只使用 `server` 的解析器，不读取 hosts 文件。
+    fn reverse_dns(server: SocketAddr, timeout: Duration) -> ReverseDns {
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig::new(server, Protocol::Udp));
+        let mut options = ResolverOpts::default();
+        options.use_hosts_file = false;
+        ReverseDns::new(config, options, timeout).unwrap()
+    }
+
+    #[test]
+    fn ptr_names_are_returned_without_trailing_dot() {
+        let server = stub_dns(HashMap::from([("192.0.2.1", "www.example.com")]));
+        let rdns = reverse_dns(server, Duration::from_secs(2));
+
+        let ips: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()];
+        let names = block_on(rdns.lookup(ips));
+
+        assert_eq!(
+            names,
+            HashMap::from([(
+                "192.0.2.1".parse().unwrap(),
+                vec!["www.example.com".to_owned()]
+            )])
+        );
+    }
+
+    #[test]
+    fn silent_resolver_times_out() {
+        // 不回答任何查询的服务器
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let rdns = reverse_dns(silent.local_addr().unwrap(), Duration::from_millis(200));
+
+        let names = block_on(rdns.lookup(vec!["192.0.2.1".parse().unwrap()]));
+
+        assert!(names.is_empty());
+    }
+}
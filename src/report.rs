@@ -0,0 +1,589 @@
+//! 汇总扫描结果，并把它们格式化成 RustScan 的输出格式。
+//!
+//! 扫描器返回的是一个个独立的 socket，这里把它们按 IP 分组，
+//! 这样 greppable 输出、脚本和后续的探测阶段都可以按主机处理结果。
+use crate::address::Target;
+use crate::http::HttpInfo;
+use crate::scanner::{escape, OpenSocket, ScanOutcome, ScanStatus};
+use crate::service::ServiceInfo;
+use crate::tarpit::Tarpit;
+use crate::tls::TlsInfo;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+/// 单个开放端口的结果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortReport {
+    pub port: u16,
+    pub banner: Option<String>,
+    pub service: Option<ServiceInfo>,
+    pub tls: Option<TlsInfo>,
+    pub http: Option<HttpInfo>,
+}
+
+/// 一个主机以及在它上面发现的全部开放端口。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostReport {
+    pub ip: IpAddr,
+    /// 解析出这个 IP 的主机名，见 [`Target::hostnames`]
+    pub hostnames: Vec<String>,
+    /// 反向 DNS 查到的 PTR 名字
+    pub ptr_names: Vec<String>,
+    pub ports: Vec<PortReport>,
+    /// 提前结束的主机只扫描了部分端口
+    pub status: ScanStatus,
+    /// 接受所有连接的主机，它的开放端口不可信
+    pub tarpit: Option<Tarpit>,
+    /// 扫描时开放、但验证时没有再次确认的端口，不会交给脚本
+    pub unconfirmed: Vec<u16>,
+}
+
+impl HostReport {
+    pub fn new(ip: IpAddr) -> Self {
+        Self {
+            ip,
+            hostnames: vec![],
+            ptr_names: vec![],
+            ports: vec![],
+            status: ScanStatus::Complete,
+            tarpit: None,
+            unconfirmed: vec![],
+        }
+    }
+
+    /// 所有开放端口的端口号，顺序与扫描器发现它们的顺序一致。
+    pub fn port_numbers(&self) -> Vec<u16> {
+        self.ports.iter().map(|p| p.port).collect()
+    }
+
+    /// 所有开放端口对应的 socket，供扫描之后的探测阶段使用。
+    pub fn sockets(&self) -> Vec<SocketAddr> {
+        self.ports
+            .iter()
+            .map(|p| SocketAddr::new(self.ip, p.port))
+            .collect()
+    }
+
+    /// 返回了 HTTP 响应的端口以及对应的结果，供脚本使用。
+    pub fn http(&self) -> Vec<(u16, HttpInfo)> {
+        self.ports
+            .iter()
+            .filter_map(|p| p.http.clone().map(|http| (p.port, http)))
+            .collect()
+    }
+
+    /// 第一个主机名，供需要虚拟主机名的脚本使用。
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostnames.first().map(String::as_str)
+    }
+
+    /// IP 的地址族，`IPv4` 或 `IPv6`。
+    pub fn family(&self) -> &'static str {
+        if self.ip.is_ipv4() {
+            "IPv4"
+        } else {
+            "IPv6"
+        }
+    }
+
+    /// 有主机名时是 `example.com (93.184.216.34)`，否则只有 IP。
+    pub fn name(&self) -> String {
+        match self.hostname() {
+            Some(hostname) => format!("{hostname} ({})", self.ip),
+            None => self.ip.to_string(),
+        }
+    }
+
+    /// 给人看的一行摘要：`127.0.0.1 -> [22,80]`，有主机名时是 `example.com (93.184.216.34) -> [80]`。
+    /// 附加信息见 [`details`](Self::details)。
+    pub fn summary(&self) -> String {
+        format!("{} -> [{}]", self.name(), self.port_list())
+    }
+
+    /// greppable 格式，每个主机只有一行，总是以 IP 开头：`127.0.0.1 -> [22,80]`。
+    /// 有 banner 的端口在后面追加一个制表符分隔的字段，例如 `\t22=SSH-2.0-OpenSSH_9.6`，
+    /// banner 中的制表符已经被转义，不会破坏字段的划分。
+    /// 其它探测阶段的结果同样按端口追加，字段名以端口号开头，
+    /// 例如服务识别的 `\t22/service=ssh OpenSSH 9.6p1`；TLS 探测输出 `443/tls`（版本和密码套件）、
+    /// `443/alpn`，以及证书的 `443/subject`、`443/san`、`443/issuer` 和 `443/expires`（Unix 时间戳）；
+    /// HTTP 探测输出 `80/http`（协议和状态码），以及 `80/server`、`80/title` 和 `80/location`。
+    /// 没有扫描完的主机加上状态，例如 `\tstatus=timed-out`，
+    /// 被认为是 tarpit 的主机加上原因，例如 `\ttarpit=950 of 1000 scanned ports are open`，
+    /// 没有通过验证的端口列在 `\tunconfirmed=8080,8443` 中。
+    /// 主机名解析出的主机最后再加上主机名和地址族，例如 `\thostname=example.com\tfamily=IPv6`，
+    /// 同一个主机名的 IPv4 和 IPv6 地址都被扫描时可以区分结果来自哪个地址族。
+    pub fn greppable(&self) -> String {
+        let mut line = format!("{} -> [{}]", self.ip, self.port_list());
+        for port in &self.ports {
+            if let Some(banner) = &port.banner {
+                line.push_str(&format!("\t{}={banner}", port.port));
+            }
+            if let Some(service) = &port.service {
+                push_field(
+                    &mut line,
+                    format_args!("{}/service", port.port),
+                    &service.to_string(),
+                );
+            }
+            if let Some(tls) = &port.tls {
+                push_tls_fields(&mut line, port.port, tls);
+            }
+            if let Some(http) = &port.http {
+                push_http_fields(&mut line, port.port, http);
+            }
+        }
+        if let Some(status) = status_field(self.status) {
+            push_field(&mut line, "status", status);
+        }
+        if let Some(tarpit) = &self.tarpit {
+            push_field(&mut line, "tarpit", &tarpit.to_string());
+        }
+        if !self.unconfirmed.is_empty() {
+            let ports: Vec<String> = self.unconfirmed.iter().map(u16::to_string).collect();
+            push_field(&mut line, "unconfirmed", &ports.join(","));
+        }
+        if !self.hostnames.is_empty() {
+            line.push_str(&format!("\thostname={}", self.hostnames.join(",")));
+            line.push_str(&format!("\tfamily={}", self.family()));
+        }
+        line
+    }
+
+    fn port_list(&self) -> String {
+        let ports: Vec<String> = self.ports.iter().map(|p| p.port.to_string()).collect();
+        ports.join(",")
+    }
+
+    /// 每个端口的附加信息，每行一条，例如 `127.0.0.1:22 banner: SSH-2.0-OpenSSH_9.6`。
+    /// 没有附加信息的端口不会出现在这里。
+    /// 没有扫描完的主机在最前面加一行状态，例如 `127.0.0.1 status: timed out`，
+    /// 被认为是 tarpit 的主机同样加一行，例如 `10.0.0.1 tarpit: 950 of 1000 scanned ports are open`，
+    /// 没有通过验证的端口也列在一行中，例如 `127.0.0.1 unconfirmed: 8080,8443`，
+    /// 反向 DNS 查到的名字同样如此，例如 `93.184.216.34 ptr: www.example.com`。
+    /// 主机名解析出的主机在最前面列出地址族，例如 `93.184.216.34 family: IPv4`。
+    pub fn details(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.hostnames.is_empty() {
+            lines.push(format!("{} family: {}", self.ip, self.family()));
+        }
+        if !self.ptr_names.is_empty() {
+            lines.push(format!("{} ptr: {}", self.ip, self.ptr_names.join(",")));
+        }
+        if self.status != ScanStatus::Complete {
+            lines.push(format!("{} status: {}", self.ip, self.status));
+        }
+        if let Some(tarpit) = &self.tarpit {
+            lines.push(format!("{} tarpit: {tarpit}", self.ip));
+        }
+        if !self.unconfirmed.is_empty() {
+            let ports: Vec<String> = self.unconfirmed.iter().map(u16::to_string).collect();
+            lines.push(format!("{} unconfirmed: {}", self.ip, ports.join(",")));
+        }
+        for port in &self.ports {
+            let socket = SocketAddr::new(self.ip, port.port);
+            if let Some(banner) = &port.banner {
+                lines.push(format!("{socket} banner: {banner}"));
+            }
+            if let Some(service) = &port.service {
+                lines.push(format!("{socket} service: {service}"));
+            }
+            if let Some(tls) = &port.tls {
+                lines.push(format!("{socket} tls: {tls}"));
+            }
+            if let Some(http) = &port.http {
+                lines.push(format!("{socket} http: {http}"));
+            }
+        }
+        lines
+    }
+}
+
+/// 在 greppable 行后面追加一个制表符分隔的字段，值和 banner 一样转义，不会包含制表符或换行。
+fn push_field(line: &mut String, key: impl fmt::Display, value: &str) {
+    line.push_str(&format!("\t{key}={}", escape(value.as_bytes())));
+}
+
+/// TLS 握手和证书的字段，证书的字段分开输出，方便按到期时间和名字盘点证书。
+fn push_tls_fields(line: &mut String, port: u16, tls: &TlsInfo) {
+    push_field(
+        line,
+        format_args!("{port}/tls"),
+        &format!("{} {}", tls.version, tls.cipher),
+    );
+    if let Some(alpn) = &tls.alpn {
+        push_field(line, format_args!("{port}/alpn"), alpn);
+    }
+    if let Some(cert) = &tls.certificate {
+        push_field(line, format_args!("{port}/subject"), &cert.subject);
+        push_field(
+            line,
+            format_args!("{port}/san"),
+            &cert.subject_alt_names.join(","),
+        );
+        push_field(line, format_args!("{port}/issuer"), &cert.issuer);
+        push_field(
+            line,
+            format_args!("{port}/expires"),
+            &cert.not_after.to_string(),
+        );
+    }
+}
+
+/// HTTP 响应的字段，服务器返回的头和标题和 banner 一样转义。
+fn push_http_fields(line: &mut String, port: u16, http: &HttpInfo) {
+    push_field(
+        line,
+        format_args!("{port}/http"),
+        &format!("{} {}", http.scheme(), http.status),
+    );
+    let headers = [
+        ("server", &http.server),
+        ("title", &http.title),
+        ("location", &http.location),
+    ];
+    for (name, value) in headers.iter() {
+        if let Some(value) = value {
+            push_field(line, format_args!("{port}/{name}"), value);
+        }
+    }
+}
+
+/// greppable 输出中的状态，扫描完的主机没有状态字段。
+fn status_field(status: ScanStatus) -> Option<&'static str> {
+    match status {
+        ScanStatus::Complete => None,
+        ScanStatus::Cancelled => Some("cancelled"),
+        ScanStatus::TimedOut => Some("timed-out"),
+        ScanStatus::TooManyOpenFiles => Some("too-many-open-files"),
+    }
+}
+
+/// 把扫描器的结果按 IP 分组，主机按照第一次出现的顺序排列。
+pub fn group_by_host(open_sockets: Vec<OpenSocket>) -> Vec<HostReport> {
+    let mut hosts: Vec<HostReport> = Vec::new();
+    let mut index_of: HashMap<IpAddr, usize> = HashMap::new();
+
+    for open in open_sockets {
+        let ip = open.socket.ip();
+        let index = *index_of.entry(ip).or_insert_with(|| {
+            hosts.push(HostReport::new(ip));
+            hosts.len() - 1
+        });
+        hosts[index].ports.push(PortReport {
+            port: open.socket.port(),
+            banner: open.banner,
+            service: None,
+            tls: None,
+            http: None,
+        });
+    }
+
+    hosts
+}
+
+/// 和 [`group_by_host`] 一样，并记录每个主机的扫描状态和 `targets` 中的主机名。
+/// `targets` 中没有扫描完的主机即使没有开放端口也会出现在结果中，这样它们的状态也会被输出，
+/// 只有未确认端口的主机同样如此。
+pub fn group_outcome(outcome: ScanOutcome, targets: &[Target]) -> Vec<HostReport> {
+    let ScanOutcome {
+        open,
+        status,
+        timed_out_hosts,
+        unconfirmed,
+    } = outcome;
+    let outcome = ScanOutcome {
+        open: vec![],
+        status,
+        timed_out_hosts,
+        unconfirmed: vec![],
+    };
+
+    let mut hosts = group_by_host(open);
+    for host in &mut hosts {
+        host.status = outcome.host_status(host.ip);
+    }
+    for socket in unconfirmed {
+        let index = match hosts.iter().position(|host| host.ip == socket.ip()) {
+            Some(index) => index,
+            None => {
+                let mut host = HostReport::new(socket.ip());
+                host.status = outcome.host_status(socket.ip());
+                hosts.push(host);
+                hosts.len() - 1
+            }
+        };
+        hosts[index].unconfirmed.push(socket.port());
+    }
+    for target in targets {
+        let status = outcome.host_status(target.ip);
+        if status != ScanStatus::Complete && !hosts.iter().any(|host| host.ip == target.ip) {
+            let mut host = HostReport::new(target.ip);
+            host.status = status;
+            hosts.push(host);
+        }
+    }
+    let hostnames: HashMap<IpAddr, &Vec<String>> = targets
+        .iter()
+        .map(|target| (target.ip, &target.hostnames))
+        .collect();
+    for host in &mut hosts {
+        if let Some(names) = hostnames.get(&host.ip) {
+            host.hostnames.clone_from(names);
+        }
+    }
+
+    hosts
+}
+
+/// 把扫描之后的某个阶段按 socket 得到的结果合并到报告中。
+///
+/// ```rust
+/// # use rustscan::report::{annotate, group_by_host};
+/// # use rustscan::scanner::OpenSocket;
+/// # use std::collections::HashMap;
+/// let socket = "127.0.0.1:22".parse().unwrap();
+/// let mut hosts = group_by_host(vec![OpenSocket::new(socket)]);
+/// let banners = HashMap::from([(socket, "SSH-2.0-OpenSSH_9.6".to_owned())]);
+/// annotate(&mut hosts, banners, |port, banner| port.banner = Some(banner));
+/// assert!(hosts[0].ports[0].banner.is_some());
+/// ```
+pub fn annotate<T>(
+    hosts: &mut [HostReport],
+    mut values: HashMap<SocketAddr, T>,
+    mut apply: impl FnMut(&mut PortReport, T),
+) {
+    for host in hosts {
+        for port in &mut host.ports {
+            if let Some(value) = values.remove(&SocketAddr::new(host.ip, port.port)) {
+                apply(port, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotate, group_by_host, group_outcome};
+    use crate::address::Target;
+    use crate::http::HttpInfo;
+    use crate::scanner::{OpenSocket, ScanOutcome, ScanStatus};
+    use crate::service::ServiceInfo;
+    use crate::tarpit::Tarpit;
+    use crate::tls::{CertificateInfo, TlsInfo};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn groups_sockets_by_ip() {
+        let mut ssh = OpenSocket::new("127.0.0.1:22".parse::<SocketAddr>().unwrap());
+        ssh.banner = Some("SSH-2.0-OpenSSH_9.6".to_owned());
+        let open = vec![
+            ssh,
+            OpenSocket::new("10.0.0.1:443".parse().unwrap()),
+            OpenSocket::new("127.0.0.1:80".parse().unwrap()),
+        ];
+
+        let hosts = group_by_host(open);
+
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].summary(), "127.0.0.1 -> [22,80]");
+        assert_eq!(
+            hosts[0].greppable(),
+            "127.0.0.1 -> [22,80]\t22=SSH-2.0-OpenSSH_9.6"
+        );
+        assert_eq!(hosts[1].greppable(), "10.0.0.1 -> [443]");
+        assert_eq!(
+            hosts[0].details(),
+            vec!["127.0.0.1:22 banner: SSH-2.0-OpenSSH_9.6".to_owned()]
+        );
+        assert!(hosts[1].details().is_empty());
+    }
+
+    #[test]
+    fn services_are_added_to_details() {
+        let socket: SocketAddr = "127.0.0.1:22".parse().unwrap();
+        let mut hosts = group_by_host(vec![OpenSocket::new(socket)]);
+        let service = ServiceInfo {
+            name: "ssh".to_owned(),
+            product: Some("OpenSSH".to_owned()),
+            ..Default::default()
+        };
+
+        annotate(&mut hosts, HashMap::from([(socket, service)]), |port, s| {
+            port.service = Some(s);
+        });
+
+        assert_eq!(hosts[0].sockets(), vec![socket]);
+        assert_eq!(
+            hosts[0].greppable(),
+            "127.0.0.1 -> [22]\t22/service=ssh OpenSSH"
+        );
+        assert_eq!(
+            hosts[0].details(),
+            vec!["127.0.0.1:22 service: ssh OpenSSH".to_owned()]
+        );
+    }
+
+    #[test]
+    fn tls_fields_are_added_to_greppable() {
+        let mut hosts = group_by_host(vec![OpenSocket::new("10.0.0.1:443".parse().unwrap())]);
+        hosts[0].ports[0].tls = Some(TlsInfo {
+            version: "TLSv1_3".to_owned(),
+            cipher: "TLS13_AES_256_GCM_SHA384".to_owned(),
+            alpn: Some("h2".to_owned()),
+            certificate: Some(CertificateInfo {
+                subject: "CN=example.com".to_owned(),
+                issuer: "CN=Example CA".to_owned(),
+                subject_alt_names: vec!["example.com".to_owned(), "www.example.com".to_owned()],
+                not_before: 1_700_000_000,
+                not_after: 1_800_000_000,
+            }),
+        });
+
+        assert_eq!(
+            hosts[0].greppable(),
+            "10.0.0.1 -> [443]\t443/tls=TLSv1_3 TLS13_AES_256_GCM_SHA384\t443/alpn=h2\
+             \t443/subject=CN=example.com\t443/san=example.com,www.example.com\
+             \t443/issuer=CN=Example CA\t443/expires=1800000000"
+        );
+    }
+
+    #[test]
+    fn http_fields_are_added_to_greppable() {
+        let mut hosts = group_by_host(vec![OpenSocket::new("10.0.0.1:80".parse().unwrap())]);
+        hosts[0].ports[0].http = Some(HttpInfo {
+            https: false,
+            status: 200,
+            reason: "OK".to_owned(),
+            server: Some("nginx".to_owned()),
+            title: Some("Welcome\tto nginx!".to_owned()),
+            location: None,
+            content_length: Some(612),
+        });
+
+        assert_eq!(
+            hosts[0].greppable(),
+            "10.0.0.1 -> [80]\t80/http=http 200\t80/server=nginx\t80/title=Welcome\\tto nginx!"
+        );
+    }
+
+    #[test]
+    fn unfinished_hosts_report_their_status() {
+        let outcome = ScanOutcome {
+            open: vec![OpenSocket::new("127.0.0.1:22".parse().unwrap())],
+            status: ScanStatus::Complete,
+            timed_out_hosts: vec!["10.0.0.1".parse().unwrap()],
+            unconfirmed: vec![],
+        };
+        let targets =
+            ["127.0.0.1", "10.0.0.1", "10.0.0.2"].map(|ip| Target::new(ip.parse().unwrap(), ip));
+
+        let hosts = group_outcome(outcome, &targets);
+
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].status, ScanStatus::Complete);
+        assert!(hosts[0].details().is_empty());
+        assert_eq!(hosts[1].greppable(), "10.0.0.1 -> []\tstatus=timed-out");
+        assert_eq!(
+            hosts[1].details(),
+            vec!["10.0.0.1 status: timed out".to_owned()]
+        );
+    }
+
+    #[test]
+    fn hostnames_are_shown_with_ips() {
+        let outcome = ScanOutcome {
+            open: vec![
+                OpenSocket::new("93.184.216.34:443".parse().unwrap()),
+                OpenSocket::new("10.0.0.1:22".parse().unwrap()),
+            ],
+            status: ScanStatus::Complete,
+            timed_out_hosts: vec![],
+            unconfirmed: vec![],
+        };
+        let mut example = Target::new("93.184.216.34".parse().unwrap(), "example.com");
+        example.hostnames = vec!["example.com".to_owned(), "www.example.com".to_owned()];
+        let targets = [
+            example,
+            Target::new("10.0.0.1".parse().unwrap(), "10.0.0.0/24"),
+        ];
+
+        let hosts = group_outcome(outcome, &targets);
+
+        assert_eq!(hosts[0].hostname(), Some("example.com"));
+        assert_eq!(hosts[0].summary(), "example.com (93.184.216.34) -> [443]");
+        assert_eq!(
+            hosts[0].greppable(),
+            "93.184.216.34 -> [443]\thostname=example.com,www.example.com\tfamily=IPv4"
+        );
+        assert_eq!(
+            hosts[0].details(),
+            vec!["93.184.216.34 family: IPv4".to_owned()]
+        );
+        assert_eq!(hosts[1].hostname(), None);
+        assert_eq!(hosts[1].greppable(), "10.0.0.1 -> [22]");
+        assert!(hosts[1].details().is_empty());
+    }
+
+    #[test]
+    fn unconfirmed_ports_are_listed_per_host() {
+        let outcome = ScanOutcome {
+            open: vec![OpenSocket::new("127.0.0.1:22".parse().unwrap())],
+            status: ScanStatus::Complete,
+            timed_out_hosts: vec![],
+            unconfirmed: vec![
+                "127.0.0.1:8080".parse().unwrap(),
+                "10.0.0.1:443".parse().unwrap(),
+                "127.0.0.1:8443".parse().unwrap(),
+            ],
+        };
+
+        let hosts = group_outcome(outcome, &[]);
+
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(
+            hosts[0].greppable(),
+            "127.0.0.1 -> [22]\tunconfirmed=8080,8443"
+        );
+        assert_eq!(
+            hosts[0].details(),
+            vec!["127.0.0.1 unconfirmed: 8080,8443".to_owned()]
+        );
+        assert_eq!(hosts[1].greppable(), "10.0.0.1 -> []\tunconfirmed=443");
+        assert_eq!(
+            hosts[1].details(),
+            vec!["10.0.0.1 unconfirmed: 443".to_owned()]
+        );
+    }
+
+    #[test]
+    fn ptr_names_are_added_to_details() {
+        let mut hosts = group_by_host(vec![OpenSocket::new("93.184.216.34:443".parse().unwrap())]);
+        hosts[0].ptr_names = vec!["www.example.com".to_owned(), "example.com".to_owned()];
+
+        assert_eq!(
+            hosts[0].details(),
+            vec!["93.184.216.34 ptr: www.example.com,example.com".to_owned()]
+        );
+    }
+
+    #[test]
+    fn tarpits_are_flagged_in_details() {
+        let mut hosts = group_by_host(vec![OpenSocket::new("10.0.0.1:1".parse().unwrap())]);
+        hosts[0].tarpit = Some(Tarpit::Silent { sampled: 5 });
+
+        assert_eq!(
+            hosts[0].greppable(),
+            "10.0.0.1 -> [1]\ttarpit=5 sampled open ports accepted connections but never responded"
+        );
+
+        assert_eq!(
+            hosts[0].details(),
+            vec![
+                "10.0.0.1 tarpit: 5 sampled open ports accepted connections but never responded"
+                    .to_owned()
+            ]
+        );
+    }
+}
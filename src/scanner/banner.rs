@@ -0,0 +1,179 @@
+//! 在开放的 TCP 端口上抓取服务 banner。
+//!
+//! 很多服务（SSH、FTP、SMTP……）在连接建立后会主动发送一行欢迎信息，
+//! 只需要读取前几个字节就能知道服务的版本，而不需要启动 nmap。
+//! 对于不会主动说话的服务，可以先发送一个很小的探测数据（比如 `\r\n`）。
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use log::debug;
+use std::time::Duration;
+
+/// 最多读取的 banner 字节数，多余的部分会被丢弃。
+const MAX_BANNER_LEN: usize = 256;
+
+/// banner 抓取的选项。
+///
+/// `probe` 为空时表示 null 探测：只等待服务主动发送数据。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BannerOptions {
+    pub timeout: Duration,
+    pub probe: Vec<u8>,
+}
+
+impl BannerOptions {
+    pub fn new(timeout: Duration, probe: Vec<u8>) -> Self {
+        Self { timeout, probe }
+    }
+}
+
+/// 将命令行中的探测字符串转换为字节，支持 `\r`、`\n`、`\t`、`\0`、`\\` 和 `\xNN` 转义。
+///
+/// ```rust
+/// # use rustscan::scanner::parse_banner_probe;
+/// assert_eq!(parse_banner_probe(r"HELP\r\n"), b"HELP\r\n".to_vec());
+/// assert_eq!(parse_banner_probe(r"\x00\x01"), vec![0, 1]);
+/// ```
+pub fn parse_banner_probe(input: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => bytes.push(byte),
+                    // 不是合法的十六进制转义，原样保留
+                    _ => bytes.extend_from_slice(format!("\\x{hex}").as_bytes()),
+                }
+            }
+            Some(other) => {
+                bytes.push(b'\\');
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+
+    bytes
+}
+
+/// 把原始 banner 转换成可以安全打印的单行字符串。
+///
+/// 去掉首尾空白，可打印的 ASCII 字符原样保留，`\r`、`\n`、`\t` 写成转义形式，
+/// 其余字节写成 `\xNN`，这样终端控制字符不会破坏输出。
+///
+/// ```rust
+/// # use rustscan::scanner::sanitize_banner;
+/// assert_eq!(sanitize_banner(b"SSH-2.0-OpenSSH_9.6\r\n"), "SSH-2.0-OpenSSH_9.6");
+/// assert_eq!(sanitize_banner(b"220 hi\r\n\x1b[31m"), r"220 hi\r\n\x1b[31m");
+/// ```
+pub fn sanitize_banner(raw: &[u8]) -> String {
+    let raw = &raw[..raw.len().min(MAX_BANNER_LEN)];
+    let start = raw
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(raw.len());
+    let end = raw
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+
+    escape(&raw[start..end])
+}
+
+/// 和 [`sanitize_banner`] 一样转义，但不去掉空白也不截断，用于输出中的其它字段。
+pub(crate) fn escape(raw: &[u8]) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for &byte in raw {
+        match byte {
+            b'\r' => escaped.push_str("\\r"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    escaped
+}
+
+/// 在已经建立的连接上读取 banner。
+///
+/// 如果设置了探测数据则先发送，然后在 `timeout` 内等待第一批数据。
+/// 超时、连接被关闭或者读到的内容为空时返回 `None`。
+pub(crate) async fn grab_banner(stream: &mut TcpStream, options: &BannerOptions) -> Option<String> {
+    if !options.probe.is_empty() {
+        if let Err(e) = io::timeout(options.timeout, stream.write_all(&options.probe)).await {
+            debug!("Sending banner probe failed {e}");
+            return None;
+        }
+    }
+
+    let mut buf = [0u8; MAX_BANNER_LEN];
+    match io::timeout(options.timeout, stream.read(&mut buf)).await {
+        Ok(0) => None,
+        Ok(size) => {
+            let banner = sanitize_banner(&buf[..size]);
+            (!banner.is_empty()).then_some(banner)
+        }
+        Err(e) => {
+            debug!("Reading banner failed {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_banner_probe, sanitize_banner};
+
+    #[test]
+    fn probe_escapes_are_decoded() {
+        assert_eq!(parse_banner_probe(""), Vec::<u8>::new());
+        assert_eq!(
+            parse_banner_probe(r"GET / HTTP/1.0\r\n\r\n"),
+            b"GET / HTTP/1.0\r\n\r\n"
+        );
+        assert_eq!(
+            parse_banner_probe(r"\x41\t\0\\"),
+            vec![b'A', b'\t', 0, b'\\']
+        );
+    }
+
+    #[test]
+    fn probe_invalid_escapes_are_kept() {
+        assert_eq!(parse_banner_probe(r"\xZZ"), br"\xZZ");
+        assert_eq!(parse_banner_probe(r"\q"), br"\q");
+        assert_eq!(parse_banner_probe("end\\"), b"end\\");
+    }
+
+    #[test]
+    fn banner_is_sanitized() {
+        assert_eq!(
+            sanitize_banner(b"\r\n  220 ftp ready \r\n"),
+            "220 ftp ready"
+        );
+        assert_eq!(sanitize_banner(b"a\x00b\xffc"), r"a\x00b\xffc");
+        assert_eq!(sanitize_banner(b"\r\n"), "");
+    }
+
+    #[test]
+    fn banner_is_truncated() {
+        let long = vec![b'A'; 1_000];
+        assert_eq!(sanitize_banner(&long).len(), 256);
+    }
+}
@@ -0,0 +1,482 @@
+//! [`Scanner`] 的构建器。
+//!
+//! 新的选项只需要在这里增加一个设置方法，不会破坏已有的调用代码。
+//!
+//! ```rust
+//! # use rustscan::input::{PortRange, ScanOrder};
+//! # use rustscan::port_strategy::PortStrategy;
+//! # use rustscan::scanner::Scanner;
+//! # use std::time::Duration;
+//! let range = PortRange { start: 1, end: 1_000 };
+//! let scanner = Scanner::builder()
+//!     .ips(vec!["127.0.0.1".parse().unwrap()])
+//!     .port_strategy(PortStrategy::pick(&Some(range), None, ScanOrder::Random))
+//!     .timeout(Duration::from_millis(500))
+//!     .greppable(true)
+//!     .build()
+//!     .unwrap();
+//! ```
+use super::{
+    BannerOptions, BuiltinProbe, CancelHandle, Probe, Proxy, RetryBackoff, ScanControl, Scanner,
+    SourceOptions, TcpConnectProbe, UdpProbe, DEFAULT_VERIFY_CONCURRENCY,
+};
+use crate::event::{CliPrinter, EventSink};
+use crate::input::{PortRange, ScanOrder};
+use crate::port_strategy::PortStrategy;
+use std::fmt;
+use std::net::IpAddr;
+use std::num::NonZeroU8;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use super::{SynProbe, SynScanner};
+
+/// 和命令行的默认值保持一致。
+const DEFAULT_BATCH_SIZE: usize = 4500;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// 构建 [`Scanner`] 时发现的无效设置。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// 没有设置任何要扫描的 IP
+    NoAddresses,
+    /// `batch_size` 为 0，扫描永远不会开始
+    ZeroBatchSize,
+    /// `timeout` 为 0，所有端口都会超时
+    ZeroTimeout,
+    /// `tries` 为 0
+    ZeroTries,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NoAddresses => write!(f, "no IP addresses to scan"),
+            BuildError::ZeroBatchSize => write!(f, "batch size must be greater than 0"),
+            BuildError::ZeroTimeout => write!(f, "timeout must be greater than 0"),
+            BuildError::ZeroTries => write!(f, "tries must be greater than 0"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// [`Scanner`] 的构建器，通过 [`Scanner::builder`] 创建。
+///
+/// 默认扫描全部 65535 个端口，批处理大小 4500，超时 1.5 秒，不重试，使用 TCP 连接扫描。
+pub struct ScannerBuilder {
+    ips: Vec<IpAddr>,
+    batch_size: usize,
+    timeout: Duration,
+    tries: u8,
+    greppable: bool,
+    accessible: bool,
+    port_strategy: Option<PortStrategy>,
+    exclude_ports: Vec<u16>,
+    udp: bool,
+    banner: Option<BannerOptions>,
+    source: SourceOptions,
+    proxy: Option<Proxy>,
+    events: Option<Arc<dyn EventSink>>,
+    cancel: Option<CancelHandle>,
+    control: Option<ScanControl>,
+    max_scan_time: Option<Duration>,
+    host_timeout: Option<Duration>,
+    scan_delay: Duration,
+    max_host_concurrency: Option<usize>,
+    max_host_rate: Option<u32>,
+    retry_backoff: RetryBackoff,
+    verify: Option<Duration>,
+    verify_concurrency: usize,
+    #[cfg(target_os = "linux")]
+    syn: Option<SynScanner>,
+}
+
+impl fmt::Debug for ScannerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScannerBuilder")
+            .field("ips", &self.ips)
+            .field("batch_size", &self.batch_size)
+            .field("timeout", &self.timeout)
+            .field("tries", &self.tries)
+            .field("greppable", &self.greppable)
+            .field("accessible", &self.accessible)
+            .field("port_strategy", &self.port_strategy)
+            .field("exclude_ports", &self.exclude_ports)
+            .field("udp", &self.udp)
+            .field("banner", &self.banner)
+            .field("source", &self.source)
+            .field("proxy", &self.proxy)
+            .field("cancel", &self.cancel)
+            .field("control", &self.control)
+            .field("max_scan_time", &self.max_scan_time)
+            .field("host_timeout", &self.host_timeout)
+            .field("scan_delay", &self.scan_delay)
+            .field("max_host_concurrency", &self.max_host_concurrency)
+            .field("max_host_rate", &self.max_host_rate)
+            .field("retry_backoff", &self.retry_backoff)
+            .field("verify", &self.verify)
+            .field("verify_concurrency", &self.verify_concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ScannerBuilder {
+    fn default() -> Self {
+        Self {
+            ips: vec![],
+            batch_size: DEFAULT_BATCH_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+            tries: 1,
+            greppable: false,
+            accessible: false,
+            port_strategy: None,
+            exclude_ports: vec![],
+            udp: false,
+            banner: None,
+            source: SourceOptions::default(),
+            proxy: None,
+            events: None,
+            cancel: None,
+            control: None,
+            max_scan_time: None,
+            host_timeout: None,
+            scan_delay: Duration::ZERO,
+            max_host_concurrency: None,
+            max_host_rate: None,
+            retry_backoff: RetryBackoff::default(),
+            verify: None,
+            verify_concurrency: DEFAULT_VERIFY_CONCURRENCY,
+            #[cfg(target_os = "linux")]
+            syn: None,
+        }
+    }
+}
+
+impl ScannerBuilder {
+    /// 要扫描的 IP。
+    #[must_use]
+    pub fn ips(mut self, ips: Vec<IpAddr>) -> Self {
+        self.ips = ips;
+        self
+    }
+
+    /// 同时扫描的 socket 数。
+    #[must_use]
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// 在判定端口关闭之前等待的时间。
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 每个 socket 最多尝试的次数，只有没有响应或者暂时性的错误才会重试。
+    #[must_use]
+    pub fn tries(mut self, tries: u8) -> Self {
+        self.tries = tries;
+        self
+    }
+
+    /// 不在扫描过程中打印开放的端口，设置了 [`ScannerBuilder::events`] 时忽略。
+    #[must_use]
+    pub fn greppable(mut self, greppable: bool) -> Self {
+        self.greppable = greppable;
+        self
+    }
+
+    /// 输出不使用颜色和符号，方便屏幕阅读器，设置了 [`ScannerBuilder::events`] 时忽略。
+    #[must_use]
+    pub fn accessible(mut self, accessible: bool) -> Self {
+        self.accessible = accessible;
+        self
+    }
+
+    /// 要扫描的端口以及扫描顺序。
+    #[must_use]
+    pub fn port_strategy(mut self, port_strategy: PortStrategy) -> Self {
+        self.port_strategy = Some(port_strategy);
+        self
+    }
+
+    /// 不扫描的端口。
+    #[must_use]
+    pub fn exclude_ports(mut self, exclude_ports: Vec<u16>) -> Self {
+        self.exclude_ports = exclude_ports;
+        self
+    }
+
+    /// 使用 UDP 扫描代替 TCP 扫描。
+    #[must_use]
+    pub fn udp(mut self, udp: bool) -> Self {
+        self.udp = udp;
+        self
+    }
+
+    /// 对开放的 TCP 端口抓取 banner，UDP 扫描时忽略。
+    #[must_use]
+    pub fn banner(mut self, options: BannerOptions) -> Self {
+        self.banner = Some(options);
+        self
+    }
+
+    /// 探测使用的源地址、源端口和网络接口，只影响 TCP 连接扫描和 UDP 扫描，
+    /// 指定多个源地址时轮流使用；SYN 扫描使用 [`SynScanner::with_source`] 创建时指定的源地址。
+    #[must_use]
+    pub fn source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 通过 SOCKS5 或 HTTP CONNECT 代理进行连接扫描和 banner 抓取。
+    /// 设置代理后不会使用 SYN 扫描，UDP 扫描忽略代理。
+    #[must_use]
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// 接收扫描事件的对象，默认是按 `greppable` 和 `accessible` 打印的 [`CliPrinter`]。
+    #[must_use]
+    pub fn events(mut self, events: Arc<dyn EventSink>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// 用于从外部停止扫描的句柄。
+    #[must_use]
+    pub fn cancel_handle(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// 用于在扫描过程中暂停、恢复扫描或者调整速度的句柄。
+    #[must_use]
+    pub fn control(mut self, control: ScanControl) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// 整个扫描最多运行的时间，到时间后返回已经得到的结果。
+    #[must_use]
+    pub fn max_scan_time(mut self, max_scan_time: Duration) -> Self {
+        self.max_scan_time = Some(max_scan_time);
+        self
+    }
+
+    /// 每个主机从第一次被探测开始最多扫描的时间，到时间后跳过它剩下的端口。
+    #[must_use]
+    pub fn host_timeout(mut self, host_timeout: Duration) -> Self {
+        self.host_timeout = Some(host_timeout);
+        self
+    }
+
+    /// 两次发起探测之间至少间隔的时间，和 [`ScanControl`] 的速度限制同时生效。
+    #[must_use]
+    pub fn scan_delay(mut self, scan_delay: Duration) -> Self {
+        self.scan_delay = scan_delay;
+        self
+    }
+
+    /// 每个主机同时进行的探测数上限，0 表示不限制。
+    /// 只扫描少数几个主机时 `batch_size` 的探测会集中在它们身上，用它保护脆弱的主机。
+    #[must_use]
+    pub fn max_host_concurrency(mut self, max_host_concurrency: usize) -> Self {
+        self.max_host_concurrency = Some(max_host_concurrency);
+        self
+    }
+
+    /// 每个主机每秒最多发起的探测数，0 表示不限制。达到上限的主机不会拖慢其它主机。
+    #[must_use]
+    pub fn max_host_rate(mut self, max_host_rate: u32) -> Self {
+        self.max_host_rate = Some(max_host_rate);
+        self
+    }
+
+    /// 重试之前等待的时间，默认从 100ms 开始每次翻倍，最多 10 秒，加上最多 20% 的随机抖动。
+    #[must_use]
+    pub fn retry_backoff(mut self, retry_backoff: RetryBackoff) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// 扫描结束后用 `timeout` 和较低的并发重新探测所有开放的端口，
+    /// 没有再次确认的端口记录在 [`ScanOutcome::unconfirmed`](super::ScanOutcome::unconfirmed) 中。
+    /// SYN 扫描的结果用连接扫描确认。
+    #[must_use]
+    pub fn verify(mut self, timeout: Duration) -> Self {
+        self.verify = Some(timeout);
+        self
+    }
+
+    /// 确认开放端口时同时进行的探测数，默认 32。
+    #[must_use]
+    pub fn verify_concurrency(mut self, concurrency: usize) -> Self {
+        self.verify_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 使用原始套接字进行 SYN 扫描，UDP 扫描时忽略。
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn syn(mut self, syn: SynScanner) -> Self {
+        self.syn = Some(syn);
+        self
+    }
+
+    /// 检查设置并使用内置的扫描技术构建扫描器。
+    pub fn build(mut self) -> Result<Scanner, BuildError> {
+        let verify = self.verify.map(|timeout| self.verify_probe(timeout));
+        let probe = self.builtin_probe();
+        let mut scanner = self.build_with_probe(probe)?;
+        scanner.verify = verify;
+        Ok(scanner)
+    }
+
+    /// 检查设置并使用自定义的扫描技术构建扫描器。
+    /// `timeout`、`udp`、`banner`、`verify` 等只影响内置技术的设置会被忽略。
+    pub fn build_with_probe<P: Probe>(self, probe: P) -> Result<Scanner<P>, BuildError> {
+        if self.ips.is_empty() {
+            return Err(BuildError::NoAddresses);
+        }
+        if self.batch_size == 0 {
+            return Err(BuildError::ZeroBatchSize);
+        }
+        if self.timeout.is_zero() {
+            return Err(BuildError::ZeroTimeout);
+        }
+        let tries = NonZeroU8::new(self.tries).ok_or(BuildError::ZeroTries)?;
+
+        let port_strategy = self.port_strategy.unwrap_or_else(|| {
+            let range = PortRange {
+                start: 1,
+                end: 65_535,
+            };
+            PortStrategy::pick(&Some(range), None, ScanOrder::Serial)
+        });
+
+        let (greppable, accessible) = (self.greppable, self.accessible);
+        let events = self
+            .events
+            .unwrap_or_else(|| Arc::new(CliPrinter::new(greppable, accessible)));
+
+        Ok(Scanner {
+            ips: self.ips,
+            batch_size: self.batch_size,
+            tries,
+            port_strategy,
+            exclude_ports: self.exclude_ports,
+            events,
+            cancel: self.cancel,
+            control: self.control,
+            max_scan_time: self.max_scan_time,
+            host_timeout: self.host_timeout,
+            scan_delay: self.scan_delay,
+            max_host_concurrency: self.max_host_concurrency,
+            max_host_rate: self.max_host_rate,
+            retry_backoff: self.retry_backoff,
+            verify: None,
+            verify_concurrency: self.verify_concurrency,
+            probe,
+        })
+    }
+
+    /// 确认开放端口使用的探测：不抓取 banner，SYN 扫描的结果用连接扫描确认。
+    fn verify_probe(&self, timeout: Duration) -> BuiltinProbe {
+        let source = self.source.clone();
+        if self.udp {
+            return BuiltinProbe::Udp(UdpProbe::new(timeout).with_source(source));
+        }
+        let mut connect = TcpConnectProbe::new(timeout).with_source(source);
+        if let Some(proxy) = self.proxy {
+            connect = connect.with_proxy(proxy);
+        }
+        BuiltinProbe::Connect(connect)
+    }
+
+    fn builtin_probe(&mut self) -> BuiltinProbe {
+        let source = std::mem::take(&mut self.source);
+        if self.udp {
+            return BuiltinProbe::Udp(UdpProbe::new(self.timeout).with_source(source));
+        }
+
+        let mut connect = TcpConnectProbe::new(self.timeout).with_source(source);
+        if let Some(options) = self.banner.take() {
+            connect = connect.with_banner(options);
+        }
+        if let Some(proxy) = self.proxy.take() {
+            return BuiltinProbe::Connect(connect.with_proxy(proxy));
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(syn) = self.syn.take() {
+            return BuiltinProbe::Syn(SynProbe::new(syn, connect));
+        }
+        BuiltinProbe::Connect(connect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuildError;
+    use crate::scanner::{BuiltinProbe, Scanner};
+    use std::time::Duration;
+
+    #[test]
+    fn defaults_build_a_connect_scanner() {
+        let scanner = Scanner::builder()
+            .ips(vec!["127.0.0.1".parse().unwrap()])
+            .build()
+            .unwrap();
+
+        assert_eq!(scanner.batch_size, 4500);
+        assert_eq!(scanner.tries.get(), 1);
+        assert_eq!(scanner.port_strategy.order().len(), 65_535);
+        assert!(matches!(scanner.probe, BuiltinProbe::Connect(_)));
+    }
+
+    #[test]
+    fn udp_overrides_tcp_options() {
+        let scanner = Scanner::builder()
+            .ips(vec!["127.0.0.1".parse().unwrap()])
+            .udp(true)
+            .build()
+            .unwrap();
+
+        assert!(matches!(scanner.probe, BuiltinProbe::Udp(_)));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let ips = vec!["127.0.0.1".parse().unwrap()];
+
+        assert_eq!(
+            Scanner::builder().build().unwrap_err(),
+            BuildError::NoAddresses
+        );
+        assert_eq!(
+            Scanner::builder()
+                .ips(ips.clone())
+                .batch_size(0)
+                .build()
+                .unwrap_err(),
+            BuildError::ZeroBatchSize
+        );
+        assert_eq!(
+            Scanner::builder()
+                .ips(ips.clone())
+                .timeout(Duration::ZERO)
+                .build()
+                .unwrap_err(),
+            BuildError::ZeroTimeout
+        );
+        assert_eq!(
+            Scanner::builder().ips(ips).tries(0).build().unwrap_err(),
+            BuildError::ZeroTries
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! 从扫描器外部停止扫描。
+use async_std::channel::{self, Receiver, Sender};
+
+/// 取消扫描的句柄，可以克隆后交给其它线程或任务。
+///
+/// 调用 [`CancelHandle::cancel`] 之后扫描器不再发起新的探测，等待已经发出的探测结束后
+/// 返回目前为止的结果，状态为 [`ScanStatus::Cancelled`](super::ScanStatus::Cancelled)。
+///
+/// ```rust
+/// # use rustscan::scanner::CancelHandle;
+/// let cancel = CancelHandle::new();
+/// let for_other_thread = cancel.clone();
+/// std::thread::spawn(move || for_other_thread.cancel());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    // 从来不发送消息，关闭通道会唤醒所有等待的接收者
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel::bounded(1);
+        Self { sender, receiver }
+    }
+
+    /// 请求停止扫描，可以多次调用。
+    pub fn cancel(&self) {
+        self.sender.close();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// 在调用 [`CancelHandle::cancel`] 之后完成。
+    pub(crate) async fn cancelled(&self) {
+        let _ = self.receiver.recv().await;
+    }
+}
+
+impl Default for CancelHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancelHandle;
+    use async_std::task::block_on;
+
+    #[test]
+    fn cancel_wakes_clones() {
+        let cancel = CancelHandle::new();
+        let clone = cancel.clone();
+        assert!(!clone.is_cancelled());
+
+        let waiter = std::thread::spawn(move || block_on(clone.cancelled()));
+        cancel.cancel();
+        waiter.join().unwrap();
+        assert!(cancel.is_cancelled());
+    }
+}
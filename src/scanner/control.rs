@@ -0,0 +1,162 @@
+//! 在扫描过程中暂停、恢复扫描或者调整速度。
+use async_std::channel::{self, Receiver, Sender};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[cfg(unix)]
+use signal_hook::consts::{SIGUSR1, SIGUSR2};
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
+#[cfg(unix)]
+use std::{io, thread};
+
+/// 控制正在运行的扫描，可以克隆后交给其它线程（例如界面或信号处理）。
+///
+/// 暂停时扫描器不再发起新的探测，已经发出的探测照常完成；恢复后从暂停的位置继续。
+///
+/// ```rust
+/// # use rustscan::scanner::ScanControl;
+/// let control = ScanControl::new();
+/// control.set_rate(Some(1_000));
+/// control.pause();
+/// assert!(control.is_paused());
+/// control.resume();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScanControl {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    paused: AtomicBool,
+    /// 每秒最多发起的探测数，0 表示不限制
+    rate: AtomicU32,
+    // 状态改变时发送一个通知，唤醒等待中的扫描器
+    changed: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+impl ScanControl {
+    pub fn new() -> Self {
+        let (changed, receiver) = channel::bounded(1);
+        Self {
+            inner: Arc::new(Inner {
+                paused: AtomicBool::new(false),
+                rate: AtomicU32::new(0),
+                changed,
+                receiver,
+            }),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.inner.paused.store(true, Ordering::SeqCst);
+        self.notify();
+    }
+
+    pub fn resume(&self) {
+        self.inner.paused.store(false, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// 切换暂停状态，返回切换之后是否处于暂停状态。
+    pub fn toggle_pause(&self) -> bool {
+        let paused = !self.inner.paused.fetch_xor(true, Ordering::SeqCst);
+        self.notify();
+        paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+
+    /// 每秒最多发起的探测数，`None` 或 `Some(0)` 表示不限制（只受批处理大小限制）。
+    pub fn set_rate(&self, rate: Option<u32>) {
+        self.inner.rate.store(rate.unwrap_or(0), Ordering::SeqCst);
+        self.notify();
+    }
+
+    pub fn rate(&self) -> Option<u32> {
+        match self.inner.rate.load(Ordering::SeqCst) {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
+    /// 收到 SIGUSR1 时暂停扫描，收到 SIGUSR2 时恢复扫描，例如 `kill -USR1 <pid>`。
+    ///
+    /// 信号在一个后台线程里处理，这个线程一直运行到进程退出。
+    #[cfg(unix)]
+    pub fn listen_for_signals(&self) -> io::Result<()> {
+        let mut signals = Signals::new([SIGUSR1, SIGUSR2])?;
+        let control = self.clone();
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                if signal == SIGUSR1 {
+                    control.pause();
+                } else {
+                    control.resume();
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn notify(&self) {
+        let _ = self.inner.changed.try_send(());
+    }
+
+    /// 在暂停状态或者速度改变之后完成。
+    pub(crate) async fn changed(&self) {
+        let _ = self.inner.receiver.recv().await;
+    }
+}
+
+impl Default for ScanControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScanControl;
+
+    #[test]
+    fn pause_and_rate() {
+        let control = ScanControl::new();
+        assert!(!control.is_paused());
+        assert!(control.toggle_pause());
+        assert!(control.clone().is_paused());
+        assert!(!control.toggle_pause());
+
+        assert_eq!(control.rate(), None);
+        control.set_rate(Some(500));
+        assert_eq!(control.rate(), Some(500));
+        control.set_rate(Some(0));
+        assert_eq!(control.rate(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signals_pause_and_resume() {
+        use signal_hook::consts::{SIGUSR1, SIGUSR2};
+        use std::time::{Duration, Instant};
+
+        let control = ScanControl::new();
+        control.listen_for_signals().unwrap();
+
+        let wait_for = |paused: bool| {
+            let start = Instant::now();
+            while control.is_paused() != paused {
+                assert!(start.elapsed() < Duration::from_secs(5));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        signal_hook::low_level::raise(SIGUSR1).unwrap();
+        wait_for(true);
+        signal_hook::low_level::raise(SIGUSR2).unwrap();
+        wait_for(false);
+    }
+}
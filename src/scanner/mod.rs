@@ -1,47 +1,195 @@
 //! 实际扫描行为的核心功能。
-use crate::generated::get_parsed_data;
+use crate::event::{CliPrinter, Event, EventSink};
 use crate::port_strategy::PortStrategy;
+use crate::progress::{Progress, PROGRESS_INTERVAL};
+use crate::{Error, Result};
 use log::debug;
 
 mod socket_iterator;
 use socket_iterator::SocketIterator;
 
-use async_std::net::TcpStream;
-use async_std::prelude::*;
-use async_std::{io, net::UdpSocket};
-use colored::Colorize;
+mod banner;
+pub(crate) use banner::escape;
+pub use banner::{parse_banner_probe, sanitize_banner, BannerOptions};
+
+mod builder;
+pub use builder::{BuildError, ScannerBuilder};
+
+mod cancel;
+pub use cancel::CancelHandle;
+
+mod control;
+pub use control::ScanControl;
+
+mod queue;
+pub use queue::RetryBackoff;
+use queue::{HostDeadlines, HostLimits, Next, SocketQueue};
+
+mod source;
+pub use source::SourceOptions;
+
+mod proxy;
+pub use proxy::Proxy;
+
+mod probe;
+#[cfg(target_os = "linux")]
+pub use probe::SynProbe;
+pub use probe::{BuiltinProbe, PortState, Probe, ProbeResult, TcpConnectProbe, UdpProbe};
+
+#[cfg(target_os = "linux")]
+mod syn;
+#[cfg(target_os = "linux")]
+pub use syn::SynScanner;
+
+use async_std::task;
+use futures::future::{self, Either, FutureExt};
 use futures::stream::FuturesUnordered;
-use std::collections::BTreeMap;
+use futures::StreamExt;
+use std::future::Future;
 use std::{
-    collections::HashSet,
-    net::{IpAddr, Shutdown, SocketAddr},
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
     num::NonZeroU8,
-    time::Duration,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
+/// 速度限制较高时允许的突发时间。
+const RATE_BURST: Duration = Duration::from_millis(100);
+
+/// 确认开放端口时默认同时进行的探测数。
+pub(crate) const DEFAULT_VERIFY_CONCURRENCY: usize = 32;
+
 /// 扫描器类
 /// IP 是 IpAddr 数据类型，表示 IP 地址
 /// port_strategy enum 描述了所有端口 的情况：Vec， Serial（start,end）, RandomRange（start,end） RandomRange和Serial 的区别是RandomRange 中端口的顺序是随机的，而不是 1，2，3这种，可以减少 防火墙或入侵检测系统的识别
 /// batch_size 是一次扫描多少个端口
 /// tries 重试次数。tries使用NonZeroU8 和 Option<u8> 1.NonZeroU8 在编译和运行时就强制保证不为0，省去if tries == 0 ，2NonZeroU8 占用1个字节 Option<u8> 占用2个，虽然在这个 Scanner 结构体里可能只省了几个字节，但在包含大量此类字段的大型数据结构中，这种内存优化还是不错的，👍
-/// greppable 是 RustScan 是否应该打印内容，或者等到最后只打印 ip 和开放端口。
+/// events 接收扫描过程中的事件（例如发现开放端口），扫描器本身不向标准输出打印任何内容。
+/// cancel、max_scan_time 和 host_timeout 决定扫描何时提前结束，见 [`ScanStatus`]。
+/// control 用于在扫描过程中暂停、恢复扫描或者调整速度，见 [`ScanControl`]。
+/// scan_delay 是两次发起探测之间至少间隔的时间。
+/// max_host_concurrency 和 max_host_rate 限制每个主机同时进行的探测数和每秒发起的探测数。
+/// retry_backoff 决定没有响应或者暂时出错的 socket 等待多久之后重试，见 [`RetryBackoff`]。
+/// verify 是扫描结束后用来确认开放端口的探测，超时时间更长，verify_concurrency 是确认时同时进行的探测数。
+/// probe 是判断端口是否开放的扫描技术，见 [`Probe`]。
 #[cfg(not(tarpaulin_include))]
-#[derive(Debug)]
-pub struct Scanner {
+pub struct Scanner<P = BuiltinProbe> {
     ips: Vec<IpAddr>,
     batch_size: usize,
-    timeout: Duration,
     tries: NonZeroU8,
-    greppable: bool,
     port_strategy: PortStrategy,
-    accessible: bool,
     exclude_ports: Vec<u16>,
-    udp: bool,
+    events: Arc<dyn EventSink>,
+    cancel: Option<CancelHandle>,
+    control: Option<ScanControl>,
+    max_scan_time: Option<Duration>,
+    host_timeout: Option<Duration>,
+    scan_delay: Duration,
+    max_host_concurrency: Option<usize>,
+    max_host_rate: Option<u32>,
+    retry_backoff: RetryBackoff,
+    verify: Option<BuiltinProbe>,
+    verify_concurrency: usize,
+    probe: P,
+}
+
+impl<P: fmt::Debug> fmt::Debug for Scanner<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scanner")
+            .field("ips", &self.ips)
+            .field("batch_size", &self.batch_size)
+            .field("tries", &self.tries)
+            .field("port_strategy", &self.port_strategy)
+            .field("exclude_ports", &self.exclude_ports)
+            .field("cancel", &self.cancel)
+            .field("control", &self.control)
+            .field("max_scan_time", &self.max_scan_time)
+            .field("host_timeout", &self.host_timeout)
+            .field("scan_delay", &self.scan_delay)
+            .field("max_host_concurrency", &self.max_host_concurrency)
+            .field("max_host_rate", &self.max_host_rate)
+            .field("retry_backoff", &self.retry_backoff)
+            .field("verify", &self.verify)
+            .field("verify_concurrency", &self.verify_concurrency)
+            .field("probe", &self.probe)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 一个开放的 socket 以及扫描时顺带收集到的信息。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenSocket {
+    pub socket: SocketAddr,
+    /// 开启 banner 抓取时读到的、已经清理过的 banner。
+    pub banner: Option<String>,
+}
+
+impl OpenSocket {
+    pub fn new(socket: SocketAddr) -> Self {
+        Self {
+            socket,
+            banner: None,
+        }
+    }
+}
+
+/// 扫描是否扫完了所有的 socket。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanStatus {
+    Complete,
+    /// 通过 [`CancelHandle`] 取消
+    Cancelled,
+    /// 超过了 `max_scan_time`，或者（单个主机）超过了 `host_timeout`
+    TimedOut,
+    /// 文件描述符耗尽，需要减小批处理大小或者提高 ulimit
+    TooManyOpenFiles,
+}
+
+impl fmt::Display for ScanStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanStatus::Complete => write!(f, "complete"),
+            ScanStatus::Cancelled => write!(f, "cancelled"),
+            ScanStatus::TimedOut => write!(f, "timed out"),
+            ScanStatus::TooManyOpenFiles => write!(f, "ran out of file descriptors"),
+        }
+    }
+}
+
+/// [`Scanner::run_detailed`] 的结果。提前结束时 `open` 只包含已经完成的探测发现的端口。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOutcome {
+    pub open: Vec<OpenSocket>,
+    pub status: ScanStatus,
+    /// 超过 `host_timeout` 后不再扫描的主机
+    pub timed_out_hosts: Vec<IpAddr>,
+    /// 开启确认时，扫描时开放但是没有再次确认的 socket，它们不在 `open` 中
+    pub unconfirmed: Vec<SocketAddr>,
+}
+
+impl ScanOutcome {
+    /// 单个主机的状态：超过 `host_timeout` 的主机是 `TimedOut`，其余的和整个扫描相同。
+    pub fn host_status(&self, ip: IpAddr) -> ScanStatus {
+        if self.timed_out_hosts.contains(&ip) {
+            ScanStatus::TimedOut
+        } else {
+            self.status
+        }
+    }
 }
 
-// 允许过多的参数，为了通过 clippy 检查。
-#[allow(clippy::too_many_arguments)]
 impl Scanner {
+    /// 创建一个使用默认设置的构建器。
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::default()
+    }
+
+    /// 按位置传参创建扫描器，`tries` 为 0 时按 1 处理。
+    #[deprecated(note = "use `Scanner::builder()` instead")]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ips: &[IpAddr],
         batch_size: usize,
@@ -53,23 +201,81 @@ impl Scanner {
         exclude_ports: Vec<u16>,
         udp: bool,
     ) -> Self {
+        let probe = if udp {
+            BuiltinProbe::Udp(UdpProbe::new(timeout))
+        } else {
+            BuiltinProbe::Connect(TcpConnectProbe::new(timeout))
+        };
         Self {
             batch_size,
-            timeout,
             tries: NonZeroU8::new(std::cmp::max(tries, 1)).unwrap(),
-            greppable,
             port_strategy,
             ips: ips.iter().map(ToOwned::to_owned).collect(),
-            accessible,
             exclude_ports,
-            udp,
+            events: Arc::new(CliPrinter::new(greppable, accessible)),
+            cancel: None,
+            control: None,
+            max_scan_time: None,
+            host_timeout: None,
+            scan_delay: Duration::ZERO,
+            max_host_concurrency: None,
+            max_host_rate: None,
+            retry_backoff: RetryBackoff::default(),
+            verify: None,
+            verify_concurrency: DEFAULT_VERIFY_CONCURRENCY,
+            probe,
+        }
+    }
+}
+
+impl<P: Probe> Scanner<P> {
+    /// 使用自定义的扫描技术，其余设置保持不变。
+    pub fn with_probe<Q: Probe>(self, probe: Q) -> Scanner<Q> {
+        Scanner {
+            ips: self.ips,
+            batch_size: self.batch_size,
+            tries: self.tries,
+            port_strategy: self.port_strategy,
+            exclude_ports: self.exclude_ports,
+            events: self.events,
+            cancel: self.cancel,
+            control: self.control,
+            max_scan_time: self.max_scan_time,
+            host_timeout: self.host_timeout,
+            scan_delay: self.scan_delay,
+            max_host_concurrency: self.max_host_concurrency,
+            max_host_rate: self.max_host_rate,
+            retry_backoff: self.retry_backoff,
+            verify: self.verify,
+            verify_concurrency: self.verify_concurrency,
+            probe,
         }
     }
 
     /// 使用块大小运行 scan_range
     /// 如果你想正常运行 RustScan，这是使用的入口点
-    /// 返回所有开放端口作为 `Vec<u16>`
-    pub async fn run(&self) -> Vec<SocketAddr> {
+    /// 返回所有开放端口作为 `Vec<SocketAddr>`
+    ///
+    /// 单个 socket 的错误只会记录到日志。被取消、超时或者文件描述符耗尽的扫描
+    /// 不再发起新的探测，等已经发出的探测结束后返回目前为止发现的端口，
+    /// 状态见 [`ScanOutcome::status`]。
+    pub async fn run(&self) -> Result<Vec<SocketAddr>> {
+        Ok(self
+            .run_detailed()
+            .await?
+            .open
+            .into_iter()
+            .map(|open| open.socket)
+            .collect())
+    }
+
+    /// 和 [`Scanner::run`] 一样，但返回每个开放 socket 的详细信息（例如 banner）以及扫描是否完成。
+    ///
+    /// 开启确认时，扫描结束后（包括超时和文件描述符耗尽）会再探测一次所有开放的端口，
+    /// 只有再次确认的端口留在 `open` 中，[`Event::OpenPort`] 也只在确认之后发出，
+    /// 进度中的开放端口数同样只计算确认的端口。
+    /// 被取消的扫描不再确认，所有开放的端口都算作没有确认。
+    pub async fn run_detailed(&self) -> Result<ScanOutcome> {
         let ports: Vec<u16> = self
             .port_strategy
             .order()
@@ -78,45 +284,246 @@ impl Scanner {
             .copied()
             .collect();
 
+        // 扫描结束时的进度，开启确认时最后的进度事件在确认之后发出
+        let last_progress = Mutex::new(None);
+        let scan = async {
+            let (outcome, progress) = self.scan_ports(&ports).await?;
+            *last_progress.lock().unwrap() = Some(progress);
+            Ok(outcome)
+        };
+        let outcome = self.probe.drive(scan.boxed()).await?;
+        match (&self.verify, last_progress.into_inner().unwrap()) {
+            (Some(verify), Some(progress)) => Ok(self.verify_open(verify, outcome, progress).await),
+            _ => Ok(outcome),
+        }
+    }
+
+    /// 确认开放的端口，然后发出最后的进度事件，其中的开放端口数是确认的端口数。
+    async fn verify_open(
+        &self,
+        verify: &BuiltinProbe,
+        outcome: ScanOutcome,
+        mut progress: Progress,
+    ) -> ScanOutcome {
+        let started = Instant::now();
+        let outcome = self.confirm_open(verify, outcome).await;
+        progress.open = outcome.open.len() as u64;
+        progress.elapsed += started.elapsed();
+        progress.finished = true;
+        self.events.event(Event::Progress(progress));
+        outcome
+    }
+
+    /// 用更长的超时时间和较低的并发重新探测开放的端口，没有再次确认的端口移到 `unconfirmed`，
+    /// 确认的端口发出 [`Event::OpenPort`]。
+    /// 扫描或者确认被取消时，所有端口都移到 `unconfirmed`。
+    async fn confirm_open(&self, verify: &BuiltinProbe, mut outcome: ScanOutcome) -> ScanOutcome {
+        if outcome.status == ScanStatus::Cancelled {
+            outcome.unconfirmed = outcome.open.drain(..).map(|open| open.socket).collect();
+            return outcome;
+        }
+
+        let sockets: Vec<SocketAddr> = outcome.open.iter().map(|open| open.socket).collect();
+        debug!("Verifying {} open sockets", sockets.len());
+        let confirm = probe_concurrently(sockets, self.verify_concurrency, |socket| async move {
+            for _ in 0..self.tries.get() {
+                match verify.probe(socket).await {
+                    Ok(ProbeResult {
+                        state: PortState::Open,
+                        ..
+                    }) => return Some(()),
+                    Ok(ProbeResult {
+                        state: PortState::Closed,
+                        ..
+                    }) => return None,
+                    Ok(_) | Err(_) => {}
+                }
+            }
+            None
+        });
+        let cancelled = async {
+            match &self.cancel {
+                Some(cancel) => cancel.cancelled().await,
+                None => future::pending().await,
+            }
+        };
+        let confirmed = match future::select(confirm.boxed(), cancelled.boxed()).await {
+            Either::Left((confirmed, _)) => confirmed,
+            Either::Right(_) => {
+                outcome.status = ScanStatus::Cancelled;
+                outcome.unconfirmed = outcome.open.drain(..).map(|open| open.socket).collect();
+                return outcome;
+            }
+        };
+
+        let (open, unconfirmed): (Vec<OpenSocket>, Vec<OpenSocket>) = outcome
+            .open
+            .into_iter()
+            .partition(|open| confirmed.contains_key(&open.socket));
+        for open in &open {
+            self.events.event(Event::OpenPort {
+                socket: open.socket,
+                banner: open.banner.clone(),
+            });
+        }
+        outcome.open = open;
+        outcome.unconfirmed = unconfirmed.into_iter().map(|open| open.socket).collect();
+        outcome
+    }
+
+    /// 扫描所有 socket，同时返回结束时的进度。
+    async fn scan_ports(&self, ports: &[u16]) -> Result<(ScanOutcome, Progress)> {
         // SocketIterator 是RustScan 针对socket专门实现的笛卡尔积迭代器，
-        let mut socket_iterator: SocketIterator = SocketIterator::new(&self.ips, &ports);
-        let mut open_sockets: Vec<SocketAddr> = Vec::new();
+        // 需要重试的 socket 排在它后面，超过 host_timeout 的主机的 socket 直接跳过，
+        // 达到单主机上限的主机的 socket 等到它有空的时候再探测
+        let mut queue = SocketQueue::new(
+            SocketIterator::new(&self.ips, ports),
+            HostDeadlines::new(self.host_timeout),
+            HostLimits::new(self.max_host_concurrency, self.max_host_rate),
+        );
+        let mut open_sockets: Vec<OpenSocket> = Vec::new();
+        let mut status = ScanStatus::Complete;
+        let mut stopped = self.stopped().boxed().fuse();
+        let control = self.control.clone().unwrap_or_default();
+        // 有速度限制时下一个探测最早的发起时间
+        let mut next_start = Instant::now();
+
+        // 进度：每隔 PROGRESS_INTERVAL 报告一次，结束时再报告一次
+        let started = Instant::now();
+        let mut last_progress = started;
+        let mut progress = Progress {
+            done: 0,
+            total: (self.ips.len() * ports.len()) as u64,
+            open: 0,
+            errors: 0,
+            elapsed: Duration::ZERO,
+            finished: false,
+        };
 
         //FuturesUnordered 异步任务池,它不会按照你添加的顺序返回，而是按照任务完成的顺序返回
         let mut ftrs = FuturesUnordered::new();
         let mut errors: HashSet<String> = HashSet::new();
 
-        // udp_map 是干嘛的？
-        //因为 udp 协议是无连接的。如果你向一个开放的 udp 端口发送空数据，服务通常会忽略，不回传任何信息，导致扫描器误以为端口是关闭的。
-        // 为了确认端口开放，必须发送特定格式的数据包触发服务的回复
-        // 比如 53端口是DNS ，udp_map会提供一个标准的 DNS 查询包
-        let udp_map = get_parsed_data();
-
-        // 提交一批 batch_size数量的任务到中
-        // 初始化并发池
-        for _ in 0..self.batch_size {
-            if let Some(socket) = socket_iterator.next() {
-                ftrs.push(self.scan_socket(socket, udp_map.clone()));
-            } else {
-                break;
-            }
-        }
-
         debug!("Start scanning sockets. \nBatch size {}\nNumber of ip-s {}\nNumber of ports {}\nTargets all together {} ",
             self.batch_size,
             self.ips.len(),
             &ports.len(),
             (self.ips.len() * ports.len()));
 
-        // 任务池中一个就会空出一个位置，所以 继续socket_iterator.next()向异步任务池中添加
-        // 动态补充任务
-        while let Some(result) = ftrs.next().await {
-            if let Some(socket) = socket_iterator.next() {
-                ftrs.push(self.scan_socket(socket, udp_map.clone()));
+        let mut paused = false;
+        loop {
+            if control.is_paused() != paused {
+                paused = !paused;
+                self.events.event(if paused {
+                    Event::ScanPaused
+                } else {
+                    Event::ScanResumed
+                });
+            }
+
+            // 任务池中一个就会空出一个位置，所以 继续queue.next()向异步任务池中添加
+            // 动态补充任务，直到任务池满、被暂停或者需要等待速度限制、重试时间
+            // 被取消或者超时之后不再补充任务，只等待已经发出的探测结束
+            let mut wait_until = None;
+            while status == ScanStatus::Complete
+                && !control.is_paused()
+                && ftrs.len() < self.batch_size
+            {
+                // 两次发起探测之间的最小间隔，由速度限制和 scan_delay 中较慢的一个决定
+                let interval = control
+                    .rate()
+                    .map_or(Duration::ZERO, |rate| Duration::from_secs(1) / rate)
+                    .max(self.scan_delay);
+                let now = Instant::now();
+                if !interval.is_zero() && next_start > now {
+                    wait_until = Some(next_start);
+                    break;
+                }
+                let (socket, attempt) = match queue.next(now) {
+                    Next::Socket(socket, attempt) => (socket, attempt),
+                    Next::Wait(at) => {
+                        wait_until = Some(at);
+                        break;
+                    }
+                    Next::Blocked | Next::Done => break,
+                };
+                if !interval.is_zero() {
+                    // 间隔很短时最多允许 100ms 的突发，避免 sleep 的精度拖慢高速扫描
+                    let burst = if interval < RATE_BURST {
+                        now.checked_sub(RATE_BURST).unwrap_or(now)
+                    } else {
+                        now
+                    };
+                    next_start = next_start.max(burst) + interval;
+                }
+                ftrs.push(self.scan_socket(socket, attempt));
+            }
+            if ftrs.is_empty() && (queue.is_empty() || status != ScanStatus::Complete) {
+                break;
+            }
+
+            // 等待某个探测完成、扫描被停止、控制状态改变或者到了可以发起下一个探测的时间
+            let completed = async {
+                if ftrs.is_empty() {
+                    future::pending().await
+                } else {
+                    ftrs.next().await
+                }
+            };
+            let woken = future::select(
+                control.changed().boxed(),
+                async {
+                    match wait_until {
+                        Some(at) => task::sleep(at.saturating_duration_since(Instant::now())).await,
+                        None => future::pending().await,
+                    }
+                }
+                .boxed(),
+            );
+            futures::pin_mut!(completed);
+            let (socket, attempt, result) =
+                match future::select(completed, future::select(&mut stopped, woken)).await {
+                    Either::Left((Some(completed), _)) => completed,
+                    Either::Left((None, _)) | Either::Right((Either::Right(_), _)) => continue,
+                    Either::Right((Either::Left((stop, _)), _)) => {
+                        debug!("Scan stopped early: {stop}");
+                        status = stop;
+                        continue;
+                    }
+                };
+
+            queue.finish(socket.ip());
+
+            // 没有结论的 socket 按照退避时间排到队列最后重试
+            if attempt < self.tries.get() && result.as_ref().is_err_and(Error::is_retryable) {
+                let at = Instant::now() + self.retry_backoff.delay(attempt);
+                queue.retry(socket, attempt + 1, at);
+                continue;
+            }
+
+            progress.done += 1;
+            match &result {
+                // 开启确认时，端口在确认之后才计入
+                Ok(_) if self.verify.is_none() => progress.open += 1,
+                Ok(_) => {}
+                Err(Error::Probe { .. }) => progress.errors += 1,
+                Err(_) => {}
+            }
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                progress.elapsed = started.elapsed();
+                self.events.event(Event::Progress(progress));
             }
 
             match result {
                 Ok(socket) => open_sockets.push(socket),
+                Err(Error::TooManyOpenFiles) => {
+                    // 不再发起新的探测，保留已经发现的端口
+                    if status == ScanStatus::Complete {
+                        debug!("Ran out of file descriptors, draining in-flight probes");
+                        status = ScanStatus::TooManyOpenFiles;
+                    }
+                }
                 Err(e) => {
                     let error_string = e.to_string();
                     if errors.len() < self.ips.len() * 1000 {
@@ -125,173 +532,120 @@ impl Scanner {
                 }
             }
         }
+        progress.elapsed = started.elapsed();
+        if self.verify.is_none() {
+            progress.finished = true;
+            self.events.event(Event::Progress(progress));
+        }
+
         debug!("Typical socket connection errors {errors:?}");
         debug!("Open Sockets found: {:?}", &open_sockets);
-        open_sockets
+        let outcome = ScanOutcome {
+            open: open_sockets,
+            status,
+            timed_out_hosts: queue.deadlines.expired,
+            unconfirmed: vec![],
+        };
+        Ok((outcome, progress))
     }
 
-    async fn scan_socket(
-        &self,
-        socket: SocketAddr,
-        udp_map: BTreeMap<Vec<u16>, Vec<u8>>,
-    ) -> io::Result<SocketAddr> {
-        if self.udp {
-            return self.scan_udp_socket(socket, udp_map).await;
-        }
-
-        let tries = self.tries.get();
-        for nr_try in 1..=tries {
-            match self.connect(socket).await {
-                Ok(tcp_stream) => {
-                    debug!(
-                        "Connection was successful, shutting down stream {}",
-                        &socket
-                    );
-                    // 这里为什么要手动关闭tcp_stream？为什么不靠Drop自动回收
-                    // 在高并发情况下，一秒几千个连接，如果不尽快显式关闭，旧连接还没彻底释放，新连接就来了，很快就FD耗尽了
-                    // 细节处才能看出高手
-                    if let Err(e) = tcp_stream.shutdown(Shutdown::Both) {
-                        debug!("Shutdown stream error {}", &e);
-                    }
-                    self.fmt_ports(socket);
-
-                    debug!("Return Ok after {nr_try} tries");
-                    return Ok(socket);
-                }
-                Err(e) => {
-                    let mut error_string = e.to_string();
-
-                    assert!(!error_string.to_lowercase().contains("too many open files"), "Too many open files. Please reduce batch size. The default is 5000. Try -b 2500.");
-
-                    if nr_try == tries {
-                        error_string.push(' ');
-                        error_string.push_str(&socket.ip().to_string());
-                        return Err(io::Error::other(error_string));
-                    }
-                }
-            };
-        }
-        unreachable!();
+    /// 在取消或者超过 `max_scan_time` 时完成，两者都没有设置时永远不会完成。
+    async fn stopped(&self) -> ScanStatus {
+        let cancelled = async {
+            match &self.cancel {
+                Some(cancel) => cancel.cancelled().await,
+                None => future::pending().await,
+            }
+            ScanStatus::Cancelled
+        };
+        let timed_out = async {
+            match self.max_scan_time {
+                Some(max_scan_time) => task::sleep(max_scan_time).await,
+                None => future::pending().await,
+            }
+            ScanStatus::TimedOut
+        };
+        future::select(cancelled.boxed(), timed_out.boxed())
+            .await
+            .factor_first()
+            .0
     }
 
-    async fn scan_udp_socket(
+    /// 用扫描技术对 `socket` 进行第 `attempt` 次探测，是否重试由调用方决定。
+    async fn scan_socket(
         &self,
         socket: SocketAddr,
-        udp_map: BTreeMap<Vec<u16>, Vec<u8>>,
-    ) -> io::Result<SocketAddr> {
-        let mut payload: Vec<u8> = Vec::new();
-        for (key, value) in udp_map {
-            if key.contains(&socket.port()) {
-                payload = value;
-            }
-        }
-
-        let tries = self.tries.get();
-        for _ in 1..=tries {
-            match self.udp_scan(socket, &payload, self.timeout).await {
-                Ok(true) => return Ok(socket),
-                Ok(false) => continue,
-                Err(e) => return Err(e),
+        attempt: u8,
+    ) -> (SocketAddr, u8, Result<OpenSocket>) {
+        let result = match self.probe.probe(socket).await {
+            Ok(ProbeResult {
+                state: PortState::Open,
+                data,
+            }) => {
+                // 开启确认时等到确认之后再发出事件
+                if self.verify.is_none() {
+                    self.events.event(Event::OpenPort {
+                        socket,
+                        banner: data.clone(),
+                    });
+                }
+                debug!("Return Ok after {attempt} tries");
+                Ok(OpenSocket {
+                    socket,
+                    banner: data,
+                })
             }
-        }
-
-        Err(io::Error::other(format!(
-            "UDP scan timed-out for all tries on socket {socket}"
-        )))
+            Ok(ProbeResult {
+                state: PortState::Closed,
+                ..
+            }) => Err(Error::PortClosed(socket)),
+            Ok(ProbeResult {
+                state: PortState::Filtered,
+                ..
+            }) => Err(Error::NoResponse(socket)),
+            Err(e) => Err(Error::probe(socket, e)),
+        };
+        (socket, attempt, result)
     }
+}
 
-    async fn connect(&self, socket: SocketAddr) -> io::Result<TcpStream> {
-        let stream = io::timeout(
-            self.timeout,
-            async move { TcpStream::connect(socket).await },
-        )
-        .await?;
-        Ok(stream)
-    }
+/// 对一组目标并发执行 `probe`，同时最多运行 `concurrency` 个，只收集返回 `Some` 的结果。
+///
+/// 扫描之前的主机发现和扫描之后的各个阶段（服务识别、TLS 探测……）都用它来控制并发，
+/// 做法和 [`Scanner::run`] 一样：完成一个任务就补充一个新任务。
+pub(crate) async fn probe_concurrently<K, T, F, Fut>(
+    targets: impl IntoIterator<Item = K>,
+    concurrency: usize,
+    probe: F,
+) -> HashMap<K, T>
+where
+    K: Hash + Eq + Copy,
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let run = |target: K| {
+        let ftr = probe(target);
+        async move { (target, ftr.await) }
+    };
 
-    /// 绑定到 UDP socket 以便我们可以发送和接收数据包
-    /// # 示例
-    ///
-    /// ```compile_fail
-    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
-    /// let port: u16 = 80;
-    /// // ip 是 IpAddr 类型
-    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
-    /// let socket = SocketAddr::new(ip, port);
-    /// scanner.udp_bind(socket);
-    /// // 返回 Result，如果是 Ok(stream) 表示端口开放，Err 表示端口关闭。
-    /// // 超时发生在 self.timeout 秒后
-    /// ```
-    ///
-    async fn udp_bind(&self, socket: SocketAddr) -> io::Result<UdpSocket> {
-        let local_addr = match socket {
-            SocketAddr::V4(_) => "0.0.0.0:0".parse::<SocketAddr>().unwrap(),
-            SocketAddr::V6(_) => "[::]:0".parse::<SocketAddr>().unwrap(),
-        };
+    let mut results = HashMap::new();
+    let mut pending = targets.into_iter();
+    let mut ftrs = FuturesUnordered::new();
 
-        UdpSocket::bind(local_addr).await
+    for target in pending.by_ref().take(concurrency.max(1)) {
+        ftrs.push(run(target));
     }
 
-    /// 在指定的 socket 上执行 UDP 扫描，带有有效载荷和等待时间
-    /// # 示例
-    ///
-    /// ```compile_fail
-    /// # use std::net::{IpAddr, Ipv6Addr, SocketAddr};
-    /// # use std::time::Duration;
-    /// let port: u16 = 123;
-    /// // ip 是 IpAddr 类型
-    /// let ip = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
-    /// let socket = SocketAddr::new(ip, port);
-    /// let payload = vec![0, 1, 2, 3];
-    /// let wait = Duration::from_secs(1);
-    /// let result = scanner.udp_scan(socket, payload, wait).await;
-    /// // 返回 Result，如果是 Ok(true) 表示收到响应，Ok(false) 表示超时。
-    /// // Err 返回其他 I/O 错误。
-    async fn udp_scan(
-        &self,
-        socket: SocketAddr,
-        payload: &[u8],
-        wait: Duration,
-    ) -> io::Result<bool> {
-        match self.udp_bind(socket).await {
-            Ok(udp_socket) => {
-                let mut buf = [0u8; 1024];
-
-                udp_socket.connect(socket).await?;
-                udp_socket.send(payload).await?;
-
-                match io::timeout(wait, udp_socket.recv(&mut buf)).await {
-                    Ok(size) => {
-                        debug!("Received {size} bytes");
-                        self.fmt_ports(socket);
-                        Ok(true)
-                    }
-                    Err(e) => {
-                        if e.kind() == io::ErrorKind::TimedOut {
-                            Ok(false)
-                        } else {
-                            Err(e)
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Err E binding sock {e:?}");
-                Err(e)
-            }
+    while let Some((target, result)) = ftrs.next().await {
+        if let Some(target) = pending.next() {
+            ftrs.push(run(target));
         }
-    }
-
-    /// 格式化并打印端口状态
-    fn fmt_ports(&self, socket: SocketAddr) {
-        if !self.greppable {
-            if self.accessible {
-                println!("Open {socket}");
-            } else {
-                println!("Open {}", socket.to_string().purple());
-            }
+        if let Some(result) = result {
+            results.insert(target, result);
         }
     }
+
+    results
 }
 
 #[cfg(test)]
@@ -299,8 +653,20 @@ mod tests {
     use super::*;
     use crate::input::{PortRange, ScanOrder};
     use async_std::task::block_on;
+    use futures::future::BoxFuture;
+    use std::sync::Mutex;
     use std::{net::IpAddr, time::Duration};
 
+    /// 把收到的事件收集起来。
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<Event>>);
+
+    impl EventSink for Collect {
+        fn event(&self, event: Event) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
     #[test]
     fn scanner_runs() {
         // Makes sure the program still runs and doesn't panic
@@ -310,18 +676,17 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            false,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
         assert_eq!(1, 1);
     }
@@ -334,18 +699,17 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            false,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
         assert_eq!(1, 1);
     }
@@ -357,18 +721,17 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            false,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         assert_eq!(1, 1);
     }
     #[test]
@@ -379,18 +742,17 @@ mod tests {
             end: 445,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            false,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         assert_eq!(1, 1);
     }
     #[test]
@@ -404,18 +766,17 @@ mod tests {
             end: 600,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            false,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         assert_eq!(1, 1);
     }
 
@@ -428,18 +789,18 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            true,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .udp(true)
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
         assert_eq!(1, 1);
     }
@@ -452,18 +813,18 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            true,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .udp(true)
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         // if the scan fails, it wouldn't be able to assert_eq! as it panicked!
         assert_eq!(1, 1);
     }
@@ -475,18 +836,18 @@ mod tests {
             end: 1_000,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            true,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .udp(true)
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         assert_eq!(1, 1);
     }
     #[test]
@@ -497,18 +858,458 @@ mod tests {
             end: 150,
         };
         let strategy = PortStrategy::pick(&Some(range), None, ScanOrder::Random);
-        let scanner = Scanner::new(
-            &addrs,
-            10,
-            Duration::from_millis(100),
-            1,
-            true,
-            strategy,
-            true,
-            vec![9000],
-            true,
-        );
-        block_on(scanner.run());
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(100))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .exclude_ports(vec![9000])
+            .udp(true)
+            .build()
+            .unwrap();
+        block_on(scanner.run()).unwrap();
         assert_eq!(1, 1);
     }
+    #[test]
+    fn banner_grab_reads_greeting() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-RustScanTest\r\n").unwrap();
+        });
+
+        let addrs = vec!["127.0.0.1".parse::<IpAddr>().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some(vec![port]), ScanOrder::Serial);
+        let scanner = Scanner::builder()
+            .ips(addrs.clone())
+            .batch_size(10)
+            .timeout(Duration::from_millis(500))
+            .greppable(true)
+            .port_strategy(strategy)
+            .accessible(true)
+            .banner(BannerOptions::new(Duration::from_millis(500), vec![]))
+            .build()
+            .unwrap();
+
+        let result = block_on(scanner.run_detailed()).unwrap().open;
+        assert_eq!(
+            result,
+            vec![OpenSocket {
+                socket: SocketAddr::new(addrs[0], port),
+                banner: Some("SSH-2.0-RustScanTest".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn open_ports_are_sent_to_events() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+
+        let events = Arc::new(Collect::default());
+        let strategy = PortStrategy::pick(&None, Some(vec![socket.port()]), ScanOrder::Serial);
+        let scanner = Scanner::builder()
+            .ips(vec![socket.ip()])
+            .batch_size(10)
+            .timeout(Duration::from_millis(500))
+            .port_strategy(strategy)
+            .events(events.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(block_on(scanner.run()).unwrap(), vec![socket]);
+        let events = events.0.lock().unwrap();
+        assert_eq!(
+            events[0],
+            Event::OpenPort {
+                socket,
+                banner: None
+            }
+        );
+        assert!(matches!(
+            events.last(),
+            Some(Event::Progress(Progress {
+                done: 1,
+                total: 1,
+                open: 1,
+                finished: true,
+                ..
+            }))
+        ));
+    }
+
+    /// 每次探测都要等待一段时间，所有端口都开放。
+    struct SlowProbe(Duration);
+
+    impl Probe for SlowProbe {
+        fn probe(&self, _socket: SocketAddr) -> BoxFuture<'_, std::io::Result<ProbeResult>> {
+            async move {
+                task::sleep(self.0).await;
+                Ok(ProbeResult::open(None))
+            }
+            .boxed()
+        }
+    }
+
+    fn slow_scanner() -> ScannerBuilder {
+        let strategy = PortStrategy::pick(&None, Some((1..=1_000).collect()), ScanOrder::Serial);
+        Scanner::builder()
+            .ips(vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()])
+            .batch_size(4)
+            .port_strategy(strategy)
+            .events(Arc::new(crate::event::NullSink))
+    }
+
+    #[test]
+    fn max_scan_time_returns_partial_results() {
+        let scanner = slow_scanner()
+            .max_scan_time(Duration::from_millis(100))
+            .build_with_probe(SlowProbe(Duration::from_millis(20)))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::TimedOut);
+        assert!(!outcome.open.is_empty());
+        assert!(outcome.open.len() < 2_000);
+    }
+
+    #[test]
+    fn cancelled_scan_stops() {
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let scanner = slow_scanner()
+            .cancel_handle(cancel)
+            .build_with_probe(SlowProbe(Duration::from_millis(20)))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::Cancelled);
+        assert!(outcome.open.len() <= 4);
+    }
+
+    #[test]
+    fn host_timeout_skips_remaining_ports() {
+        let scanner = slow_scanner()
+            .host_timeout(Duration::from_millis(100))
+            .build_with_probe(SlowProbe(Duration::from_millis(20)))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::Complete);
+        assert_eq!(outcome.timed_out_hosts.len(), 2);
+        assert_eq!(
+            outcome.host_status("::1".parse().unwrap()),
+            ScanStatus::TimedOut
+        );
+        assert!(outcome.open.len() < 2_000);
+    }
+
+    #[test]
+    fn paused_scan_resumes() {
+        let control = ScanControl::new();
+        control.pause();
+        let events = Arc::new(Collect::default());
+        let scanner = slow_scanner()
+            .control(control.clone())
+            .events(events.clone())
+            .build_with_probe(SlowProbe(Duration::ZERO))
+            .unwrap();
+
+        let resume = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(control.is_paused());
+            control.resume();
+        });
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+        resume.join().unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::Complete);
+        assert_eq!(outcome.open.len(), 2_000);
+        let events = events.0.lock().unwrap();
+        assert_eq!(events[0], Event::ScanPaused);
+        assert_eq!(events[1], Event::ScanResumed);
+    }
+
+    #[test]
+    fn rate_limits_new_probes() {
+        let control = ScanControl::new();
+        control.set_rate(Some(200));
+        let scanner = slow_scanner()
+            .control(control)
+            .max_scan_time(Duration::from_millis(500))
+            .build_with_probe(SlowProbe(Duration::ZERO))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::TimedOut);
+        assert!(outcome.open.len() < 150, "{}", outcome.open.len());
+    }
+
+    /// 记录每个 socket 被探测的次数，第一次返回 `first`，之后端口开放。
+    struct FlakyProbe {
+        first: PortState,
+        attempts: Mutex<HashMap<SocketAddr, u8>>,
+    }
+
+    impl FlakyProbe {
+        fn new(first: PortState) -> Self {
+            Self {
+                first,
+                attempts: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl Probe for FlakyProbe {
+        fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, std::io::Result<ProbeResult>> {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(socket).or_default();
+            *attempt += 1;
+            let state = if *attempt == 1 {
+                self.first
+            } else {
+                PortState::Open
+            };
+            future::ready(Ok(ProbeResult::new(state))).boxed()
+        }
+    }
+
+    fn retrying_scanner(first: PortState) -> Scanner<FlakyProbe> {
+        let strategy = PortStrategy::pick(&None, Some((1..=50).collect()), ScanOrder::Serial);
+        Scanner::builder()
+            .ips(vec!["127.0.0.1".parse().unwrap()])
+            .port_strategy(strategy)
+            .tries(3)
+            .retry_backoff(RetryBackoff::new(Duration::from_millis(10), 0))
+            .events(Arc::new(crate::event::NullSink))
+            .build_with_probe(FlakyProbe::new(first))
+            .unwrap()
+    }
+
+    #[test]
+    fn unanswered_probes_are_retried() {
+        let scanner = retrying_scanner(PortState::Filtered);
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.open.len(), 50);
+        let attempts = scanner.probe.attempts.lock().unwrap();
+        assert!(attempts.values().all(|&attempt| attempt == 2));
+    }
+
+    #[test]
+    fn closed_ports_are_not_retried() {
+        let scanner = retrying_scanner(PortState::Closed);
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert!(outcome.open.is_empty());
+        let attempts = scanner.probe.attempts.lock().unwrap();
+        assert!(attempts.values().all(|&attempt| attempt == 1));
+    }
+
+    /// 端口号大于给定值的探测都因为文件描述符耗尽而失败，其余端口开放。
+    #[cfg(unix)]
+    struct ExhaustedProbe(u16);
+
+    #[cfg(unix)]
+    impl Probe for ExhaustedProbe {
+        fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, std::io::Result<ProbeResult>> {
+            let result = if socket.port() > self.0 {
+                Err(std::io::Error::from_raw_os_error(libc::EMFILE))
+            } else {
+                Ok(ProbeResult::open(None))
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fd_exhaustion_keeps_partial_results() {
+        let strategy = PortStrategy::pick(&None, Some((1..=100).collect()), ScanOrder::Serial);
+        let scanner = Scanner::builder()
+            .ips(vec!["127.0.0.1".parse().unwrap()])
+            .port_strategy(strategy)
+            .batch_size(1)
+            .events(Arc::new(crate::event::NullSink))
+            .build_with_probe(ExhaustedProbe(10))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::TooManyOpenFiles);
+        assert_eq!(outcome.open.len(), 10);
+    }
+
+    #[test]
+    fn scan_delay_spaces_out_probes() {
+        let scanner = slow_scanner()
+            .scan_delay(Duration::from_millis(100))
+            .max_scan_time(Duration::from_millis(450))
+            .build_with_probe(SlowProbe(Duration::ZERO))
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::TimedOut);
+        assert!(
+            (3..=5).contains(&outcome.open.len()),
+            "{}",
+            outcome.open.len()
+        );
+    }
+
+    /// 记录每个主机同时进行的探测数的最大值。
+    #[derive(Default)]
+    struct ConcurrencyProbe {
+        in_flight: Mutex<HashMap<IpAddr, usize>>,
+        max: Mutex<HashMap<IpAddr, usize>>,
+    }
+
+    impl Probe for ConcurrencyProbe {
+        fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, std::io::Result<ProbeResult>> {
+            async move {
+                {
+                    let mut in_flight = self.in_flight.lock().unwrap();
+                    let count = in_flight.entry(socket.ip()).or_default();
+                    *count += 1;
+                    let mut max = self.max.lock().unwrap();
+                    let max = max.entry(socket.ip()).or_default();
+                    *max = (*max).max(*count);
+                }
+                task::sleep(Duration::from_millis(5)).await;
+                *self
+                    .in_flight
+                    .lock()
+                    .unwrap()
+                    .get_mut(&socket.ip())
+                    .unwrap() -= 1;
+                Ok(ProbeResult::new(PortState::Open))
+            }
+            .boxed()
+        }
+    }
+
+    #[test]
+    fn max_host_concurrency_caps_each_host() {
+        let ips: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let strategy = PortStrategy::pick(&None, Some((1..=20).collect()), ScanOrder::Serial);
+        let scanner = Scanner::builder()
+            .ips(ips.clone())
+            .port_strategy(strategy)
+            .batch_size(20)
+            .max_host_concurrency(3)
+            .events(Arc::new(crate::event::NullSink))
+            .build_with_probe(ConcurrencyProbe::default())
+            .unwrap();
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.open.len(), 40);
+        let max = scanner.probe.max.lock().unwrap();
+        assert_eq!(max[&ips[0]], 3);
+        assert_eq!(max[&ips[1]], 3);
+    }
+
+    #[test]
+    fn verify_moves_unconfirmed_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        // 探测报告两个端口都开放，确认时只有仍在监听的端口能连接上
+        let strategy = PortStrategy::pick(
+            &None,
+            Some(vec![open.port(), closed.port()]),
+            ScanOrder::Serial,
+        );
+        let events = Arc::new(Collect::default());
+        let mut scanner = Scanner::builder()
+            .ips(vec![open.ip()])
+            .port_strategy(strategy)
+            .events(events.clone())
+            .build_with_probe(SlowProbe(Duration::ZERO))
+            .unwrap();
+        scanner.verify = Some(BuiltinProbe::Connect(TcpConnectProbe::new(
+            Duration::from_secs(1),
+        )));
+
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+
+        assert_eq!(outcome.status, ScanStatus::Complete);
+        assert_eq!(outcome.open, vec![OpenSocket::new(open)]);
+        assert_eq!(outcome.unconfirmed, vec![closed]);
+        // 只有确认的端口发出了事件
+        let open_events: Vec<Event> = events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, Event::OpenPort { .. }))
+            .cloned()
+            .collect();
+        assert_eq!(
+            open_events,
+            vec![Event::OpenPort {
+                socket: open,
+                banner: None
+            }]
+        );
+        // 进度只计算确认的端口，最后的进度事件在确认之后发出
+        let events = events.0.lock().unwrap();
+        let progress: Vec<Progress> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Progress(progress) => Some(*progress),
+                _ => None,
+            })
+            .collect();
+        assert!(progress.iter().all(|p| p.open <= 1));
+        assert_eq!(progress.iter().filter(|p| p.finished).count(), 1);
+        let last = progress.last().unwrap();
+        assert!(last.finished);
+        assert_eq!((last.done, last.open), (2, 1));
+        assert!(matches!(events.last(), Some(Event::Progress(_))));
+    }
+
+    #[test]
+    fn verify_checks_partial_scans() {
+        let verify = || {
+            Some(BuiltinProbe::Connect(TcpConnectProbe::new(
+                Duration::from_millis(200),
+            )))
+        };
+
+        // 超时的扫描同样会确认，本机上的端口 1 到 1000 基本都没有监听
+        let mut scanner = slow_scanner()
+            .max_scan_time(Duration::from_millis(100))
+            .build_with_probe(SlowProbe(Duration::from_millis(20)))
+            .unwrap();
+        scanner.verify = verify();
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+        assert_eq!(outcome.status, ScanStatus::TimedOut);
+        assert!(!outcome.unconfirmed.is_empty());
+
+        // 被取消的扫描不再确认，所有端口都没有确认
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let mut scanner = slow_scanner()
+            .cancel_handle(cancel)
+            .build_with_probe(SlowProbe(Duration::from_millis(20)))
+            .unwrap();
+        scanner.verify = verify();
+        let outcome = block_on(scanner.run_detailed()).unwrap();
+        assert_eq!(outcome.status, ScanStatus::Cancelled);
+        assert!(outcome.open.is_empty());
+    }
 }
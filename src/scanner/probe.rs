@@ -0,0 +1,397 @@
+//! 可插拔的扫描技术。
+//!
+//! [`Scanner`](super::Scanner) 负责并发、重试和输出，具体怎样判断一个端口是否开放
+//! 由 [`Probe`] 决定。内置的实现有 TCP 连接扫描、带载荷的 UDP 扫描以及
+//! （Linux 上）SYN 扫描；库的使用者可以实现自己的 `Probe`，
+//! 例如完成一次应用层握手，再通过 [`Scanner::with_probe`](super::Scanner::with_probe) 使用它。
+//!
+//! ```rust
+//! # use futures::future::{BoxFuture, FutureExt};
+//! # use rustscan::scanner::{PortState, Probe, ProbeResult};
+//! # use std::io;
+//! # use std::net::SocketAddr;
+//! /// 只认为 80 端口开放的探测。
+//! struct OnlyHttp;
+//!
+//! impl Probe for OnlyHttp {
+//!     fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+//!         async move {
+//!             Ok(if socket.port() == 80 {
+//!                 ProbeResult::open(Some("http".to_owned()))
+//!             } else {
+//!                 ProbeResult::new(PortState::Closed)
+//!             })
+//!         }
+//!         .boxed()
+//!     }
+//! }
+//! ```
+use super::banner::{self, BannerOptions};
+use super::{Proxy, ScanOutcome, SourceOptions};
+use crate::generated::get_parsed_data;
+use async_std::io;
+use async_std::net::TcpStream;
+use futures::future::{BoxFuture, FutureExt};
+use log::debug;
+use std::net::{Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use super::SynScanner;
+
+/// 一次探测得到的端口状态。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    Open,
+    /// 对方明确拒绝（例如 TCP RST），重试没有意义。
+    Closed,
+    /// 没有任何响应，可能被防火墙丢弃，会按照 `tries` 重试。
+    Filtered,
+}
+
+/// 探测结果：端口状态以及探测时顺带得到的数据（例如 banner）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub state: PortState,
+    pub data: Option<String>,
+}
+
+impl ProbeResult {
+    pub fn new(state: PortState) -> Self {
+        Self { state, data: None }
+    }
+
+    pub fn open(data: Option<String>) -> Self {
+        Self {
+            state: PortState::Open,
+            data,
+        }
+    }
+}
+
+/// 一种扫描技术。
+pub trait Probe: Send + Sync {
+    /// 探测一个 socket。返回 `Err` 表示探测本身出错（例如无法创建套接字），会被重试。
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>>;
+
+    /// 包裹整个扫描过程，需要后台任务的技术（例如接收原始套接字的响应）可以在这里驱动它。
+    /// 默认直接返回 `scan`。
+    fn drive<'a>(
+        &'a self,
+        scan: BoxFuture<'a, crate::Result<ScanOutcome>>,
+    ) -> BoxFuture<'a, crate::Result<ScanOutcome>> {
+        scan
+    }
+}
+
+impl<P: Probe + ?Sized> Probe for Box<P> {
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+        (**self).probe(socket)
+    }
+
+    fn drive<'a>(
+        &'a self,
+        scan: BoxFuture<'a, crate::Result<ScanOutcome>>,
+    ) -> BoxFuture<'a, crate::Result<ScanOutcome>> {
+        (**self).drive(scan)
+    }
+}
+
+/// TCP 连接扫描：完成一次完整的三次握手，可以顺便抓取 banner。
+#[derive(Debug, Clone)]
+pub struct TcpConnectProbe {
+    timeout: Duration,
+    banner: Option<BannerOptions>,
+    source: SourceOptions,
+    proxy: Option<Proxy>,
+    /// 已经发起的连接数，用来轮流选择源地址
+    connects: Arc<AtomicUsize>,
+}
+
+impl TcpConnectProbe {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            banner: None,
+            source: SourceOptions::default(),
+            proxy: None,
+            connects: Arc::default(),
+        }
+    }
+
+    /// 连接成功后抓取 banner，而不是立即关闭连接。
+    #[must_use]
+    pub fn with_banner(mut self, options: BannerOptions) -> Self {
+        self.banner = Some(options);
+        self
+    }
+
+    /// 从指定的源地址、源端口或网络接口发起连接。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 通过代理连接，banner 抓取也经过同一个隧道。
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub(crate) async fn connect(&self, socket: SocketAddr) -> io::Result<TcpStream> {
+        let nth = self.connects.fetch_add(1, Ordering::Relaxed);
+        let connect = async {
+            match &self.proxy {
+                Some(proxy) => proxy.connect(socket, &self.source, nth).await,
+                None => self.source.connect(socket, nth).await,
+            }
+        };
+        io::timeout(self.timeout, connect).await
+    }
+
+    async fn probe_socket(&self, socket: SocketAddr) -> io::Result<ProbeResult> {
+        let mut tcp_stream = match self.connect(socket).await {
+            Ok(tcp_stream) => tcp_stream,
+            Err(e) => {
+                return match e.kind() {
+                    io::ErrorKind::ConnectionRefused => Ok(ProbeResult::new(PortState::Closed)),
+                    io::ErrorKind::TimedOut => Ok(ProbeResult::new(PortState::Filtered)),
+                    _ => Err(e),
+                };
+            }
+        };
+
+        let mut data = None;
+        if let Some(options) = &self.banner {
+            data = banner::grab_banner(&mut tcp_stream, options).await;
+        }
+
+        debug!(
+            "Connection was successful, shutting down stream {}",
+            &socket
+        );
+        // 这里为什么要手动关闭tcp_stream？为什么不靠Drop自动回收
+        // 在高并发情况下，一秒几千个连接，如果不尽快显式关闭，旧连接还没彻底释放，新连接就来了，很快就FD耗尽了
+        // 细节处才能看出高手
+        if let Err(e) = tcp_stream.shutdown(Shutdown::Both) {
+            debug!("Shutdown stream error {}", &e);
+        }
+        Ok(ProbeResult::open(data))
+    }
+}
+
+impl Probe for TcpConnectProbe {
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+        self.probe_socket(socket).boxed()
+    }
+}
+
+/// UDP 扫描：发送和端口对应的载荷，收到任何响应都说明端口开放。
+///
+/// UDP 是无连接的，如果向开放的 UDP 端口发送空数据，服务通常会忽略，不回传任何信息，
+/// 所以必须发送特定格式的数据包触发服务的回复，比如 53 端口会发送一个标准的 DNS 查询。
+#[derive(Debug, Clone)]
+pub struct UdpProbe {
+    timeout: Duration,
+    source: SourceOptions,
+    /// 已经绑定的 socket 数，用来轮流选择源地址
+    binds: Arc<AtomicUsize>,
+}
+
+impl UdpProbe {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            source: SourceOptions::default(),
+            binds: Arc::default(),
+        }
+    }
+
+    /// 从指定的源地址、源端口或网络接口发送探测。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 端口对应的载荷，没有时发送空数据。
+    fn payload(port: u16) -> &'static [u8] {
+        get_parsed_data()
+            .iter()
+            .rev()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(_, payload)| payload.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// 在指定的 socket 上执行 UDP 扫描，收到响应时端口开放，超时则没有结论。
+    async fn probe_socket(&self, socket: SocketAddr) -> io::Result<ProbeResult> {
+        let nth = self.binds.fetch_add(1, Ordering::Relaxed);
+        let udp_socket = match self.source.udp_bind(socket, nth).await {
+            Ok(udp_socket) => udp_socket,
+            Err(e) => {
+                debug!("Err E binding sock {e:?}");
+                return Err(e);
+            }
+        };
+        let mut buf = [0u8; 1024];
+
+        udp_socket.connect(socket).await?;
+        udp_socket.send(Self::payload(socket.port())).await?;
+
+        match io::timeout(self.timeout, udp_socket.recv(&mut buf)).await {
+            Ok(size) => {
+                debug!("Received {size} bytes");
+                Ok(ProbeResult::open(None))
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                Ok(ProbeResult::new(PortState::Filtered))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Probe for UdpProbe {
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+        self.probe_socket(socket).boxed()
+    }
+}
+
+/// SYN 扫描，开启 banner 抓取时会对开放的端口再连接一次。
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct SynProbe {
+    syn: SynScanner,
+    connect: TcpConnectProbe,
+}
+
+#[cfg(target_os = "linux")]
+impl SynProbe {
+    /// `connect` 提供超时时间和 banner 选项。
+    pub fn new(syn: SynScanner, connect: TcpConnectProbe) -> Self {
+        Self { syn, connect }
+    }
+
+    /// 对开放的端口再连接一次，抓取 banner。
+    #[must_use]
+    pub fn with_banner(mut self, options: BannerOptions) -> Self {
+        self.connect = self.connect.with_banner(options);
+        self
+    }
+
+    async fn probe_socket(&self, socket: SocketAddr) -> io::Result<ProbeResult> {
+        match self.syn.probe(socket, self.connect.timeout).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                return Ok(ProbeResult::new(PortState::Closed));
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                return Ok(ProbeResult::new(PortState::Filtered));
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut data = None;
+        if let Some(options) = &self.connect.banner {
+            if let Ok(mut tcp_stream) = self.connect.connect(socket).await {
+                data = banner::grab_banner(&mut tcp_stream, options).await;
+                let _ = tcp_stream.shutdown(Shutdown::Both);
+            }
+        }
+        Ok(ProbeResult::open(data))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Probe for SynProbe {
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+        self.probe_socket(socket).boxed()
+    }
+
+    fn drive<'a>(
+        &'a self,
+        scan: BoxFuture<'a, crate::Result<ScanOutcome>>,
+    ) -> BoxFuture<'a, crate::Result<ScanOutcome>> {
+        self.syn.drive(scan).boxed()
+    }
+}
+
+/// 命令行使用的内置技术，由 [`Scanner::new`](super::Scanner::new) 根据选项选择。
+#[derive(Debug)]
+pub enum BuiltinProbe {
+    Connect(TcpConnectProbe),
+    Udp(UdpProbe),
+    #[cfg(target_os = "linux")]
+    Syn(SynProbe),
+}
+
+impl Probe for BuiltinProbe {
+    fn probe(&self, socket: SocketAddr) -> BoxFuture<'_, io::Result<ProbeResult>> {
+        match self {
+            BuiltinProbe::Connect(probe) => probe.probe(socket),
+            BuiltinProbe::Udp(probe) => probe.probe(socket),
+            #[cfg(target_os = "linux")]
+            BuiltinProbe::Syn(probe) => probe.probe(socket),
+        }
+    }
+
+    fn drive<'a>(
+        &'a self,
+        scan: BoxFuture<'a, crate::Result<ScanOutcome>>,
+    ) -> BoxFuture<'a, crate::Result<ScanOutcome>> {
+        match self {
+            BuiltinProbe::Connect(probe) => probe.drive(scan),
+            BuiltinProbe::Udp(probe) => probe.drive(scan),
+            #[cfg(target_os = "linux")]
+            BuiltinProbe::Syn(probe) => probe.drive(scan),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PortState, Probe, TcpConnectProbe, UdpProbe};
+    use async_std::net::{TcpListener, UdpSocket};
+    use async_std::task::{self, block_on};
+    use std::time::Duration;
+
+    #[test]
+    fn connect_probe_reports_open_and_closed() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let socket = listener.local_addr().unwrap();
+            let probe = TcpConnectProbe::new(Duration::from_millis(500));
+
+            assert_eq!(probe.probe(socket).await.unwrap().state, PortState::Open);
+            drop(listener);
+            assert_eq!(probe.probe(socket).await.unwrap().state, PortState::Closed);
+        });
+    }
+
+    #[test]
+    fn udp_probe_needs_a_reply() {
+        block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let socket = server.local_addr().unwrap();
+            let probe = UdpProbe::new(Duration::from_millis(200));
+
+            assert_eq!(
+                probe.probe(socket).await.unwrap().state,
+                PortState::Filtered
+            );
+
+            task::spawn(async move {
+                let mut buf = [0u8; 64];
+                while let Ok((_, peer)) = server.recv_from(&mut buf).await {
+                    let _ = server.send_to(b"pong", peer).await;
+                }
+            });
+            assert_eq!(probe.probe(socket).await.unwrap().state, PortState::Open);
+        });
+    }
+}
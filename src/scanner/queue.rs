@@ -0,0 +1,387 @@
+//! 等待探测的 socket：先是还没有探测过的 socket，之后是到了重试时间的 socket。
+//!
+//! 主机达到单主机并发数或者速度上限时，它的 socket 暂时放在一边，
+//! 其它主机的 socket 继续占用批处理的位置。
+use super::SocketIterator;
+use log::debug;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::iter::Fuse;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// 重试之前等待的时间。
+///
+/// 第 n 次重试之前等待 `base * 2^(n-1)`，最多 `max`，再随机增加最多 `jitter` 百分比的时间，
+/// 避免大量重试同时发出。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryBackoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: u8,
+}
+
+impl RetryBackoff {
+    pub fn new(base: Duration, jitter: u8) -> Self {
+        Self {
+            base,
+            jitter,
+            ..Self::default()
+        }
+    }
+
+    /// 第 `retry` 次重试（从 1 开始）之前等待的时间。
+    pub fn delay(&self, retry: u8) -> Duration {
+        let shift = u32::from(retry.saturating_sub(1)).min(31);
+        let delay = self.base.saturating_mul(1 << shift).min(self.max);
+        let jitter = f64::from(self.jitter.min(100)) / 100.0;
+        delay + delay.mul_f64(rand::random_range(0.0..=jitter))
+    }
+}
+
+impl Default for RetryBackoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: 20,
+        }
+    }
+}
+
+/// 最多暂时放在一边的 socket 数，超过之后不再取出新的 socket，
+/// 避免只扫描少数几个主机时把所有 socket 都读进内存。
+const MAX_DEFERRED: usize = 65_536;
+
+/// [`SocketQueue::next`] 的结果。
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Next {
+    /// 下一个要探测的 socket 以及这是第几次尝试
+    Socket(SocketAddr, u8),
+    /// 只剩下还没到时间的重试，或者主机的速度上限
+    Wait(Instant),
+    /// 剩下的 socket 都在等待它们主机上正在进行的探测结束
+    Blocked,
+    /// 所有 socket 都已经发出
+    Done,
+}
+
+/// 记录每个主机第一次被探测的时间，超过 `host_timeout` 的主机不再发起新的探测。
+pub(super) struct HostDeadlines {
+    timeout: Option<Duration>,
+    started: HashMap<IpAddr, Instant>,
+    pub(super) expired: Vec<IpAddr>,
+}
+
+impl HostDeadlines {
+    pub(super) fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            started: HashMap::new(),
+            expired: Vec::new(),
+        }
+    }
+
+    /// 是否还可以探测 `ip`。
+    fn admit(&mut self, ip: IpAddr) -> bool {
+        let Some(timeout) = self.timeout else {
+            return true;
+        };
+        let started = *self.started.entry(ip).or_insert_with(Instant::now);
+        if started.elapsed() < timeout {
+            return true;
+        }
+        if !self.expired.contains(&ip) {
+            debug!("Host {ip} exceeded its deadline");
+            self.expired.push(ip);
+        }
+        false
+    }
+}
+
+/// 每个主机同时进行的探测数和发起探测的速度上限，保护脆弱的单个主机（嵌入式设备、老旧的防火墙）。
+#[derive(Debug, Default)]
+pub(super) struct HostLimits {
+    concurrency: Option<usize>,
+    interval: Option<Duration>,
+    in_flight: HashMap<IpAddr, usize>,
+    next_start: HashMap<IpAddr, Instant>,
+}
+
+impl HostLimits {
+    /// `concurrency` 和 `rate`（每秒探测数）为 0 时表示不限制。
+    pub(super) fn new(concurrency: Option<usize>, rate: Option<u32>) -> Self {
+        Self {
+            concurrency: concurrency.filter(|&n| n > 0),
+            interval: rate
+                .filter(|&rate| rate > 0)
+                .map(|rate| Duration::from_secs(1) / rate),
+            ..Self::default()
+        }
+    }
+
+    /// 现在是否可以探测 `ip`。不可以时返回可以探测的时间，
+    /// 受并发数限制时是 `None`，需要等待正在进行的探测结束。
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Option<Instant>> {
+        if let Some(concurrency) = self.concurrency {
+            if self.in_flight.get(&ip).copied().unwrap_or_default() >= concurrency {
+                return Err(None);
+            }
+        }
+        match self.next_start.get(&ip) {
+            Some(&at) if at > now => Err(Some(at)),
+            _ => Ok(()),
+        }
+    }
+
+    fn start(&mut self, ip: IpAddr, now: Instant) {
+        if self.concurrency.is_some() {
+            *self.in_flight.entry(ip).or_default() += 1;
+        }
+        if let Some(interval) = self.interval {
+            self.next_start.insert(ip, now + interval);
+        }
+    }
+
+    fn finish(&mut self, ip: IpAddr) {
+        if let Some(in_flight) = self.in_flight.get_mut(&ip) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                self.in_flight.remove(&ip);
+            }
+        }
+    }
+}
+
+/// 重试排在所有新 socket 之后，不会在等待期间占用批处理的位置。
+pub(super) struct SocketQueue<'s> {
+    fresh: Fuse<SocketIterator<'s>>,
+    /// 新的 socket 已经全部取出
+    exhausted: bool,
+    /// 按重试时间排序的 (时间, 第几次尝试, socket)
+    retries: BinaryHeap<Reverse<(Instant, u8, SocketAddr)>>,
+    /// 主机达到上限时暂时放在一边的 (socket, 第几次尝试)
+    deferred: HashMap<IpAddr, VecDeque<(SocketAddr, u8)>>,
+    deferred_len: usize,
+    limits: HostLimits,
+    pub(super) deadlines: HostDeadlines,
+}
+
+impl<'s> SocketQueue<'s> {
+    pub(super) fn new(
+        fresh: SocketIterator<'s>,
+        deadlines: HostDeadlines,
+        limits: HostLimits,
+    ) -> Self {
+        Self {
+            fresh: fresh.fuse(),
+            exhausted: false,
+            retries: BinaryHeap::new(),
+            deferred: HashMap::new(),
+            deferred_len: 0,
+            limits,
+            deadlines,
+        }
+    }
+
+    /// 在 `at` 之后第 `attempt` 次探测 `socket`。
+    pub(super) fn retry(&mut self, socket: SocketAddr, attempt: u8, at: Instant) {
+        self.retries.push(Reverse((at, attempt, socket)));
+    }
+
+    /// 对 `ip` 的一次探测结束了。
+    pub(super) fn finish(&mut self, ip: IpAddr) {
+        self.limits.finish(ip);
+    }
+
+    /// 下一个要探测的 socket，跳过超过 `host_timeout` 的主机。
+    pub(super) fn next(&mut self, now: Instant) -> Next {
+        let mut wait: Option<Instant> = None;
+        let mut wait_for = |at: Option<Instant>| {
+            if let Some(at) = at {
+                wait = Some(wait.map_or(at, |wait| wait.min(at)));
+            }
+        };
+
+        // 先探测之前因为主机达到上限而放在一边的 socket
+        loop {
+            let mut ready = None;
+            for &ip in self.deferred.keys() {
+                match self.limits.check(ip, now) {
+                    Ok(()) => {
+                        ready = Some(ip);
+                        break;
+                    }
+                    Err(at) => wait_for(at),
+                }
+            }
+            let Some(ip) = ready else {
+                break;
+            };
+            let sockets = self.deferred.get_mut(&ip).unwrap();
+            let (socket, attempt) = sockets.pop_front().unwrap();
+            if sockets.is_empty() {
+                self.deferred.remove(&ip);
+            }
+            self.deferred_len -= 1;
+            if self.start(socket, now) {
+                return Next::Socket(socket, attempt);
+            }
+        }
+
+        while self.deferred_len < MAX_DEFERRED {
+            let Some(socket) = self.fresh.next() else {
+                self.exhausted = true;
+                break;
+            };
+            match self.limits.check(socket.ip(), now) {
+                Ok(()) if self.start(socket, now) => return Next::Socket(socket, 1),
+                Ok(()) => {}
+                Err(at) => {
+                    wait_for(at);
+                    self.defer(socket, 1);
+                }
+            }
+        }
+
+        while let Some(&Reverse((at, attempt, socket))) = self.retries.peek() {
+            if at > now {
+                wait_for(Some(at));
+                break;
+            }
+            self.retries.pop();
+            match self.limits.check(socket.ip(), now) {
+                Ok(()) if self.start(socket, now) => return Next::Socket(socket, attempt),
+                Ok(()) => {}
+                Err(at) => {
+                    wait_for(at);
+                    self.defer(socket, attempt);
+                }
+            }
+        }
+
+        match wait {
+            Some(at) => Next::Wait(at),
+            None if self.deferred_len > 0 => Next::Blocked,
+            None => Next::Done,
+        }
+    }
+
+    /// 开始探测 `socket`，主机已经超过 `host_timeout` 时返回 `false`。
+    fn start(&mut self, socket: SocketAddr, now: Instant) -> bool {
+        if !self.deadlines.admit(socket.ip()) {
+            return false;
+        }
+        self.limits.start(socket.ip(), now);
+        true
+    }
+
+    fn defer(&mut self, socket: SocketAddr, attempt: u8) {
+        self.deferred
+            .entry(socket.ip())
+            .or_default()
+            .push_back((socket, attempt));
+        self.deferred_len += 1;
+    }
+
+    /// 没有任何等待探测的 socket。
+    pub(super) fn is_empty(&self) -> bool {
+        self.exhausted && self.retries.is_empty() && self.deferred_len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HostDeadlines, HostLimits, Next, RetryBackoff, SocketQueue};
+    use crate::scanner::SocketIterator;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = RetryBackoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+            jitter: 0,
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(4), Duration::from_millis(500));
+        assert_eq!(backoff.delay(u8::MAX), Duration::from_millis(500));
+
+        let backoff = RetryBackoff::new(Duration::from_millis(100), 50);
+        for _ in 0..100 {
+            let delay = backoff.delay(1);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn retries_come_after_fresh_sockets() {
+        let ips: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap()];
+        let ports = [1, 2];
+        let mut queue = SocketQueue::new(
+            SocketIterator::new(&ips, &ports),
+            HostDeadlines::new(None),
+            HostLimits::default(),
+        );
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+
+        let Next::Socket(first, 1) = queue.next(now) else {
+            panic!("expected a fresh socket");
+        };
+        queue.retry(first, 2, later);
+        assert!(matches!(queue.next(now), Next::Socket(s, 1) if s.port() == 2));
+        assert!(!queue.is_empty());
+        assert_eq!(queue.next(now), Next::Wait(later));
+        assert_eq!(queue.next(later), Next::Socket(first, 2));
+        assert_eq!(queue.next(later), Next::Done);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn busy_hosts_do_not_block_others() {
+        let ips: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let ports = [1, 2];
+        let mut queue = SocketQueue::new(
+            SocketIterator::new(&ips, &ports),
+            HostDeadlines::new(None),
+            HostLimits::new(Some(1), None),
+        );
+        let now = Instant::now();
+
+        let Next::Socket(first, 1) = queue.next(now) else {
+            panic!("expected a fresh socket");
+        };
+        assert!(matches!(queue.next(now), Next::Socket(s, 1) if s.ip() == ips[1]));
+        assert_eq!(queue.next(now), Next::Blocked);
+
+        queue.finish(first.ip());
+        assert!(
+            matches!(queue.next(now), Next::Socket(s, 1) if s == "127.0.0.1:2".parse().unwrap())
+        );
+        assert_eq!(queue.next(now), Next::Blocked);
+        assert!(!queue.is_empty());
+    }
+
+    #[test]
+    fn host_rate_spaces_out_probes() {
+        let ips: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap()];
+        let ports = [1, 2];
+        let mut queue = SocketQueue::new(
+            SocketIterator::new(&ips, &ports),
+            HostDeadlines::new(None),
+            HostLimits::new(None, Some(10)),
+        );
+        let now = Instant::now();
+        let later = now + Duration::from_millis(100);
+
+        assert!(matches!(queue.next(now), Next::Socket(s, 1) if s.port() == 1));
+        assert_eq!(queue.next(now), Next::Wait(later));
+        assert!(matches!(queue.next(later), Next::Socket(s, 1) if s.port() == 2));
+        assert_eq!(queue.next(later), Next::Done);
+        assert!(queue.is_empty());
+    }
+}
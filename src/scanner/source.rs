@@ -0,0 +1,249 @@
+//! 探测使用的本机源地址、源端口和网络接口。
+use async_io::Async;
+use async_std::net::{TcpStream, UdpSocket};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// 探测从哪里发出，默认全部由操作系统选择。
+///
+/// 多网卡的扫描机器可以用它从指定的 VLAN 发出探测，也可以满足只允许特定源地址的 ACL。
+///
+/// 同一对源地址和目标地址之间只有大约 28000 个临时端口可用，`batch_size`
+/// 很大时会被耗尽。指定多个源地址时探测轮流使用它们；再打开 `linger_zero`，
+/// 连接用 RST 关闭，不会在 TIME_WAIT 中占用端口。
+///
+/// ```rust
+/// # use rustscan::scanner::SourceOptions;
+/// let source = SourceOptions {
+///     ips: vec!["192.0.2.10".parse().unwrap(), "192.0.2.11".parse().unwrap()],
+///     port: Some(53),
+///     ..SourceOptions::default()
+/// };
+/// assert!(!source.is_default());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceOptions {
+    /// 源地址，多个时轮流使用，只能扫描和其中某个地址同一地址族的目标
+    pub ips: Vec<IpAddr>,
+    /// 源端口，所有探测共用
+    pub port: Option<u16>,
+    /// 发出探测的网络接口（Linux 上的 SO_BINDTODEVICE）
+    pub interface: Option<String>,
+    /// 设置 SO_LINGER(0)，关闭连接时直接发送 RST
+    pub linger_zero: bool,
+}
+
+impl SourceOptions {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// 第 `nth` 次探测 `target` 时使用的源地址，在同一地址族的源地址中轮流选择。
+    pub(crate) fn ip_for(&self, target: IpAddr, nth: usize) -> io::Result<Option<IpAddr>> {
+        if self.ips.is_empty() {
+            return Ok(None);
+        }
+        let mut candidates = self
+            .ips
+            .iter()
+            .filter(|ip| ip.is_ipv4() == target.is_ipv4());
+        let count = candidates.clone().count();
+        match candidates.nth(nth % count.max(1)) {
+            Some(ip) => Ok(Some(*ip)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no source address can reach {target}"),
+            )),
+        }
+    }
+
+    /// 第 `nth` 次探测 `target` 时绑定的本机地址，没有指定源地址和源端口时返回 `None`。
+    pub(crate) fn local_addr(&self, target: IpAddr, nth: usize) -> io::Result<Option<SocketAddr>> {
+        if self.ips.is_empty() && self.port.is_none() {
+            return Ok(None);
+        }
+        let ip = match self.ip_for(target, nth)? {
+            Some(ip) => ip,
+            None if target.is_ipv4() => IpAddr::from([0, 0, 0, 0]),
+            None => IpAddr::from([0u16; 8]),
+        };
+        Ok(Some(SocketAddr::new(ip, self.port.unwrap_or(0))))
+    }
+
+    /// 创建一个绑定到第 `nth` 个源地址和网络接口的 socket。
+    pub(crate) fn socket(
+        &self,
+        target: IpAddr,
+        nth: usize,
+        ty: Type,
+        protocol: Option<Protocol>,
+    ) -> io::Result<Socket> {
+        let domain = if target.is_ipv4() {
+            Domain::IPV4
+        } else {
+            Domain::IPV6
+        };
+        let socket = Socket::new(domain, ty, protocol)?;
+        self.bind_device(&socket)?;
+        if self.linger_zero && ty == Type::STREAM {
+            socket.set_linger(Some(Duration::ZERO))?;
+        }
+        if let Some(local) = self.local_addr(target, nth)? {
+            // 所有探测共用同一个源端口
+            if self.port.is_some() {
+                socket.set_reuse_address(true)?;
+            }
+            socket.bind(&local.into())?;
+        }
+        Ok(socket)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn bind_device(&self, socket: &Socket) -> io::Result<()> {
+        match &self.interface {
+            Some(interface) => socket.bind_device(Some(interface.as_bytes())),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn bind_device(&self, _socket: &Socket) -> io::Result<()> {
+        match &self.interface {
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "binding to an interface is only supported on Linux",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// 从第 `nth` 个源地址连接 `target`。
+    pub(crate) async fn connect(&self, target: SocketAddr, nth: usize) -> io::Result<TcpStream> {
+        if self.is_default() {
+            return TcpStream::connect(target).await;
+        }
+
+        let socket = self.socket(target.ip(), nth, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_nonblocking(true)?;
+        match socket.connect(&SockAddr::from(target)) {
+            Ok(()) => {}
+            Err(e) if in_progress(&e) => {}
+            Err(e) => return Err(e),
+        }
+        // 和 async_std 自己的 connect 一样：等待 socket 可写，再检查连接是否出错
+        let stream = Async::new(std::net::TcpStream::from(socket))?;
+        stream.writable().await?;
+        if let Some(e) = stream.get_ref().take_error()? {
+            return Err(e);
+        }
+        Ok(TcpStream::from(stream.into_inner()?))
+    }
+
+    /// 绑定一个用来探测 `target` 的 UDP socket，使用第 `nth` 个源地址。
+    pub(crate) async fn udp_bind(&self, target: SocketAddr, nth: usize) -> io::Result<UdpSocket> {
+        if self.ips.is_empty() && self.port.is_none() && self.interface.is_none() {
+            let unspecified: SocketAddr = match target {
+                SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+                SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+            };
+            return UdpSocket::bind(unspecified).await;
+        }
+
+        let socket = self.socket(target.ip(), nth, Type::DGRAM, Some(Protocol::UDP))?;
+        Ok(UdpSocket::from(std::net::UdpSocket::from(socket)))
+    }
+}
+
+/// 非阻塞的 connect 已经开始，需要等待结果。
+fn in_progress(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    if error.raw_os_error() == Some(libc::EINPROGRESS) {
+        return true;
+    }
+    error.kind() == io::ErrorKind::WouldBlock
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceOptions;
+    use async_std::task::block_on;
+    use std::io::{ErrorKind, Read};
+    use std::net::{IpAddr, TcpListener};
+
+    #[test]
+    fn local_addr_matches_target_family() {
+        let v4: IpAddr = "127.0.0.1".parse().unwrap();
+        let v6: IpAddr = "::1".parse().unwrap();
+
+        assert_eq!(SourceOptions::default().local_addr(v4, 0).unwrap(), None);
+
+        let port_only = SourceOptions {
+            port: Some(4000),
+            ..SourceOptions::default()
+        };
+        assert_eq!(
+            port_only.local_addr(v6, 0).unwrap(),
+            Some("[::]:4000".parse().unwrap())
+        );
+
+        let source = SourceOptions {
+            ips: vec![v4],
+            ..SourceOptions::default()
+        };
+        assert_eq!(
+            source.local_addr(v4, 0).unwrap(),
+            Some("127.0.0.1:0".parse().unwrap())
+        );
+        assert!(source.local_addr(v6, 0).is_err());
+    }
+
+    #[test]
+    fn source_addresses_are_used_in_turn() {
+        let first: IpAddr = "192.0.2.1".parse().unwrap();
+        let second: IpAddr = "192.0.2.2".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let source = SourceOptions {
+            ips: vec![first, v6, second],
+            ..SourceOptions::default()
+        };
+
+        let target: IpAddr = "198.51.100.1".parse().unwrap();
+        let picked: Vec<_> = (0..4)
+            .map(|nth| source.ip_for(target, nth).unwrap().unwrap())
+            .collect();
+        assert_eq!(picked, [first, second, first, second]);
+
+        let target: IpAddr = "2001:db8::2".parse().unwrap();
+        assert_eq!(source.ip_for(target, 1).unwrap(), Some(v6));
+    }
+
+    // 其它系统上 127.0.0.2 不一定可用
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connect_from_source_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap();
+        let source = SourceOptions {
+            ips: vec!["127.0.0.2".parse().unwrap(), "127.0.0.3".parse().unwrap()],
+            linger_zero: true,
+            ..SourceOptions::default()
+        };
+
+        for nth in 0..2 {
+            let stream = block_on(source.connect(target, nth)).unwrap();
+            assert_eq!(stream.local_addr().unwrap().ip(), source.ips[nth]);
+            let (mut accepted, peer) = listener.accept().unwrap();
+            assert_eq!(peer.ip(), source.ips[nth]);
+
+            // SO_LINGER(0) 让关闭连接时发送 RST
+            drop(stream);
+            let read = accepted.read(&mut [0u8; 1]);
+            assert_eq!(read.unwrap_err().kind(), ErrorKind::ConnectionReset);
+        }
+
+        drop(listener);
+        assert!(block_on(source.connect(target, 0)).is_err());
+    }
+}
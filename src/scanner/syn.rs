@@ -0,0 +1,384 @@
+//! 基于原始套接字的 SYN 扫描（半开扫描），仅支持 Linux，需要 CAP_NET_RAW。
+//!
+//! 连接扫描每个探测都要完成一次完整的三次握手并占用一个文件描述符，
+//! 所以 `batch_size` 会受到 ulimit 的限制。SYN 扫描只在一个原始套接字上发送 SYN，
+//! 收到 SYN-ACK 说明端口开放，收到 RST 说明端口关闭，连接永远不会建立：
+//! 内核不知道这个"连接"，收到 SYN-ACK 后会自己回复 RST。
+//!
+//! 所有探测共用同一个原始套接字接收响应，由 [`SynScanner::drive`] 在扫描期间
+//! 持续读取，并按照对端地址把结果交给正在等待的探测。
+use super::SourceOptions;
+use async_std::io;
+use async_std::net::UdpSocket;
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use log::debug;
+use socket2::{Domain, Protocol, Type};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const TCP_HEADER_LEN: usize = 24;
+const TCP_PROTOCOL: u8 = 6;
+const FLAG_SYN: u8 = 0x02;
+const FLAG_RST: u8 = 0x04;
+const FLAG_ACK: u8 = 0x10;
+
+/// 原始套接字会收到本机所有的 TCP 报文，接收缓冲区要足够大。
+const RECV_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// 一个地址族的原始套接字，以及为它保留的源端口。
+#[derive(Debug)]
+struct RawTcp {
+    socket: UdpSocket,
+    source_port: u16,
+    /// 占住源端口，避免和本机其他连接冲突；它不会接受任何连接。
+    _reservation: TcpListener,
+}
+
+impl RawTcp {
+    fn new(domain: Domain, source: &SourceOptions) -> io::Result<Self> {
+        let target = if domain == Domain::IPV4 {
+            IpAddr::from([0, 0, 0, 0])
+        } else {
+            IpAddr::from([0u16; 8])
+        };
+        let local = source
+            .local_addr(target, 0)?
+            .unwrap_or_else(|| SocketAddr::new(target, 0));
+        let reservation = TcpListener::bind(local)?;
+
+        // 绑定源地址之后内核在 IP 头中使用它，和我们计算校验和用的地址一致
+        let socket = source.socket(target, 0, Type::RAW, Some(Protocol::TCP))?;
+        socket.set_nonblocking(true)?;
+        if let Err(e) = socket.set_recv_buffer_size(RECV_BUFFER_SIZE) {
+            debug!("Could not enlarge raw socket buffer {e}");
+        }
+
+        Ok(Self {
+            socket: UdpSocket::from(std::net::UdpSocket::from(socket)),
+            source_port: reservation.local_addr()?.port(),
+            _reservation: reservation,
+        })
+    }
+}
+
+/// SYN 扫描的共享状态。
+#[derive(Debug)]
+pub struct SynScanner {
+    v4: Option<RawTcp>,
+    v6: Option<RawTcp>,
+    /// 所有 SYN 使用同一个初始序列号，用来校验响应中的确认号。
+    sequence: u32,
+    /// 正在等待响应的探测，`true` 表示 SYN-ACK，`false` 表示 RST。
+    pending: Mutex<HashMap<SocketAddr, oneshot::Sender<bool>>>,
+    /// 指定的源地址，每个地址族只使用第一个
+    source_ips: Vec<IpAddr>,
+    /// 目标 IP 对应的本机源地址，用于计算校验和。
+    sources: Mutex<HashMap<IpAddr, IpAddr>>,
+}
+
+impl SynScanner {
+    /// 创建原始套接字，没有 CAP_NET_RAW 时返回错误，调用方应该退回连接扫描。
+    pub fn new() -> io::Result<Self> {
+        Self::with_source(&SourceOptions::default())
+    }
+
+    /// 从指定的源地址、源端口或网络接口发送 SYN，只创建和源地址同一地址族的原始套接字。
+    pub fn with_source(source: &SourceOptions) -> io::Result<Self> {
+        let v4 = RawTcp::new(Domain::IPV4, source);
+        let v6 = RawTcp::new(Domain::IPV6, source);
+        if let (Err(e), Err(_)) = (&v4, &v6) {
+            return Err(io::Error::new(e.kind(), e.to_string()));
+        }
+
+        Ok(Self {
+            v4: v4.ok(),
+            v6: v6.ok(),
+            sequence: rand::random(),
+            pending: Mutex::new(HashMap::new()),
+            source_ips: source.ips.clone(),
+            sources: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn raw(&self, ip: IpAddr) -> io::Result<&RawTcp> {
+        let raw = match ip {
+            IpAddr::V4(_) => self.v4.as_ref(),
+            IpAddr::V6(_) => self.v6.as_ref(),
+        };
+        raw.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("no raw socket for {ip}"),
+            )
+        })
+    }
+
+    /// 在扫描期间持续接收响应，`scan` 结束后停止接收并返回它的结果。
+    pub(crate) async fn drive<F: Future>(&self, scan: F) -> F::Output {
+        let receive = future::join(
+            self.receive(self.v4.as_ref()),
+            self.receive(self.v6.as_ref()),
+        );
+        match future::select(Box::pin(scan), Box::pin(receive)).await {
+            Either::Left((output, _)) => output,
+            // 接收出错时所有剩下的探测都会超时
+            Either::Right((_, scan)) => scan.await,
+        }
+    }
+
+    async fn receive(&self, raw: Option<&RawTcp>) {
+        let Some(raw) = raw else {
+            return;
+        };
+        let mut buf = [0u8; 1500];
+        loop {
+            let (size, from) = match raw.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("Receiving from raw socket failed {e}");
+                    return;
+                }
+            };
+            // IPv4 原始套接字收到的数据包含 IP 首部，IPv6 不包含
+            let segment = match from {
+                SocketAddr::V4(_) => buf
+                    .first()
+                    .and_then(|byte| buf[..size].get(usize::from(byte & 0x0f) * 4..)),
+                SocketAddr::V6(_) => Some(&buf[..size]),
+            };
+            let Some(reply) = segment.and_then(|s| parse_reply(s, raw.source_port, self.sequence))
+            else {
+                continue;
+            };
+
+            let target = SocketAddr::new(from.ip(), reply.port);
+            if let Some(waiting) = self.pending.lock().unwrap().remove(&target) {
+                let _ = waiting.send(reply.open);
+            }
+        }
+    }
+
+    /// 发送一个 SYN 并等待响应。
+    ///
+    /// 和连接扫描一样，端口开放时返回 `Ok`，被拒绝时返回 `ConnectionRefused`，
+    /// 没有响应时返回 `TimedOut`。必须在 [`SynScanner::drive`] 中调用。
+    pub(crate) async fn probe(&self, target: SocketAddr, timeout: Duration) -> io::Result<()> {
+        let raw = self.raw(target.ip())?;
+        let source = self.source_for(target.ip())?;
+        let packet = syn_packet(
+            SocketAddr::new(source, raw.source_port),
+            target,
+            self.sequence,
+        );
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(target, sender);
+
+        let result = async {
+            raw.socket
+                .send_to(&packet, SocketAddr::new(target.ip(), 0))
+                .await?;
+            io::timeout(timeout, async {
+                receiver
+                    .await
+                    .map_err(|_| io::Error::other("SYN scan receiver stopped"))
+            })
+            .await
+        }
+        .await;
+        self.pending.lock().unwrap().remove(&target);
+
+        match result? {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("{target} answered with RST"),
+            )),
+        }
+    }
+
+    /// 通过一个已连接的 UDP 套接字找到内核为目标选择的源地址，不会发送任何数据。
+    fn source_for(&self, ip: IpAddr) -> io::Result<IpAddr> {
+        if let Some(source) = self.source_ips.iter().find(|s| s.is_ipv4() == ip.is_ipv4()) {
+            return Ok(*source);
+        }
+        if let Some(source) = self.sources.lock().unwrap().get(&ip) {
+            return Ok(*source);
+        }
+        let unspecified: SocketAddr = match ip {
+            IpAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            IpAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let probe = std::net::UdpSocket::bind(unspecified)?;
+        probe.connect(SocketAddr::new(ip, 9))?;
+        let source = probe.local_addr()?.ip();
+        self.sources.lock().unwrap().insert(ip, source);
+        Ok(source)
+    }
+}
+
+/// 从响应报文中解析出的结果。
+#[derive(Debug, PartialEq, Eq)]
+struct Reply {
+    port: u16,
+    open: bool,
+}
+
+/// 只接受发往我们源端口、确认号正确的 SYN-ACK 或 RST。
+fn parse_reply(segment: &[u8], source_port: u16, sequence: u32) -> Option<Reply> {
+    if segment.len() < 20 {
+        return None;
+    }
+    let port = u16::from_be_bytes([segment[0], segment[1]]);
+    let destination = u16::from_be_bytes([segment[2], segment[3]]);
+    let ack = u32::from_be_bytes([segment[8], segment[9], segment[10], segment[11]]);
+    let flags = segment[13];
+
+    if destination != source_port || ack != sequence.wrapping_add(1) {
+        return None;
+    }
+    if flags & FLAG_RST != 0 {
+        Some(Reply { port, open: false })
+    } else if flags & (FLAG_SYN | FLAG_ACK) == FLAG_SYN | FLAG_ACK {
+        Some(Reply { port, open: true })
+    } else {
+        None
+    }
+}
+
+/// 构造带 MSS 选项的 SYN 报文（不含 IP 首部）。
+fn syn_packet(source: SocketAddr, target: SocketAddr, sequence: u32) -> Vec<u8> {
+    let mut packet = vec![0u8; TCP_HEADER_LEN];
+    packet[0..2].copy_from_slice(&source.port().to_be_bytes());
+    packet[2..4].copy_from_slice(&target.port().to_be_bytes());
+    packet[4..8].copy_from_slice(&sequence.to_be_bytes());
+    // 数据偏移：6 个 32 位字
+    packet[12] = 6 << 4;
+    packet[13] = FLAG_SYN;
+    packet[14..16].copy_from_slice(&1024u16.to_be_bytes());
+    // MSS = 1460
+    packet[20..24].copy_from_slice(&[2, 4, 0x05, 0xb4]);
+
+    let checksum = tcp_checksum(source.ip(), target.ip(), &packet);
+    packet[16..18].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// 带伪首部的 TCP 校验和。
+fn tcp_checksum(source: IpAddr, target: IpAddr, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40 + segment.len());
+    match (source, target) {
+        (IpAddr::V4(source), IpAddr::V4(target)) => {
+            pseudo.extend_from_slice(&source.octets());
+            pseudo.extend_from_slice(&target.octets());
+            pseudo.extend_from_slice(&[0, TCP_PROTOCOL]);
+            #[allow(clippy::cast_possible_truncation)]
+            pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        }
+        (source, target) => {
+            pseudo.extend_from_slice(&to_ipv6(source).octets());
+            pseudo.extend_from_slice(&to_ipv6(target).octets());
+            #[allow(clippy::cast_possible_truncation)]
+            pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, TCP_PROTOCOL]);
+        }
+    }
+    pseudo.extend_from_slice(segment);
+
+    let mut sum: u32 = pseudo
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let sum = sum as u16;
+    !sum
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_reply, syn_packet, tcp_checksum, Reply, SynScanner};
+    use async_std::task::block_on;
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn syn_packet_is_well_formed() {
+        let source: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let target: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let packet = syn_packet(source, target, 7);
+
+        assert_eq!(packet.len(), 24);
+        assert_eq!(&packet[0..4], &[0x9c, 0x40, 0x01, 0xbb]);
+        assert_eq!(packet[13], 0x02);
+        // 对包含校验和的报文再次计算，结果为 0
+        assert_eq!(tcp_checksum(source.ip(), target.ip(), &packet), 0);
+    }
+
+    #[test]
+    fn replies_are_matched() {
+        let mut syn_ack = [0u8; 20];
+        syn_ack[0..2].copy_from_slice(&443u16.to_be_bytes());
+        syn_ack[2..4].copy_from_slice(&40000u16.to_be_bytes());
+        syn_ack[8..12].copy_from_slice(&8u32.to_be_bytes());
+        syn_ack[13] = 0x12;
+        assert_eq!(
+            parse_reply(&syn_ack, 40000, 7),
+            Some(Reply {
+                port: 443,
+                open: true
+            })
+        );
+
+        let mut rst = syn_ack;
+        rst[13] = 0x14;
+        assert_eq!(
+            parse_reply(&rst, 40000, 7),
+            Some(Reply {
+                port: 443,
+                open: false
+            })
+        );
+
+        // 确认号不对或者发往其他端口的报文被忽略
+        assert_eq!(parse_reply(&syn_ack, 40000, 8), None);
+        assert_eq!(parse_reply(&syn_ack, 40001, 7), None);
+    }
+
+    /// 需要 CAP_NET_RAW，没有权限时跳过。
+    #[test]
+    fn scans_loopback() {
+        let Ok(syn) = SynScanner::new() else {
+            return;
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed: SocketAddr = {
+            let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+            unused.local_addr().unwrap()
+        };
+
+        block_on(syn.drive(async {
+            let timeout = Duration::from_secs(1);
+            assert!(syn.probe(open, timeout).await.is_ok());
+            let refused = syn.probe(closed, timeout).await.unwrap_err();
+            assert_eq!(refused.kind(), std::io::ErrorKind::ConnectionRefused);
+        }));
+        // 没有完成握手，监听队列中没有连接
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+}
@@ -56,14 +56,31 @@
 //!
 //! 另一种是格式中只有 `{{ip}}` 和 `{{port}}`，只有这些将被替换为扫描的参数。
 //!
+//! 两种格式都可以使用 `{{ipversion}}`，IPv4 地址替换为 `4`，IPv6 地址替换为 `6`，
+//! 同一个主机名的两个地址族都被扫描时可以用它区分结果。
+//!
+//! 两种格式都可以使用 `{{hostname}}`，它会被替换为解析出这个 IP 的主机名，
+//! 便于对虚拟主机运行 HTTP 或 TLS 工具。主机名已经加上了 shell 引号，直接扫描 IP 时替换为 IP。
+//!
+//! 启用 `--http` 时还可以使用 HTTP 探测的结果：
+//!
+//! - `{{http_ports}}`：返回了 HTTP 响应的端口，用 `ports_separator` 分隔。
+//! - `{{http_scheme}}`、`{{http_status}}`、`{{http_server}}`、`{{http_title}}`、`{{http_location}}`：
+//!   脚本文件定义了 `port` 时取该端口的结果，否则取第一个 HTTP 端口的结果。
+//!
+//! `{{http_server}}`、`{{http_title}}` 和 `{{http_location}}` 来自远程服务器，
+//! 替换时已经加上了 shell 引号，不需要在 `call_format` 中再加引号。
+//! 没有结果的占位符会被替换为空。
+//!
 //! 这使得运行像 `nmap` 这样的系统安装命令并为其提供任何类型的参数变得容易。
 //!
 //! 如果格式不同，脚本将被静默丢弃且不运行。使用 `Debug` 选项可以看到出错的地方。
 
 #![allow(clippy::module_name_repetitions)]
 
+use crate::http::HttpInfo;
 use crate::input::ScriptsRequired;
-use anyhow::{anyhow, Result};
+use crate::{Error, Result};
 use log::debug;
 use serde_derive::{Deserialize, Serialize};
 use std::fs::{self, File};
@@ -74,9 +91,6 @@ use std::process::{Command, Stdio};
 use std::string::ToString;
 use text_placeholder::Template;
 
-#[cfg(unix)]
-use std::os::unix::process::ExitStatusExt;
-
 static DEFAULT: &str = r#"tags = ["core_approved", "RustScan", "default"]
 developer = [ "RustScan", "https://github.com/RustScan" ]
 ports_separator = ","
@@ -101,7 +115,7 @@ pub fn init_scripts(scripts: &ScriptsRequired) -> Result<Vec<ScriptFile>> {
             let script_dir_base = if let Some(config_directory) = &script_config.directory {
                 PathBuf::from(config_directory)
             } else {
-                dirs::home_dir().ok_or_else(|| anyhow!("Could not infer scripts path."))?
+                dirs::home_dir().ok_or(Error::NoConfigPath("scripts folder"))?
             };
 
             let script_paths = find_scripts(script_dir_base)?;
@@ -170,21 +184,86 @@ pub struct Script {
 
     // 我们希望脚本运行的格式。
     call_format: Option<String>,
+
+    // HTTP 探测阶段的结果，按端口排列。
+    http: Vec<(u16, HttpInfo)>,
+
+    // 解析出这个 IP 的主机名。
+    hostname: Option<String>,
 }
 
 #[derive(Serialize)]
 struct ExecPartsScript {
     script: String,
     ip: String,
+    hostname: String,
     port: String,
     ipversion: String,
+    #[serde(flatten)]
+    http: HttpParts,
 }
 
 #[derive(Serialize)]
 struct ExecParts {
     ip: String,
+    hostname: String,
     port: String,
     ipversion: String,
+    #[serde(flatten)]
+    http: HttpParts,
+}
+
+#[derive(Serialize, Default)]
+struct HttpParts {
+    http_ports: String,
+    http_scheme: String,
+    http_status: String,
+    http_server: String,
+    http_title: String,
+    http_location: String,
+}
+
+impl HttpParts {
+    fn new(results: &[(u16, HttpInfo)], trigger_port: Option<&str>, separator: &str) -> Self {
+        let http_ports = results
+            .iter()
+            .map(|(port, _)| port.to_string())
+            .collect::<Vec<String>>()
+            .join(separator);
+
+        let chosen = match trigger_port.and_then(|p| p.trim().parse::<u16>().ok()) {
+            Some(trigger) => results.iter().find(|(port, _)| *port == trigger),
+            None => results.first(),
+        };
+        let Some((_, info)) = chosen else {
+            return Self {
+                http_ports,
+                ..Self::default()
+            };
+        };
+
+        Self {
+            http_ports,
+            http_scheme: info.scheme().to_owned(),
+            http_status: info.status.to_string(),
+            http_server: shell_quote(info.server.as_deref().unwrap_or_default()),
+            http_title: shell_quote(info.title.as_deref().unwrap_or_default()),
+            http_location: shell_quote(info.location.as_deref().unwrap_or_default()),
+        }
+    }
+}
+
+/// 把远程服务器返回的文本作为单个 shell 参数，防止命令注入。
+fn shell_quote(value: &str) -> String {
+    if cfg!(unix) {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    } else {
+        let value: String = value
+            .chars()
+            .filter(|c| !matches!(c, '"' | '%' | '^' | '&' | '|' | '<' | '>'))
+            .collect();
+        format!("\"{value}\"")
+    }
 }
 
 impl Script {
@@ -205,15 +284,37 @@ impl Script {
             ports_separator,
             tags,
             call_format,
+            http: vec![],
+            hostname: None,
         }
     }
 
+    /// 附加目标的主机名，用于填充 `{{hostname}}` 占位符。
+    #[must_use]
+    pub fn with_hostname(mut self, hostname: Option<&str>) -> Self {
+        self.hostname = hostname.map(str::to_owned);
+        self
+    }
+
+    /// 附加 HTTP 探测的结果，用于填充 `{{http_*}}` 占位符。
+    #[must_use]
+    pub fn with_http(mut self, http: Vec<(u16, HttpInfo)>) -> Self {
+        self.http = http;
+        self
+    }
+
     // 一些变量在读取之前被更改，编译器会对 warn(unused_assignments) 发出警告
     #[allow(unused_assignments)]
     pub fn run(self) -> Result<String> {
         debug!("run self {:?}", &self);
 
         let separator = self.ports_separator.unwrap_or_else(|| ",".into());
+        let http = HttpParts::new(&self.http, self.trigger_port.as_deref(), &separator);
+        // 主机名来自输入文件，同样需要引号；直接扫描 IP 时使用 IP
+        let hostname = match &self.hostname {
+            Some(hostname) => shell_quote(hostname),
+            None => self.ip.to_string(),
+        };
 
         let mut ports_str = self
             .open_ports
@@ -229,7 +330,7 @@ impl Script {
         if let Some(call_format) = self.call_format {
             final_call_format = call_format;
         } else {
-            return Err(anyhow!("Failed to parse execution format."));
+            return Err(Error::ScriptFormat("missing call_format".to_owned()));
         }
         let default_template: Template = Template::new(&final_call_format);
         let mut to_run = String::new();
@@ -238,23 +339,31 @@ impl Script {
             let exec_parts_script: ExecPartsScript = ExecPartsScript {
                 script: self.path.unwrap().to_str().unwrap().to_string(),
                 ip: self.ip.to_string(),
+                hostname,
                 port: ports_str,
                 ipversion: match &self.ip {
                     IpAddr::V4(_) => String::from("4"),
                     IpAddr::V6(_) => String::from("6"),
                 },
+                http,
             };
-            to_run = default_template.fill_with_struct(&exec_parts_script)?;
+            to_run = default_template
+                .fill_with_struct(&exec_parts_script)
+                .map_err(|e| Error::ScriptFormat(e.to_string()))?;
         } else {
             let exec_parts: ExecParts = ExecParts {
                 ip: self.ip.to_string(),
+                hostname,
                 port: ports_str,
                 ipversion: match &self.ip {
                     IpAddr::V4(_) => String::from("4"),
                     IpAddr::V6(_) => String::from("6"),
                 },
+                http,
             };
-            to_run = default_template.fill_with_struct(&exec_parts)?;
+            to_run = default_template
+                .fill_with_struct(&exec_parts)
+                .map_err(|e| Error::ScriptFormat(e.to_string()))?;
         }
         debug!("\nScript format to run {to_run}");
         execute_script(&to_run)
@@ -271,53 +380,46 @@ fn execute_script(script: &str) -> Result<String> {
         ("cmd.exe", "/c")
     };
 
-    match Command::new(cmd)
+    let output = Command::new(cmd)
         .args([arg, script])
         .stdin(Stdio::piped())
         .stderr(Stdio::piped())
         .output()
-    {
-        Ok(output) => {
-            let status = output.status;
-
-            let es = match status.code() {
-                Some(code) => code,
-                _ => {
-                    #[cfg(unix)]
-                    {
-                        status.signal().unwrap()
-                    }
-
-                    #[cfg(windows)]
-                    {
-                        return Err(anyhow!("Unknown exit status"));
-                    }
-                }
-            };
-
-            if es != 0 {
-                return Err(anyhow!("Exit code = {}", es));
+        .map_err(|source| {
+            debug!("Command error {source}");
+            Error::ScriptSpawn {
+                command: script.to_owned(),
+                source,
             }
-            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
-        }
-        Err(error) => {
-            debug!("Command error {error}",);
-            Err(anyhow!(error.to_string()))
-        }
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::ScriptStatus {
+            command: script.to_owned(),
+            status: output.status,
+        });
     }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
 pub fn find_scripts(path: PathBuf) -> Result<Vec<PathBuf>> {
     if path.is_dir() {
         debug!("Scripts folder found {}", &path.display());
+        let scripts_dir_error = |source| Error::ScriptsDir {
+            path: path.clone(),
+            source,
+        };
         let mut files_vec: Vec<PathBuf> = Vec::new();
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
+        for entry in fs::read_dir(&path).map_err(scripts_dir_error)? {
+            let entry = entry.map_err(scripts_dir_error)?;
             files_vec.push(entry.path());
         }
         Ok(files_vec)
     } else {
-        Err(anyhow!("Can't find scripts folder {}", path.display()))
+        Err(Error::ScriptsDir {
+            path,
+            source: io::ErrorKind::NotFound.into(),
+        })
     }
 }
 
@@ -378,14 +480,15 @@ pub struct ScriptConfig {
 #[cfg(not(tarpaulin_include))]
 impl ScriptConfig {
     pub fn read_config() -> Result<ScriptConfig> {
-        let Some(mut home_dir) = dirs::home_dir() else {
-            return Err(anyhow!("Could not infer ScriptConfig path."));
-        };
-        home_dir.push(".rustscan_scripts.toml");
-
-        let content = fs::read_to_string(home_dir)?;
-        let config = toml::from_str::<ScriptConfig>(&content)?;
-        Ok(config)
+        let mut path = dirs::home_dir().ok_or(Error::NoConfigPath("script config"))?;
+        path.push(".rustscan_scripts.toml");
+
+        let content = fs::read_to_string(&path).map_err(|source| Error::ConfigRead {
+            path: path.clone(),
+            source,
+        })?;
+        toml::from_str::<ScriptConfig>(&content)
+            .map_err(|source| Error::ConfigParse { path, source })
     }
 }
 
@@ -447,6 +550,22 @@ mod tests {
         let _output = script.run().unwrap();
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn failing_script_returns_exit_status() {
+        let mut script_f =
+            ScriptFile::new("fixtures/.rustscan_scripts/test_script.txt".into()).unwrap();
+        script_f.call_format = Some("exit 3".to_string());
+        let script: Script = into_script(script_f);
+        match script.run() {
+            Err(Error::ScriptStatus { command, status }) => {
+                assert_eq!(command, "exit 3");
+                assert_eq!(status.code(), Some(3));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
     #[test]
     #[should_panic]
     fn open_nonexisting_script_file() {
@@ -507,6 +626,55 @@ mod tests {
         assert_eq!(output.trim(), "Total args passed to fixtures/.rustscan_scripts/test_script.pl : 2\nArg # 1 : 127.0.0.1\nArg # 2 : 80,8080");
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn http_placeholders_are_filled_and_quoted() {
+        let info = HttpInfo {
+            https: true,
+            status: 200,
+            reason: "OK".to_owned(),
+            server: Some("nginx".to_owned()),
+            title: Some("it's $(whoami)".to_owned()),
+            location: None,
+            content_length: None,
+        };
+        let script = Script::build(
+            None,
+            "127.0.0.1".parse().unwrap(),
+            vec![22, 443],
+            None,
+            None,
+            None,
+            Some("echo {{http_ports}} {{http_scheme}} {{http_status}} {{http_server}} {{http_title}}".to_owned()),
+        )
+        .with_http(vec![(443, info)]);
+
+        let output = script.run().unwrap();
+        assert_eq!(output.trim(), "443 https 200 nginx it's $(whoami)");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hostname_placeholder_falls_back_to_ip() {
+        let script = |hostname: Option<&str>| {
+            Script::build(
+                None,
+                "127.0.0.1".parse().unwrap(),
+                vec![443],
+                None,
+                None,
+                None,
+                Some("echo {{hostname}}:{{port}}".to_owned()),
+            )
+            .with_hostname(hostname)
+        };
+
+        let output = script(Some("example.com")).run().unwrap();
+        assert_eq!(output.trim(), "example.com:443");
+        let output = script(None).run().unwrap();
+        assert_eq!(output.trim(), "127.0.0.1:443");
+    }
+
     #[test]
     fn test_custom_directory_config() {
         // Create test config
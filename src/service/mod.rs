@@ -0,0 +1,374 @@
+//! 不依赖 nmap 的服务与版本识别。
+//!
+//! 在 [`Scanner::run`](crate::scanner::Scanner::run) 找到开放端口之后，
+//! [`ServiceDetector`] 会按照 nmap-service-probes 数据库向每个端口发送探测，
+//! 再用数据库中的 `match` / `softmatch` 正则识别服务名和版本。
+//!
+//! 探测数据库按以下顺序查找：
+//! 1. `--service-probes` 指定的文件；
+//! 2. 系统中 nmap 自带的 `nmap-service-probes`（如果安装了 nmap）；
+//! 3. 编译进 RustScan 的精简数据库。
+//!
+//! ```rust
+//! # use rustscan::service::{load_probes, ServiceDetector};
+//! # use std::time::Duration;
+//! let probes = load_probes(None).unwrap();
+//! let detector = ServiceDetector::new(probes, Duration::from_millis(1_500), 7);
+//! ```
+mod probes;
+pub use probes::{Protocol, ServiceMatch, ServiceProbe, ServiceProbes};
+
+use crate::scanner::{probe_concurrently, SourceOptions};
+use crate::{Error, Result};
+use async_std::io;
+use async_std::prelude::*;
+use log::debug;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// 编译进二进制的精简探测数据库。
+pub(crate) static BUILTIN_PROBES: &str = include_str!("../../nmap-service-probes");
+
+/// nmap 安装后 nmap-service-probes 通常所在的位置。
+const SYSTEM_PROBE_PATHS: [&str; 3] = [
+    "/usr/share/nmap/nmap-service-probes",
+    "/usr/local/share/nmap/nmap-service-probes",
+    "/opt/homebrew/share/nmap/nmap-service-probes",
+];
+
+/// 每个响应最多保留的字节数。
+const MAX_RESPONSE_LEN: usize = 16 * 1024;
+
+/// 默认同时探测的端口数。
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// 识别出的服务信息。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub info: Option<String>,
+    pub hostname: Option<String>,
+    pub os: Option<String>,
+    pub device_type: Option<String>,
+    pub cpes: Vec<String>,
+    /// 只有软匹配：知道是什么服务，但不知道具体的产品和版本。
+    pub soft: bool,
+}
+
+impl fmt::Display for ServiceInfo {
+    /// 格式化为 `ssh OpenSSH 9.6p1 (protocol 2.0)`。
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for part in [&self.product, &self.version].iter().copied().flatten() {
+            write!(f, " {part}")?;
+        }
+        if let Some(info) = &self.info {
+            write!(f, " ({info})")?;
+        }
+        Ok(())
+    }
+}
+
+/// 读取探测数据库，查找顺序见模块文档。
+pub fn load_probes(path: Option<&Path>) -> Result<ServiceProbes> {
+    if let Some(path) = path {
+        let error = |reason: String| Error::ServiceProbes {
+            path: path.to_owned(),
+            reason,
+        };
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        return ServiceProbes::parse(&content).map_err(|e| error(e.to_string()));
+    }
+
+    for system_path in SYSTEM_PROBE_PATHS {
+        if let Ok(content) = fs::read_to_string(system_path) {
+            match ServiceProbes::parse(&content) {
+                Ok(probes) => {
+                    debug!("Using service probes from {system_path}");
+                    return Ok(probes);
+                }
+                Err(e) => debug!("Ignoring service probes {system_path} {e}"),
+            }
+        }
+    }
+
+    ServiceProbes::parse(BUILTIN_PROBES).map_err(|e| Error::ServiceProbes {
+        path: "<built-in>".into(),
+        reason: e.to_string(),
+    })
+}
+
+/// 对开放端口进行服务识别。
+#[derive(Debug)]
+pub struct ServiceDetector {
+    probes: ServiceProbes,
+    timeout: Duration,
+    intensity: u8,
+    concurrency: usize,
+    source: SourceOptions,
+    /// 已经建立的连接和绑定的 socket 数，用来轮流选择源地址
+    sockets: AtomicUsize,
+}
+
+impl ServiceDetector {
+    /// `timeout` 是每个探测等待响应的最长时间（探测自己的 `totalwaitms` 更短时使用后者），
+    /// `intensity` 和 nmap 的 `--version-intensity` 一样，取值 0 到 9。
+    pub fn new(probes: ServiceProbes, timeout: Duration, intensity: u8) -> Self {
+        Self {
+            probes,
+            timeout,
+            intensity,
+            concurrency: DEFAULT_CONCURRENCY,
+            source: SourceOptions::default(),
+            sockets: AtomicUsize::new(0),
+        }
+    }
+
+    /// 设置同时探测的端口数。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 从和端口扫描相同的源地址、源端口或网络接口发出探测。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 识别所有给定 socket 上的服务，识别不出来的 socket 不会出现在结果中。
+    pub async fn detect(
+        &self,
+        sockets: &[SocketAddr],
+        protocol: Protocol,
+    ) -> HashMap<SocketAddr, ServiceInfo> {
+        let sockets = sockets
+            .iter()
+            .copied()
+            .filter(|socket| !self.probes.is_excluded(socket.port(), protocol));
+
+        probe_concurrently(sockets, self.concurrency, |socket| {
+            self.detect_socket(socket, protocol)
+        })
+        .await
+    }
+
+    /// 依次发送探测直到得到硬匹配。
+    /// 得到软匹配以后，只继续发送能确定该服务版本的探测。
+    async fn detect_socket(&self, socket: SocketAddr, protocol: Protocol) -> Option<ServiceInfo> {
+        let mut soft: Option<ServiceInfo> = None;
+
+        for probe in self
+            .probes
+            .probes_for(socket.port(), protocol, self.intensity)
+        {
+            if let Some(soft) = &soft {
+                if !probe.can_identify(&soft.name) {
+                    continue;
+                }
+            }
+
+            let response = match protocol {
+                Protocol::Tcp => self.exchange_tcp(socket, probe).await,
+                Protocol::Udp => self.exchange_udp(socket, probe).await,
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    debug!("Service detection stopped on {socket} {e}");
+                    break;
+                }
+                Err(e) => {
+                    debug!("Probe {} on {socket} failed {e}", probe.name);
+                    continue;
+                }
+            };
+            if response.is_empty() {
+                continue;
+            }
+
+            match self.probes.match_response(probe, &response) {
+                Some(info) if !info.soft => {
+                    debug!("Service on {socket} is {info}");
+                    return Some(info);
+                }
+                Some(info) if soft.is_none() => soft = Some(info),
+                _ => {}
+            }
+        }
+
+        soft
+    }
+
+    fn wait_for(&self, probe: &ServiceProbe) -> Duration {
+        probe
+            .total_wait
+            .map_or(self.timeout, |wait| wait.min(self.timeout))
+    }
+
+    /// 建立新连接、发送探测数据并读取响应。
+    /// 一直读到超时或连接关闭，提前得到硬匹配时立即返回。
+    async fn exchange_tcp(&self, socket: SocketAddr, probe: &ServiceProbe) -> io::Result<Vec<u8>> {
+        let nth = self.sockets.fetch_add(1, Ordering::Relaxed);
+        let mut stream = io::timeout(self.timeout, self.source.connect(socket, nth)).await?;
+        let wait = self.wait_for(probe);
+        let deadline = Instant::now() + wait;
+
+        if !probe.payload.is_empty() {
+            io::timeout(wait, stream.write_all(&probe.payload)).await?;
+        }
+
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while response.len() < MAX_RESPONSE_LEN {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match io::timeout(remaining, stream.read(&mut chunk)).await {
+                Ok(0) => break,
+                Ok(size) => {
+                    response.extend_from_slice(&chunk[..size]);
+                    let identified = self
+                        .probes
+                        .match_response(probe, &response)
+                        .is_some_and(|info| !info.soft);
+                    if identified {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) if response.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        response.truncate(MAX_RESPONSE_LEN);
+        Ok(response)
+    }
+
+    /// 发送 UDP 探测并等待一个响应报文。
+    async fn exchange_udp(&self, socket: SocketAddr, probe: &ServiceProbe) -> io::Result<Vec<u8>> {
+        let nth = self.sockets.fetch_add(1, Ordering::Relaxed);
+        let udp_socket = self.source.udp_bind(socket, nth).await?;
+        udp_socket.connect(socket).await?;
+        udp_socket.send(&probe.payload).await?;
+
+        let mut buf = vec![0u8; MAX_RESPONSE_LEN];
+        match io::timeout(self.wait_for(probe), udp_socket.recv(&mut buf)).await {
+            Ok(size) => {
+                buf.truncate(size);
+                Ok(buf)
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_probes, Protocol, ServiceDetector, ServiceInfo, ServiceProbes};
+    use async_std::task::block_on;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    #[test]
+    fn service_info_display() {
+        let info = ServiceInfo {
+            name: "ssh".to_owned(),
+            product: Some("OpenSSH".to_owned()),
+            version: Some("9.6p1".to_owned()),
+            info: Some("protocol 2.0".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(info.to_string(), "ssh OpenSSH 9.6p1 (protocol 2.0)");
+
+        let soft = ServiceInfo {
+            name: "http".to_owned(),
+            soft: true,
+            ..Default::default()
+        };
+        assert_eq!(soft.to_string(), "http");
+    }
+
+    #[test]
+    fn builtin_probes_load() {
+        assert!(load_probes(None).unwrap().probes().len() > 1);
+        assert!(load_probes(Some("does/not/exist".as_ref())).is_err());
+    }
+
+    #[test]
+    fn detects_banner_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6p1\r\n").unwrap();
+        });
+
+        let detector = ServiceDetector::new(
+            ServiceProbes::parse(super::BUILTIN_PROBES).unwrap(),
+            Duration::from_millis(500),
+            7,
+        );
+        let services = block_on(detector.detect(&[socket], Protocol::Tcp));
+
+        let ssh = &services[&socket];
+        assert_eq!(ssh.name, "ssh");
+        assert_eq!(ssh.version.as_deref(), Some("9.6p1"));
+    }
+
+    #[test]
+    fn detects_service_after_request_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 64];
+                // the NULL probe sends nothing, so this read times out on that connection
+                stream
+                    .set_read_timeout(Some(Duration::from_millis(400)))
+                    .unwrap();
+                if let Ok(size) = stream.read(&mut request) {
+                    if request[..size].starts_with(b"GET / ") {
+                        stream
+                            .write_all(b"HTTP/1.0 200 OK\r\nServer: nginx/1.25.3\r\n\r\n")
+                            .unwrap();
+                    }
+                }
+            }
+        });
+
+        let probes = ServiceProbes::parse(
+            "Probe TCP NULL q||\nProbe TCP GetRequest q|GET / HTTP/1.0\\r\\n\\r\\n|\nrarity 1\nmatch http m|^HTTP/1\\.[01] \\d\\d\\d .*?\\r\\nServer: nginx/([\\d.]+)|s p/nginx/ v/$1/\n",
+        )
+        .unwrap();
+        let detector = ServiceDetector::new(probes, Duration::from_millis(200), 7);
+        let services = block_on(detector.detect(&[socket], Protocol::Tcp));
+
+        assert_eq!(services[&socket].to_string(), "http nginx 1.25.3");
+    }
+
+    #[test]
+    fn closed_port_has_no_service() {
+        let socket: SocketAddr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let detector =
+            ServiceDetector::new(load_probes(None).unwrap(), Duration::from_millis(200), 7);
+        assert!(block_on(detector.detect(&[socket], Protocol::Tcp)).is_empty());
+    }
+}
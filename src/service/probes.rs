@@ -0,0 +1,675 @@
+//! 解析 nmap-service-probes 格式的服务探测数据库。
+//!
+//! 格式说明见 <https://nmap.org/book/vscan-fileformat.html>，这里支持的指令有：
+//! `Exclude`、`Probe`、`match`、`softmatch`、`ports`、`sslports`、`rarity`、
+//! `totalwaitms` 和 `fallback`，其余指令会被忽略。
+//!
+//! nmap 的正则是 PCRE，这里用 `regex` crate 编译。正则在第一次使用时才编译，
+//! 依赖 PCRE 独有特性（反向引用、环视）的 match 行编译失败后会被跳过。
+use super::ServiceInfo;
+use anyhow::{anyhow, Result};
+use log::debug;
+use once_cell::sync::OnceCell;
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use std::time::Duration;
+
+/// 没有 `rarity` 指令的探测使用的稀有度。
+const DEFAULT_RARITY: u8 = 5;
+
+/// 探测使用的传输层协议。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// 一个 `Probe` 指令以及它下面的所有 match 行。
+#[derive(Debug)]
+pub struct ServiceProbe {
+    pub protocol: Protocol,
+    pub name: String,
+    pub payload: Vec<u8>,
+    pub rarity: u8,
+    pub ports: Vec<(u16, u16)>,
+    pub ssl_ports: Vec<(u16, u16)>,
+    pub total_wait: Option<Duration>,
+    pub fallback: Vec<String>,
+    pub matches: Vec<ServiceMatch>,
+}
+
+impl ServiceProbe {
+    /// 这个探测是否专门针对该端口（出现在 `ports` 或 `sslports` 中）。
+    pub fn targets_port(&self, port: u16) -> bool {
+        port_in_ranges(port, &self.ports) || port_in_ranges(port, &self.ssl_ports)
+    }
+
+    /// 这个探测是否有能确定该服务的 match 行，用于软匹配之后筛选探测。
+    pub fn can_identify(&self, service: &str) -> bool {
+        self.matches.iter().any(|m| !m.soft && m.service == service)
+    }
+}
+
+/// 一条 `match` 或 `softmatch` 行。
+#[derive(Debug)]
+pub struct ServiceMatch {
+    pub service: String,
+    pub soft: bool,
+    pattern: String,
+    case_insensitive: bool,
+    dot_matches_new_line: bool,
+    template: VersionTemplate,
+    regex: OnceCell<Option<Regex>>,
+}
+
+/// match 行中的版本信息模板，其中的 `$1`、`$P(1)` 等会在匹配后被替换。
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct VersionTemplate {
+    product: Option<String>,
+    version: Option<String>,
+    info: Option<String>,
+    hostname: Option<String>,
+    os: Option<String>,
+    device_type: Option<String>,
+    cpes: Vec<String>,
+}
+
+impl ServiceMatch {
+    fn regex(&self) -> Option<&Regex> {
+        self.regex
+            .get_or_init(|| {
+                RegexBuilder::new(&translate_pattern(&self.pattern))
+                    .unicode(false)
+                    .case_insensitive(self.case_insensitive)
+                    .dot_matches_new_line(self.dot_matches_new_line)
+                    .build()
+                    .map_err(|e| debug!("Skipping match for {} {e}", self.service))
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// 用响应去匹配这一行，成功时返回填好版本信息的 [`ServiceInfo`]。
+    pub fn apply(&self, response: &[u8]) -> Option<ServiceInfo> {
+        let captures = self.regex()?.captures(response)?;
+        let fill = |field: &Option<String>| {
+            field
+                .as_deref()
+                .map(|template| substitute(template, &captures))
+                .filter(|value| !value.is_empty())
+        };
+
+        Some(ServiceInfo {
+            name: self.service.clone(),
+            product: fill(&self.template.product),
+            version: fill(&self.template.version),
+            info: fill(&self.template.info),
+            hostname: fill(&self.template.hostname),
+            os: fill(&self.template.os),
+            device_type: fill(&self.template.device_type),
+            cpes: self
+                .template
+                .cpes
+                .iter()
+                .map(|cpe| substitute(cpe, &captures))
+                .collect(),
+            soft: self.soft,
+        })
+    }
+}
+
+/// 解析好的探测数据库。
+#[derive(Debug, Default)]
+pub struct ServiceProbes {
+    probes: Vec<ServiceProbe>,
+    excluded_tcp: Vec<(u16, u16)>,
+    excluded_udp: Vec<(u16, u16)>,
+}
+
+impl ServiceProbes {
+    /// 解析 nmap-service-probes 格式的内容。
+    ///
+    /// ```rust
+    /// # use rustscan::service::ServiceProbes;
+    /// let probes = ServiceProbes::parse(
+    ///     "Probe TCP NULL q||\nmatch ssh m|^SSH-([\\d.]+)-| i/protocol $1/\n",
+    /// ).unwrap();
+    /// assert_eq!(probes.probes().len(), 1);
+    /// ```
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut parsed = Self::default();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+            let error = |e: anyhow::Error| anyhow!("line {}: {e}", number + 1);
+
+            if directive == "Exclude" {
+                parsed.parse_exclude(rest).map_err(error)?;
+                continue;
+            }
+            if directive == "Probe" {
+                parsed.probes.push(parse_probe(rest).map_err(error)?);
+                continue;
+            }
+
+            let Some(probe) = parsed.probes.last_mut() else {
+                return Err(anyhow!(
+                    "line {}: `{directive}` before any Probe",
+                    number + 1
+                ));
+            };
+            match directive {
+                "match" | "softmatch" => probe
+                    .matches
+                    .push(parse_match(rest, directive == "softmatch").map_err(error)?),
+                "ports" => probe.ports = parse_ports(rest).map_err(error)?,
+                "sslports" => probe.ssl_ports = parse_ports(rest).map_err(error)?,
+                "rarity" => {
+                    probe.rarity = rest
+                        .parse()
+                        .map_err(|_| anyhow!("line {}: invalid rarity {rest}", number + 1))?;
+                }
+                "totalwaitms" => {
+                    let millis: u64 = rest
+                        .parse()
+                        .map_err(|_| anyhow!("line {}: invalid totalwaitms {rest}", number + 1))?;
+                    probe.total_wait = Some(Duration::from_millis(millis));
+                }
+                "fallback" => {
+                    probe.fallback = rest.split(',').map(|s| s.trim().to_owned()).collect();
+                }
+                _ => debug!("Ignoring service probe directive {directive}"),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn probes(&self) -> &[ServiceProbe] {
+        &self.probes
+    }
+
+    /// 按名字和协议查找探测。
+    pub fn probe(&self, name: &str, protocol: Protocol) -> Option<&ServiceProbe> {
+        self.probes
+            .iter()
+            .find(|p| p.name == name && p.protocol == protocol)
+    }
+
+    /// 端口是否被 `Exclude` 指令排除（例如会把探测数据打印出来的打印机端口）。
+    pub fn is_excluded(&self, port: u16, protocol: Protocol) -> bool {
+        match protocol {
+            Protocol::Tcp => port_in_ranges(port, &self.excluded_tcp),
+            Protocol::Udp => port_in_ranges(port, &self.excluded_udp),
+        }
+    }
+
+    /// 按 nmap 的顺序返回要对某个端口发送的探测：
+    /// 先是 NULL 探测，然后是专门针对该端口的探测，最后是稀有度不超过 `intensity` 的其它探测。
+    pub fn probes_for(&self, port: u16, protocol: Protocol, intensity: u8) -> Vec<&ServiceProbe> {
+        let candidates = self.probes.iter().filter(|p| p.protocol == protocol);
+        let (mut targeted, mut rest): (Vec<&ServiceProbe>, Vec<&ServiceProbe>) = candidates
+            .filter(|p| p.name != "NULL")
+            .partition(|p| p.targets_port(port));
+        targeted.sort_by_key(|p| p.rarity);
+        rest.retain(|p| p.rarity <= intensity);
+        rest.sort_by_key(|p| p.rarity);
+
+        self.probe("NULL", protocol)
+            .filter(|_| protocol == Protocol::Tcp)
+            .into_iter()
+            .chain(targeted)
+            .chain(rest)
+            .collect()
+    }
+
+    /// 用某个探测得到的响应去匹配数据库。
+    ///
+    /// 依次尝试该探测自己的 match 行、`fallback` 中列出的探测，
+    /// 以及（对 TCP）NULL 探测的 match 行。硬匹配优先，否则返回第一个软匹配。
+    pub fn match_response(&self, probe: &ServiceProbe, response: &[u8]) -> Option<ServiceInfo> {
+        let fallbacks = probe
+            .fallback
+            .iter()
+            .filter_map(|name| self.probe(name, probe.protocol));
+        let null = self
+            .probe("NULL", Protocol::Tcp)
+            .filter(|null| probe.protocol == Protocol::Tcp && null.name != probe.name);
+
+        let mut soft: Option<ServiceInfo> = None;
+        for candidate in std::iter::once(probe).chain(fallbacks).chain(null) {
+            for service_match in &candidate.matches {
+                if soft.is_some() && service_match.soft {
+                    continue;
+                }
+                match service_match.apply(response) {
+                    Some(info) if !info.soft => return Some(info),
+                    Some(info) => soft = Some(info),
+                    None => {}
+                }
+            }
+        }
+        soft
+    }
+
+    fn parse_exclude(&mut self, rest: &str) -> Result<()> {
+        for spec in rest.split(',') {
+            let spec = spec.trim();
+            if let Some(ports) = spec.strip_prefix("T:") {
+                self.excluded_tcp.extend(parse_ports(ports)?);
+            } else if let Some(ports) = spec.strip_prefix("U:") {
+                self.excluded_udp.extend(parse_ports(ports)?);
+            } else {
+                let ranges = parse_ports(spec)?;
+                self.excluded_tcp.extend(&ranges);
+                self.excluded_udp.extend(&ranges);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 解析 `Probe TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|`。
+fn parse_probe(rest: &str) -> Result<ServiceProbe> {
+    let mut parts = rest.splitn(3, ' ');
+    let protocol = match parts.next() {
+        Some("TCP") => Protocol::Tcp,
+        Some("UDP") => Protocol::Udp,
+        other => return Err(anyhow!("unknown probe protocol {other:?}")),
+    };
+    let name = parts
+        .next()
+        .filter(|n| !n.is_empty())
+        .ok_or_else(|| anyhow!("probe without a name"))?;
+    let payload = parts
+        .next()
+        .and_then(|p| p.strip_prefix('q'))
+        .ok_or_else(|| anyhow!("probe {name} without a q|...| payload"))?;
+    let (payload, _) = split_delimited(payload)?;
+
+    Ok(ServiceProbe {
+        protocol,
+        name: name.to_owned(),
+        payload: unescape(payload),
+        rarity: DEFAULT_RARITY,
+        ports: vec![],
+        ssl_ports: vec![],
+        total_wait: None,
+        fallback: vec![],
+        matches: vec![],
+    })
+}
+
+/// 解析 `ssh m|^SSH-([\d.]+)-|s p/OpenSSH/ v/$1/ cpe:/a:openbsd:openssh:$1/`。
+fn parse_match(rest: &str, soft: bool) -> Result<ServiceMatch> {
+    let (service, pattern) = rest
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("match without a pattern"))?;
+    let pattern = pattern
+        .strip_prefix('m')
+        .ok_or_else(|| anyhow!("pattern for {service} must start with m"))?;
+    let (pattern, rest) = split_delimited(pattern)?;
+
+    let flags_end = rest.find(' ').unwrap_or(rest.len());
+    let (flags, version_info) = rest.split_at(flags_end);
+
+    Ok(ServiceMatch {
+        service: service.to_owned(),
+        soft,
+        pattern: pattern.to_owned(),
+        case_insensitive: flags.contains('i'),
+        dot_matches_new_line: flags.contains('s'),
+        template: parse_version_info(version_info)?,
+        regex: OnceCell::new(),
+    })
+}
+
+/// 解析 match 行末尾的 `p/.../ v/.../ i/.../ h/.../ o/.../ d/.../ cpe:/.../` 字段。
+fn parse_version_info(mut rest: &str) -> Result<VersionTemplate> {
+    let mut template = VersionTemplate::default();
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(template);
+        }
+
+        let (field, after_field) = match rest.strip_prefix("cpe:") {
+            Some(after) => ("cpe", after),
+            None => rest.split_at(1),
+        };
+        let (value, after_value) = split_delimited(after_field)?;
+        // 跳过字段后面的标志位，例如 cpe 的 `a`
+        let next = after_value.find(' ').unwrap_or(after_value.len());
+        rest = &after_value[next..];
+
+        let value = value.to_owned();
+        match field {
+            "p" => template.product = Some(value),
+            "v" => template.version = Some(value),
+            "i" => template.info = Some(value),
+            "h" => template.hostname = Some(value),
+            "o" => template.os = Some(value),
+            "d" => template.device_type = Some(value),
+            "cpe" => template.cpes.push(format!("cpe:/{value}")),
+            other => debug!("Ignoring version info field {other}"),
+        }
+    }
+}
+
+/// 把 `|abc|rest` 拆成 `("abc", "rest")`，第一个字符就是分隔符。
+fn split_delimited(input: &str) -> Result<(&str, &str)> {
+    let delimiter = input
+        .chars()
+        .next()
+        .ok_or_else(|| anyhow!("missing delimiter"))?;
+    let body = &input[delimiter.len_utf8()..];
+    let end = body
+        .find(delimiter)
+        .ok_or_else(|| anyhow!("unterminated {delimiter}...{delimiter}"))?;
+    Ok((&body[..end], &body[end + delimiter.len_utf8()..]))
+}
+
+/// 解析 `21,80-85,443` 这样的端口列表。
+fn parse_ports(input: &str) -> Result<Vec<(u16, u16)>> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|segment| {
+            let (start, end) = segment.split_once('-').unwrap_or((segment, segment));
+            match (start.parse::<u16>(), end.parse::<u16>()) {
+                (Ok(start), Ok(end)) if start <= end => Ok((start, end)),
+                _ => Err(anyhow!("invalid port range {segment}")),
+            }
+        })
+        .collect()
+}
+
+fn port_in_ranges(port: u16, ranges: &[(u16, u16)]) -> bool {
+    ranges
+        .iter()
+        .any(|&(start, end)| start <= port && port <= end)
+}
+
+/// 解码探测数据中的 C 风格转义：`\0 \a \b \f \n \r \t \v \\ \xHH`。
+fn unescape(input: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match iter.next() {
+            Some(b'0') => bytes.push(0),
+            Some(b'a') => bytes.push(0x07),
+            Some(b'b') => bytes.push(0x08),
+            Some(b'f') => bytes.push(0x0c),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'v') => bytes.push(0x0b),
+            Some(b'x') => {
+                let hex: Vec<u8> = iter.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(value) if hex.len() == 2 => bytes.push(value),
+                    _ => {
+                        bytes.extend_from_slice(b"\\x");
+                        bytes.extend_from_slice(&hex);
+                    }
+                }
+            }
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+
+    bytes
+}
+
+/// 把 PCRE 写法转换成 `regex` crate 能接受的写法：`\0` 写成 `\x00`。
+fn translate_pattern(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            translated.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => translated.push_str("\\x00"),
+            Some(other) => {
+                translated.push('\\');
+                translated.push(other);
+            }
+            None => translated.push('\\'),
+        }
+    }
+
+    translated
+}
+
+/// 替换模板中的 `$1`、`$P(1)` 和 `$SUBST(1,"from","to")`。
+fn substitute(template: &str, captures: &Captures) -> String {
+    let group = |index: &str| -> String {
+        index
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| captures.get(i))
+            .map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned())
+            .unwrap_or_default()
+    };
+
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(position) = rest.find('$') {
+        output.push_str(&rest[..position]);
+        let after = &rest[position + 1..];
+
+        if let Some(args) = after.strip_prefix("P(") {
+            if let Some(end) = args.find(')') {
+                output.extend(group(&args[..end]).chars().filter(|c| !c.is_control()));
+                rest = &args[end + 1..];
+                continue;
+            }
+        } else if let Some(args) = after.strip_prefix("SUBST(") {
+            if let Some(end) = args.find(')') {
+                let parts: Vec<&str> = args[..end].splitn(3, ',').collect();
+                if let [index, from, to] = parts.as_slice() {
+                    let from = from.trim().trim_matches('"');
+                    let to = to.trim().trim_matches('"');
+                    output.push_str(&group(index).replace(from, to));
+                }
+                rest = &args[end + 1..];
+                continue;
+            }
+        } else {
+            let digits = after.chars().take_while(char::is_ascii_digit).count();
+            if digits > 0 {
+                output.push_str(&group(&after[..digits]));
+                rest = &after[digits..];
+                continue;
+            }
+        }
+
+        output.push('$');
+        rest = after;
+    }
+
+    output.push_str(rest);
+    output.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ports, unescape, Protocol, ServiceProbes};
+
+    const PROBES: &str = r#"
+# comment
+Exclude T:9100-9107,U:30000
+
+Probe TCP NULL q||
+totalwaitms 6000
+match ssh m|^SSH-([\d.]+)-OpenSSH_([\w._-]+)\r?\n| p/OpenSSH/ v/$2/ i/protocol $1/ cpe:/a:openbsd:openssh:$2/
+softmatch ssh m|^SSH-([\d.]+)-| i/protocol $1/
+match mysql m|^.\0\0\0\n(5\.[\w.]+)\0|s p/MySQL/ v/$1/
+match weird m|^(?=lookahead)| p/unsupported/
+
+Probe TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|
+rarity 1
+ports 80,8000-8010
+fallback NULL
+match http m=^HTTP/1\.[01] \d\d\d .*\r\nServer: nginx/([\d.]+)=si p/nginx/ v/$1/ cpe:/a:igor_sysoev:nginx:$1/a
+softmatch http m|^HTTP/1\.[01] \d\d\d|
+
+Probe TCP Rare q|rare|
+rarity 9
+
+Probe UDP DNS q|\0\x06\x01\0|
+rarity 1
+ports 53
+"#;
+
+    #[test]
+    fn parses_probes_and_directives() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        assert_eq!(probes.probes().len(), 4);
+
+        let null = probes.probe("NULL", Protocol::Tcp).unwrap();
+        assert!(null.payload.is_empty());
+        assert_eq!(null.matches.len(), 4);
+        assert_eq!(null.total_wait, Some(std::time::Duration::from_secs(6)));
+
+        let get = probes.probe("GetRequest", Protocol::Tcp).unwrap();
+        assert_eq!(get.payload, b"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(get.rarity, 1);
+        assert_eq!(get.ports, vec![(80, 80), (8000, 8010)]);
+        assert_eq!(get.fallback, vec!["NULL".to_owned()]);
+
+        let dns = probes.probe("DNS", Protocol::Udp).unwrap();
+        assert_eq!(dns.payload, vec![0, 6, 1, 0]);
+
+        assert!(probes.is_excluded(9101, Protocol::Tcp));
+        assert!(!probes.is_excluded(9101, Protocol::Udp));
+        assert!(probes.is_excluded(30000, Protocol::Udp));
+    }
+
+    #[test]
+    fn orders_probes_by_port_and_rarity() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let names = |port, intensity| -> Vec<String> {
+            probes
+                .probes_for(port, Protocol::Tcp, intensity)
+                .iter()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+
+        assert_eq!(names(22, 7), vec!["NULL", "GetRequest"]);
+        assert_eq!(names(22, 0), vec!["NULL"]);
+        assert_eq!(names(8005, 0), vec!["NULL", "GetRequest"]);
+        assert_eq!(names(22, 9), vec!["NULL", "GetRequest", "Rare"]);
+        assert_eq!(
+            probes
+                .probes_for(53, Protocol::Udp, 7)
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["DNS"]
+        );
+    }
+
+    #[test]
+    fn hard_match_fills_version_info() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let null = probes.probe("NULL", Protocol::Tcp).unwrap();
+
+        let info = probes
+            .match_response(null, b"SSH-2.0-OpenSSH_9.6p1\r\n")
+            .unwrap();
+        assert_eq!(info.name, "ssh");
+        assert!(!info.soft);
+        assert_eq!(info.product.as_deref(), Some("OpenSSH"));
+        assert_eq!(info.version.as_deref(), Some("9.6p1"));
+        assert_eq!(info.info.as_deref(), Some("protocol 2.0"));
+        assert_eq!(info.cpes, vec!["cpe:/a:openbsd:openssh:9.6p1".to_owned()]);
+    }
+
+    #[test]
+    fn soft_match_and_binary_patterns() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let null = probes.probe("NULL", Protocol::Tcp).unwrap();
+
+        let info = probes.match_response(null, b"SSH-1.99-Cisco\r\n").unwrap();
+        assert_eq!(info.name, "ssh");
+        assert!(info.soft);
+
+        let info = probes
+            .match_response(null, b"J\0\0\0\n5.7.33\0\x01\x02")
+            .unwrap();
+        assert_eq!(info.name, "mysql");
+        assert_eq!(info.version.as_deref(), Some("5.7.33"));
+
+        assert!(probes.match_response(null, b"lookahead").is_none());
+    }
+
+    #[test]
+    fn fallback_and_flags_are_used() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let get = probes.probe("GetRequest", Protocol::Tcp).unwrap();
+
+        let info = probes
+            .match_response(get, b"HTTP/1.1 200 OK\r\nserver: NGINX/1.25.3\r\n\r\n")
+            .unwrap();
+        assert_eq!(info.name, "http");
+        assert_eq!(info.version.as_deref(), Some("1.25.3"));
+
+        // the NULL probe is used as fallback for the GetRequest probe
+        let info = probes
+            .match_response(get, b"SSH-2.0-OpenSSH_8.0\r\n")
+            .unwrap();
+        assert_eq!(info.name, "ssh");
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(ServiceProbes::parse("match ssh m|^SSH|").is_err());
+        assert!(ServiceProbes::parse("Probe SCTP NULL q||").is_err());
+        assert!(ServiceProbes::parse("Probe TCP NULL q|unterminated").is_err());
+        assert!(ServiceProbes::parse("Probe TCP NULL q||\nrarity high").is_err());
+        assert!(parse_ports("80-79").is_err());
+    }
+
+    #[test]
+    fn unescapes_probe_payloads() {
+        assert_eq!(unescape(r"\r\n\0\x41\\"), b"\r\n\0A\\".to_vec());
+        assert_eq!(unescape(r"\xZZ"), b"\\xZZ".to_vec());
+    }
+
+    #[test]
+    fn builtin_database_compiles() {
+        let probes = ServiceProbes::parse(super::super::BUILTIN_PROBES).unwrap();
+        for probe in probes.probes() {
+            for service_match in &probe.matches {
+                assert!(
+                    service_match.regex().is_some(),
+                    "{} pattern does not compile",
+                    service_match.service
+                );
+            }
+        }
+    }
+}
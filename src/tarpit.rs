@@ -0,0 +1,264 @@
+//! 识别 tarpit 和"所有端口都开放"的主机。
+//!
+//! SYN 代理防火墙和 tarpit 会接受每一个连接，扫描器因此报告成千上万个开放端口，
+//! 脚本也会对着它们逐个运行。这里用两种方法识别这类主机：开放端口占扫描端口的比例超过阈值，
+//! 或者抽样的开放端口接受连接之后，即使收到数据也从不响应。
+//! 抽样检查会产生额外的连接，需要用 [`TarpitDetector::with_sample`] 打开。
+//!
+//! ```rust
+//! # use rustscan::tarpit::{Tarpit, TarpitDetector};
+//! # use std::time::Duration;
+//! let detector = TarpitDetector::new(Duration::from_millis(1_500));
+//! assert_eq!(
+//!     detector.open_ratio(950, 1_000),
+//!     Some(Tarpit::OpenRatio { open: 950, scanned: 1_000 })
+//! );
+//! // 端口太少时比例没有意义
+//! assert_eq!(detector.open_ratio(2, 2), None);
+//! ```
+use crate::report::HostReport;
+use crate::scanner::{probe_concurrently, SourceOptions};
+use async_std::io;
+use async_std::prelude::*;
+use log::debug;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 默认的开放比例阈值（百分比）。
+pub const DEFAULT_THRESHOLD: u8 = 90;
+/// 扫描的端口少于这个数时不按比例判断。
+const MIN_SCANNED_PORTS: usize = 100;
+/// 开放端口少于这个数的主机不做抽样检查。
+const MIN_OPEN_PORTS: usize = 20;
+/// 默认同时检查的端口数。
+const DEFAULT_CONCURRENCY: usize = 100;
+/// 发给抽样端口的数据，大多数服务收到后会回复错误或者关闭连接。
+const NUDGE: &[u8] = b"\r\n\r\n";
+
+/// 主机被认为是 tarpit 的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tarpit {
+    /// 扫描的端口中开放的比例超过了阈值
+    OpenRatio { open: usize, scanned: usize },
+    /// 抽样的开放端口都接受了连接，但没有任何响应
+    Silent { sampled: usize },
+}
+
+impl fmt::Display for Tarpit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tarpit::OpenRatio { open, scanned } => {
+                write!(f, "{open} of {scanned} scanned ports are open")
+            }
+            Tarpit::Silent { sampled } => write!(
+                f,
+                "{sampled} sampled open ports accepted connections but never responded"
+            ),
+        }
+    }
+}
+
+/// 在端口扫描之后识别 tarpit。
+#[derive(Debug, Clone)]
+pub struct TarpitDetector {
+    timeout: Duration,
+    threshold: u8,
+    sample: usize,
+    concurrency: usize,
+    source: SourceOptions,
+    /// 已经发起的连接数，用来轮流选择源地址
+    connects: Arc<AtomicUsize>,
+}
+
+impl TarpitDetector {
+    /// `timeout` 同时限制建立连接和等待响应的时间。
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            threshold: DEFAULT_THRESHOLD,
+            sample: 0,
+            concurrency: DEFAULT_CONCURRENCY,
+            source: SourceOptions::default(),
+            connects: Arc::default(),
+        }
+    }
+
+    /// 开放端口占扫描端口的百分比达到多少时认为是 tarpit，默认 90。
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: u8) -> Self {
+        self.threshold = threshold.clamp(1, 100);
+        self
+    }
+
+    /// 每个主机抽样检查的开放端口数，默认 0，只按比例判断，不建立任何连接。
+    #[must_use]
+    pub fn with_sample(mut self, sample: usize) -> Self {
+        self.sample = sample;
+        self
+    }
+
+    /// 设置同时检查的端口数。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 从和端口扫描相同的源地址、源端口或网络接口发起连接。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 按开放端口的比例判断，`scanned` 是每个主机扫描的端口数。
+    pub fn open_ratio(&self, open: usize, scanned: usize) -> Option<Tarpit> {
+        if scanned < MIN_SCANNED_PORTS || open * 100 < scanned * usize::from(self.threshold) {
+            return None;
+        }
+        Some(Tarpit::OpenRatio { open, scanned })
+    }
+
+    /// 检查所有主机，只返回被认为是 tarpit 的主机。
+    pub async fn detect(&self, hosts: &[HostReport], scanned: usize) -> HashMap<IpAddr, Tarpit> {
+        let mut tarpits = HashMap::new();
+        let mut samples: HashMap<IpAddr, usize> = HashMap::new();
+        let mut sockets = Vec::new();
+        for host in hosts {
+            if let Some(tarpit) = self.open_ratio(host.ports.len(), scanned) {
+                tarpits.insert(host.ip, tarpit);
+            } else if self.sample > 0 && host.ports.len() >= MIN_OPEN_PORTS {
+                // 在开放端口中均匀地抽样
+                let count = self.sample.min(host.ports.len());
+                let step = (host.ports.len() / count).max(1);
+                let before = sockets.len();
+                sockets.extend(host.sockets().into_iter().step_by(step).take(count));
+                samples.insert(host.ip, sockets.len() - before);
+            }
+        }
+
+        let silent = probe_concurrently(sockets, self.concurrency, |socket| async move {
+            self.is_silent(socket).await.then_some(())
+        })
+        .await;
+        let mut silent_per_host: HashMap<IpAddr, usize> = HashMap::new();
+        for socket in silent.keys() {
+            *silent_per_host.entry(socket.ip()).or_default() += 1;
+        }
+        for (ip, sampled) in samples {
+            if silent_per_host.get(&ip) == Some(&sampled) {
+                tarpits.insert(ip, Tarpit::Silent { sampled });
+            }
+        }
+        tarpits
+    }
+
+    /// 连接成功并发送数据之后，直到超时都没有收到任何响应（包括关闭连接）。
+    async fn is_silent(&self, socket: SocketAddr) -> bool {
+        let nth = self.connects.fetch_add(1, Ordering::Relaxed);
+        let Ok(mut stream) = io::timeout(self.timeout, self.source.connect(socket, nth)).await
+        else {
+            return false;
+        };
+        if let Err(e) = stream.write_all(NUDGE).await {
+            debug!("Could not write to {socket} {e}");
+            return false;
+        }
+        let mut byte = [0u8; 1];
+        matches!(
+            io::timeout(self.timeout, stream.read(&mut byte)).await,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tarpit, TarpitDetector};
+    use crate::report::group_by_host;
+    use crate::scanner::OpenSocket;
+    use async_std::task::block_on;
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn open_ratio_respects_threshold() {
+        let detector = TarpitDetector::new(Duration::from_millis(100)).with_threshold(50);
+        assert_eq!(detector.open_ratio(49, 100), None);
+        assert_eq!(
+            detector.open_ratio(50, 100),
+            Some(Tarpit::OpenRatio {
+                open: 50,
+                scanned: 100
+            })
+        );
+        assert_eq!(detector.open_ratio(99, 99), None);
+    }
+
+    /// 监听 `count` 个端口，`respond` 为真时对每个连接回复一行数据。
+    fn listeners(count: usize, respond: bool) -> (Vec<TcpListener>, Vec<OpenSocket>) {
+        let listeners: Vec<TcpListener> = (0..count)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let open = listeners
+            .iter()
+            .map(|listener| OpenSocket::new(listener.local_addr().unwrap()))
+            .collect();
+        if respond {
+            for listener in &listeners {
+                let listener = listener.try_clone().unwrap();
+                thread::spawn(move || {
+                    for mut stream in listener.incoming().flatten() {
+                        let _ = stream.write_all(b"hello\r\n");
+                    }
+                });
+            }
+        }
+        (listeners, open)
+    }
+
+    #[test]
+    fn silent_hosts_are_tarpits() {
+        let detector = TarpitDetector::new(Duration::from_millis(200)).with_sample(5);
+
+        // 内核替没有 accept 的监听端口完成握手，之后不会有任何响应
+        let (_silent, open) = listeners(20, false);
+        let hosts = group_by_host(open);
+        let tarpits = block_on(detector.detect(&hosts, 65_535));
+        assert_eq!(
+            tarpits.get(&hosts[0].ip),
+            Some(&Tarpit::Silent { sampled: 5 })
+        );
+
+        let (_talking, open) = listeners(20, true);
+        let hosts = group_by_host(open);
+        assert!(block_on(detector.detect(&hosts, 65_535)).is_empty());
+
+        // 开放端口太少的主机不做抽样检查
+        let socket: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let hosts = group_by_host(vec![OpenSocket::new(socket)]);
+        assert!(block_on(detector.detect(&hosts, 65_535)).is_empty());
+
+        // 抽样数大于开放端口数时检查所有开放端口
+        let detector = detector.with_sample(50);
+        let (_silent, open) = listeners(20, false);
+        let hosts = group_by_host(open);
+        let tarpits = block_on(detector.detect(&hosts, 65_535));
+        assert_eq!(
+            tarpits.get(&hosts[0].ip),
+            Some(&Tarpit::Silent { sampled: 20 })
+        );
+
+        // 默认不抽样，不建立任何连接
+        let (_silent, open) = listeners(20, false);
+        let hosts = group_by_host(open);
+        let detector = TarpitDetector::new(Duration::from_millis(200));
+        assert!(block_on(detector.detect(&hosts, 65_535)).is_empty());
+    }
+}
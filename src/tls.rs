@@ -0,0 +1,326 @@
+//! 扫描之后的 TLS 探测阶段。
+//!
+//! 对开放的 TCP 端口发起 TLS 握手（ClientHello），记录协商出的协议版本、
+//! 加密套件和 ALPN，并解析服务器叶子证书的主题、SAN、签发者和有效期。
+//! 这样一次 RustScan 扫描就能得到证书过期时间和主机名清单。
+//!
+//! 这里只是收集信息，所以不会校验证书链：自签名或者已经过期的证书也会被记录下来。
+//!
+//! ```rust
+//! # use rustscan::tls::TlsProber;
+//! # use std::time::Duration;
+//! let prober = TlsProber::new(Duration::from_millis(1_500));
+//! ```
+use crate::scanner::{probe_concurrently, SourceOptions};
+use async_std::io;
+use futures_rustls::TlsConnector;
+use log::debug;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ServerName};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+use x509_parser::time::ASN1Time;
+
+/// 默认同时探测的端口数。
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// 握手时提供的 ALPN 协议。
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// 一次成功握手得到的信息。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// 例如 `TLSv1_3`
+    pub version: String,
+    /// 例如 `TLS13_AES_256_GCM_SHA384`
+    pub cipher: String,
+    pub alpn: Option<String>,
+    pub certificate: Option<CertificateInfo>,
+}
+
+/// 叶子证书中对资产盘点有用的字段。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS 名称和 IP 地址形式的 SAN
+    pub subject_alt_names: Vec<String>,
+    /// Unix 时间戳（秒）
+    pub not_before: i64,
+    /// Unix 时间戳（秒）
+    pub not_after: i64,
+}
+
+impl CertificateInfo {
+    /// 从 DER 编码的证书中提取字段，证书无法解析时返回 `None`。
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|e| debug!("Could not parse certificate {e}"))
+            .ok()?;
+
+        let subject_alt_names = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some((*dns).to_owned()),
+                    GeneralName::IPAddress(bytes) => ip_from_bytes(bytes),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            subject_alt_names,
+            not_before: cert.validity().not_before.timestamp(),
+            not_after: cert.validity().not_after.timestamp(),
+        })
+    }
+
+    /// 证书在给定时间点是否已经过期。
+    pub fn is_expired_at(&self, now: SystemTime) -> bool {
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| i64::try_from(d.as_secs()).unwrap_or(i64::MAX));
+        self.not_after < now
+    }
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(|b| std::net::Ipv4Addr::from(b).to_string()),
+        16 => <[u8; 16]>::try_from(bytes)
+            .ok()
+            .map(|b| std::net::Ipv6Addr::from(b).to_string()),
+        _ => None,
+    }
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    ASN1Time::from_timestamp(timestamp).map_or_else(|_| timestamp.to_string(), |t| t.to_string())
+}
+
+impl fmt::Display for TlsInfo {
+    /// 格式化为 `TLSv1_3 TLS13_AES_256_GCM_SHA384 alpn=h2 subject="CN=example.com" ...`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.version, self.cipher)?;
+        if let Some(alpn) = &self.alpn {
+            write!(f, " alpn={alpn}")?;
+        }
+        if let Some(cert) = &self.certificate {
+            write!(
+                f,
+                " subject=\"{}\" san=[{}] issuer=\"{}\" expires=\"{}\"",
+                cert.subject,
+                cert.subject_alt_names.join(","),
+                cert.issuer,
+                format_timestamp(cert.not_after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 接受任何证书的校验器：我们只需要读取证书，而不是信任它。
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// 不校验证书的 TLS 客户端，握手时提供给定的 ALPN 协议。
+pub(crate) fn insecure_connector(alpn: &[&[u8]]) -> TlsConnector {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    TlsConnector::from(Arc::new(config))
+}
+
+/// 对开放端口进行 TLS 握手探测。
+pub struct TlsProber {
+    connector: TlsConnector,
+    timeout: Duration,
+    concurrency: usize,
+    source: SourceOptions,
+    /// 已经发起的连接数，用来轮流选择源地址
+    connects: AtomicUsize,
+}
+
+impl fmt::Debug for TlsProber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsProber")
+            .field("timeout", &self.timeout)
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsProber {
+    /// `timeout` 同时限制建立 TCP 连接和完成 TLS 握手的时间。
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            connector: insecure_connector(&ALPN_PROTOCOLS),
+            timeout,
+            concurrency: DEFAULT_CONCURRENCY,
+            source: SourceOptions::default(),
+            connects: AtomicUsize::new(0),
+        }
+    }
+
+    /// 设置同时探测的端口数。
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 从和端口扫描相同的源地址、源端口或网络接口发起连接。
+    #[must_use]
+    pub fn with_source(mut self, source: SourceOptions) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// 对所有给定的 socket 尝试 TLS 握手，握手失败的 socket 不会出现在结果中。
+    pub async fn probe(&self, sockets: &[SocketAddr]) -> HashMap<SocketAddr, TlsInfo> {
+        probe_concurrently(
+            sockets.iter().copied(),
+            self.concurrency,
+            |socket| async move {
+                match self.handshake(socket).await {
+                    Ok(info) => Some(info),
+                    Err(e) => {
+                        debug!("TLS handshake with {socket} failed {e}");
+                        None
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// 与一个 socket 完成握手并读取会话信息。
+    pub async fn handshake(&self, socket: SocketAddr) -> io::Result<TlsInfo> {
+        let nth = self.connects.fetch_add(1, Ordering::Relaxed);
+        let stream = io::timeout(self.timeout, self.source.connect(socket, nth)).await?;
+        let server_name = ServerName::IpAddress(socket.ip());
+        let tls = io::timeout(self.timeout, self.connector.connect(server_name, stream)).await?;
+        let (_, session) = tls.get_ref();
+
+        Ok(TlsInfo {
+            version: session
+                .protocol_version()
+                .map_or_else(|| "unknown".to_owned(), |v| format!("{v:?}")),
+            cipher: session
+                .negotiated_cipher_suite()
+                .map_or_else(|| "unknown".to_owned(), |c| format!("{:?}", c.suite())),
+            alpn: session
+                .alpn_protocol()
+                .map(|p| String::from_utf8_lossy(p).into_owned()),
+            certificate: session
+                .peer_certificates()
+                .and_then(<[Certificate]>::first)
+                .and_then(|leaf| CertificateInfo::from_der(&leaf.0)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CertificateInfo, TlsProber};
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task::{self, block_on};
+    use futures_rustls::TlsAcceptor;
+    use rustls::{Certificate, PrivateKey, ServerConfig};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    const CERT: &[u8] = include_bytes!("../fixtures/tls/cert.der");
+    const KEY: &[u8] = include_bytes!("../fixtures/tls/key.der");
+
+    #[test]
+    fn parses_certificate_fields() {
+        let cert = CertificateInfo::from_der(CERT).unwrap();
+        assert_eq!(cert.subject, "CN=rustscan.test, O=RustScan");
+        assert_eq!(cert.issuer, cert.subject);
+        assert_eq!(
+            cert.subject_alt_names,
+            vec!["rustscan.test", "www.rustscan.test", "127.0.0.1"]
+        );
+        assert!(cert.not_before < cert.not_after);
+        assert!(!cert.is_expired_at(SystemTime::now()));
+        assert!(CertificateInfo::from_der(b"not a certificate").is_none());
+    }
+
+    #[test]
+    fn handshake_records_session_and_certificate() {
+        block_on(async {
+            let mut config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+                .unwrap();
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            let acceptor = TlsAcceptor::from(Arc::new(config));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let socket = listener.local_addr().unwrap();
+            task::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut tls = acceptor.accept(stream).await.unwrap();
+                let _ = tls.write_all(b"hello").await;
+            });
+
+            let prober = TlsProber::new(Duration::from_secs(2));
+            let results = prober.probe(&[socket]).await;
+            let info = &results[&socket];
+
+            assert_eq!(info.version, "TLSv1_3");
+            assert!(info.cipher.starts_with("TLS13_"));
+            assert_eq!(info.alpn.as_deref(), Some("http/1.1"));
+            let cert = info.certificate.as_ref().unwrap();
+            assert_eq!(cert.subject, "CN=rustscan.test, O=RustScan");
+            assert!(info.to_string().contains("san=[rustscan.test,"));
+        });
+    }
+
+    #[test]
+    fn plain_tcp_port_is_not_tls() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let socket = listener.local_addr().unwrap();
+            task::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let _ = stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await;
+            });
+
+            let prober = TlsProber::new(Duration::from_millis(500));
+            assert!(prober.probe(&[socket]).await.is_empty());
+        });
+    }
+}
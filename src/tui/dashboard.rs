@@ -0,0 +1,391 @@
+//! 交互式的全屏终端界面（`--tui`，需要开启 `tui` feature）。
+//!
+//! 界面在单独的线程里运行，通过 [`Dashboard::events`] 返回的 [`EventSink`] 接收扫描事件，
+//! 显示开放端口的表格、进度条和出错的探测数，并通过 [`ScanControl`] 和 [`CancelHandle`]
+//! 控制扫描：
+//!
+//! | 按键            | 作用                                   |
+//! |-----------------|----------------------------------------|
+//! | `p` / 空格      | 暂停或恢复扫描                         |
+//! | `+` / `-`       | 提高或降低每秒的探测数                 |
+//! | `0`             | 取消速度限制                           |
+//! | `j` `k` / 方向键 | 滚动表格                               |
+//! | `q` / `Esc`     | 扫描中停止扫描，扫描结束后退出界面     |
+use crate::event::{Event, EventSink};
+use crate::progress::Progress;
+use crate::scanner::{CancelHandle, ScanControl};
+use ratatui::crossterm::event::{self as term, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 两次重绘之间最多等待按键的时间。
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 没有速度限制时按 `-`，从目前平均速度的这个比例开始限速。
+const RATE_STEP: f64 = 0.8;
+
+/// 界面的状态以及它控制扫描所用的句柄。
+///
+/// 先把 [`Dashboard::events`]、[`Dashboard::cancel_handle`] 以及创建时使用的
+/// [`ScanControl`] 交给 [`ScannerBuilder`](crate::scanner::ScannerBuilder)，
+/// 构建成功之后再调用 [`Dashboard::start`] 接管终端。
+#[derive(Debug)]
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    control: ScanControl,
+    cancel: CancelHandle,
+}
+
+impl Dashboard {
+    pub fn new(control: ScanControl) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::default())),
+            control,
+            cancel: CancelHandle::new(),
+        }
+    }
+
+    /// 交给 [`ScannerBuilder::events`](crate::scanner::ScannerBuilder::events) 的事件接收者。
+    pub fn events(&self) -> Arc<dyn EventSink> {
+        Arc::new(DashboardSink {
+            state: Arc::clone(&self.state),
+        })
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// 切换到备用屏幕并开始显示界面，stdout 不是终端时返回错误。
+    pub fn start(self) -> io::Result<RunningDashboard> {
+        if !io::stdout().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the interactive UI needs a terminal on stdout",
+            ));
+        }
+        let terminal = ratatui::try_init().inspect_err(|_| ratatui::restore())?;
+
+        let state = Arc::clone(&self.state);
+        let mut ui = Ui {
+            state: self.state,
+            control: self.control,
+            cancel: self.cancel,
+            table: TableState::default(),
+            quit: false,
+        };
+        let thread = thread::spawn(move || {
+            let mut terminal = terminal;
+            let result = ui.run(&mut terminal);
+            ratatui::restore();
+            result
+        });
+        Ok(RunningDashboard { state, thread })
+    }
+}
+
+/// 正在显示的界面，扫描结束后调用 [`RunningDashboard::finish`] 等待用户退出并恢复终端。
+#[derive(Debug)]
+pub struct RunningDashboard {
+    state: Arc<Mutex<State>>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl RunningDashboard {
+    /// 标记扫描已经结束，等待用户退出界面。
+    pub fn finish(self) -> io::Result<()> {
+        lock(&self.state).finished = true;
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the interactive UI panicked")))
+    }
+}
+
+/// 把扫描事件记录到界面的状态里。
+struct DashboardSink {
+    state: Arc<Mutex<State>>,
+}
+
+impl EventSink for DashboardSink {
+    fn event(&self, event: Event) {
+        lock(&self.state).apply(event);
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 界面显示的内容。
+#[derive(Debug, Default)]
+struct State {
+    /// 按 IP 和端口排序的开放端口以及它们的 banner
+    open: BTreeMap<SocketAddr, Option<String>>,
+    progress: Option<Progress>,
+    finished: bool,
+}
+
+impl State {
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::OpenPort { socket, banner } => {
+                self.open.insert(socket, banner);
+            }
+            Event::Progress(progress) => {
+                self.finished |= progress.finished;
+                self.progress = Some(progress);
+            }
+            // 地址在扫描开始之前就已经解析完，暂停状态直接从 ScanControl 读取
+            Event::UnresolvedHost(_) | Event::ScanPaused | Event::ScanResumed => {}
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        self.progress.map_or(0.0, |p| p.rate())
+    }
+}
+
+/// 按 `+` 或 `-` 之后的速度限制，`None` 表示不限制。
+fn next_rate(current: Option<u32>, measured: f64, faster: bool) -> Option<u32> {
+    match (current, faster) {
+        (None, true) => None,
+        (None, false) => Some(((measured * RATE_STEP) as u32).max(1)),
+        (Some(rate), true) => Some(((f64::from(rate) / RATE_STEP) as u32).max(rate + 1)),
+        (Some(rate), false) => Some(((f64::from(rate) * RATE_STEP) as u32).max(1)),
+    }
+}
+
+struct Ui {
+    state: Arc<Mutex<State>>,
+    control: ScanControl,
+    cancel: CancelHandle,
+    table: TableState,
+    /// 用户要求退出，扫描结束后立即关闭界面
+    quit: bool,
+}
+
+impl Ui {
+    fn run(&mut self, terminal: &mut ratatui::DefaultTerminal) -> io::Result<()> {
+        let shared = Arc::clone(&self.state);
+        loop {
+            let finished = {
+                let state = lock(&shared);
+                terminal.draw(|frame| self.draw(frame, &state))?;
+                state.finished
+            };
+            if finished && self.quit {
+                return Ok(());
+            }
+
+            if term::poll(REDRAW_INTERVAL)? {
+                if let term::Event::Key(key) = term::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.key(key);
+                    }
+                }
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            _ if ctrl_c => self.quit(),
+            KeyCode::Char('q') | KeyCode::Esc => self.quit(),
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                self.control.toggle_pause();
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => self.adjust_rate(true),
+            KeyCode::Char('-') => self.adjust_rate(false),
+            KeyCode::Char('0') => self.control.set_rate(None),
+            KeyCode::Char('j') | KeyCode::Down => self.table.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => self.table.select_previous(),
+            KeyCode::Home => self.table.select_first(),
+            KeyCode::End => self.table.select_last(),
+            _ => {}
+        }
+    }
+
+    fn quit(&mut self) {
+        self.quit = true;
+        self.cancel.cancel();
+    }
+
+    fn adjust_rate(&self, faster: bool) {
+        let measured = lock(&self.state).rate();
+        self.control
+            .set_rate(next_rate(self.control.rate(), measured, faster));
+    }
+
+    fn draw(&mut self, frame: &mut Frame<'_>, state: &State) {
+        let [gauge_area, table_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (ratio, label) = match &state.progress {
+            Some(progress) => (progress.percent() / 100.0, progress.to_line()),
+            None => (0.0, "starting...".to_owned()),
+        };
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(" Progress "))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(label);
+        frame.render_widget(gauge, gauge_area);
+
+        let rows = state.open.iter().map(|(socket, banner)| {
+            Row::new(vec![
+                socket.ip().to_string(),
+                socket.port().to_string(),
+                banner.clone().unwrap_or_default(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(39),
+                Constraint::Length(6),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec!["Host", "Port", "Banner"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title(format!(" Open ports ({}) ", state.open.len())))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        frame.render_widget(Paragraph::new(self.status(state)), status_area);
+    }
+
+    fn status(&self, state: &State) -> Line<'static> {
+        let errors = state.progress.map_or(0, |p| p.errors);
+        let errors_style = if errors > 0 {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        };
+        let (scan, keys) = if state.finished {
+            ("finished", "q quit")
+        } else if self.control.is_paused() {
+            (
+                "paused",
+                "p resume  +/- rate  0 unlimited  j/k scroll  q stop",
+            )
+        } else {
+            (
+                "running",
+                "p pause  +/- rate  0 unlimited  j/k scroll  q stop",
+            )
+        };
+        let rate = self
+            .control
+            .rate()
+            .map_or_else(|| "unlimited".to_owned(), |rate| format!("{rate}/s"));
+
+        Line::from(vec![
+            Span::styled(
+                format!(" {scan} "),
+                Style::default().add_modifier(Modifier::REVERSED),
+            ),
+            Span::styled(format!("  errors: {errors}"), errors_style),
+            Span::raw(format!("  rate limit: {rate}  |  {keys}")),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_rate, State, Ui};
+    use crate::event::Event;
+    use crate::progress::Progress;
+    use crate::scanner::{CancelHandle, ScanControl};
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+    use ratatui::widgets::TableState;
+    use ratatui::Terminal;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn ui() -> Ui {
+        Ui {
+            state: Arc::new(Mutex::new(State::default())),
+            control: ScanControl::new(),
+            cancel: CancelHandle::new(),
+            table: TableState::default(),
+            quit: false,
+        }
+    }
+
+    #[test]
+    fn rate_steps() {
+        assert_eq!(next_rate(None, 1_000.0, true), None);
+        assert_eq!(next_rate(None, 1_000.0, false), Some(800));
+        assert_eq!(next_rate(None, 0.0, false), Some(1));
+        assert_eq!(next_rate(Some(800), 0.0, true), Some(1_000));
+        assert_eq!(next_rate(Some(1), 0.0, true), Some(2));
+        assert_eq!(next_rate(Some(1), 0.0, false), Some(1));
+    }
+
+    #[test]
+    fn keys_control_the_scan() {
+        let mut ui = ui();
+        ui.key(KeyEvent::from(KeyCode::Char('p')));
+        assert!(ui.control.is_paused());
+        ui.key(KeyEvent::from(KeyCode::Char('-')));
+        assert_eq!(ui.control.rate(), Some(1));
+        ui.key(KeyEvent::from(KeyCode::Char('0')));
+        assert_eq!(ui.control.rate(), None);
+        ui.key(KeyEvent::from(KeyCode::Char('q')));
+        assert!(ui.quit);
+        assert!(ui.cancel.is_cancelled());
+    }
+
+    #[test]
+    fn draws_open_ports_and_errors() {
+        let mut ui = ui();
+        let mut state = State::default();
+        state.apply(Event::OpenPort {
+            socket: "127.0.0.1:22".parse().unwrap(),
+            banner: Some("SSH-2.0-OpenSSH_9.6".to_owned()),
+        });
+        state.apply(Event::Progress(Progress {
+            done: 500,
+            total: 1_000,
+            open: 1,
+            errors: 3,
+            elapsed: Duration::from_secs(1),
+            finished: false,
+        }));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 10)).unwrap();
+        terminal.draw(|frame| ui.draw(frame, &state)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(screen.contains("Open ports (1)"));
+        assert!(screen.contains("SSH-2.0-OpenSSH_9.6"));
+        assert!(screen.contains("500/1000 (50.0%)"));
+        assert!(screen.contains("errors: 3"));
+        assert!(screen.contains("running"));
+    }
+}
@@ -1,5 +1,8 @@
 //! 扫描期间终端输出的实用工具。
 
+#[cfg(feature = "tui")]
+pub mod dashboard;
+
 /// RustScan 的终端用户界面模块
 /// 定义要使用的宏
 #[macro_export]